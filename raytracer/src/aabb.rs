@@ -0,0 +1,124 @@
+use crate::{Point3, Ray, Vec3};
+
+// Axis-aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+// The x, y or z component for 0, 1 or 2
+pub fn axis(v: Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Self {
+        Self { min, max }
+    }
+
+    // contains nothing, and is the identity of `union`
+    pub fn empty() -> Self {
+        Self {
+            min: Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.min + self.max) * 0.5
+    }
+
+    // 0, 1 or 2 for x, y or z
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x >= d.y && d.x >= d.z {
+            0
+        } else if d.y >= d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn min_on(&self, i: usize) -> f64 {
+        axis(self.min, i)
+    }
+
+    pub fn max_on(&self, i: usize) -> f64 {
+        axis(self.max, i)
+    }
+
+    // Slab test, with `inv_direction` the componentwise inverse of the ray direction
+    pub fn hit(&self, ray: &Ray, inv_direction: Vec3, mut t_min: f64, mut t_max: f64) -> bool {
+        for i in 0..3 {
+            let origin = axis(ray.origin, i);
+            let inv = axis(inv_direction, i);
+            let mut t0 = (self.min_on(i) - origin) * inv;
+            let mut t1 = (self.max_on(i) - origin) * inv;
+            if inv < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN from 0 * inf keeps the current bounds
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hit() {
+        let aabb = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let hit = |origin: Point3, direction: Vec3, t_max: f64| {
+            let inv = Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+            aabb.hit(&Ray::new(origin, direction), inv, 0.0, t_max)
+        };
+        let origin = Point3::new(0.0, 0.0, 5.0);
+        assert!(hit(origin, Vec3::new(0.0, 0.0, -1.0), f64::INFINITY));
+        assert!(!hit(origin, Vec3::new(0.0, 0.0, -1.0), 3.0));
+        assert!(!hit(origin, Vec3::new(0.0, 0.0, 1.0), f64::INFINITY));
+        assert!(!hit(origin, Vec3::new(0.0, 1.0, -1.0), f64::INFINITY));
+        // a ray in the plane of a face still counts
+        assert!(hit(
+            Point3::new(-5.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            f64::INFINITY
+        ));
+        assert_eq!(
+            aabb.union(&Aabb::empty()),
+            aabb,
+            "the empty box changes nothing"
+        );
+    }
+}
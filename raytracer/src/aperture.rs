@@ -0,0 +1,144 @@
+// The shape of the lens opening, which is also the shape of out-of-focus highlights.
+
+use crate::distribution::Distribution2D;
+use crate::warp::{sample_concentric_disk, sample_uniform_triangle};
+use serde::Deserialize;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+// How the scene file or the command line describes the shape
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type")]
+pub enum ApertureShape {
+    Circle,
+    // a regular polygon of straight diaphragm blades, `rotation` in degrees
+    Polygon {
+        blades: u32,
+        #[serde(default)]
+        rotation: f64,
+    },
+    // a grayscale image covering the square around the lens, brighter is more open,
+    // relative to the scene file when it comes from one
+    Image {
+        path: String,
+    },
+}
+
+impl ApertureShape {
+    pub fn build(&self) -> Result<Aperture, String> {
+        match self {
+            ApertureShape::Circle => Ok(Aperture::Circle),
+            ApertureShape::Polygon { blades, .. } if *blades < 3 => Err(format!(
+                "an aperture needs at least 3 blades, not {}",
+                blades
+            )),
+            ApertureShape::Polygon { blades, rotation } => Ok(Aperture::Polygon {
+                blades: *blades,
+                rotation: rotation.to_radians(),
+            }),
+            ApertureShape::Image { path } => {
+                let image = image::open(path)
+                    .map_err(|e| format!("cannot read aperture mask {}: {}", path, e))?
+                    .to_luma8();
+                let (width, height) = image.dimensions();
+                let values: Vec<f64> = image.pixels().map(|p| p.0[0] as f64 / 255.0).collect();
+                if values.iter().all(|&v| v == 0.0) {
+                    return Err(format!("aperture mask {} is completely black", path));
+                }
+                Ok(Aperture::Mask(Distribution2D::new(
+                    &values,
+                    width as usize,
+                    height as usize,
+                )))
+            }
+        }
+    }
+}
+
+pub enum Aperture {
+    Circle,
+    Polygon { blades: u32, rotation: f64 },
+    Mask(Distribution2D),
+}
+
+impl Aperture {
+    // A point of the opening, within the unit disk for the circle and polygon
+    // and within [-1, 1]² for a mask
+    pub fn sample(&self, u: (f64, f64)) -> (f64, f64) {
+        match self {
+            Aperture::Circle => {
+                let p = sample_concentric_disk(u);
+                (p.x, p.y)
+            }
+            Aperture::Polygon { blades, rotation } => {
+                // pick a blade's triangle with the first sample, then reuse what is left of it
+                let n = *blades as f64;
+                let i = ((u.0 * n) as u32).min(blades - 1);
+                let u0 = u.0 * n - i as f64;
+                let corner = |k: u32| {
+                    let angle = FRAC_PI_2 + rotation + 2.0 * PI * k as f64 / n;
+                    (angle.cos(), angle.sin())
+                };
+                let ((x1, y1), (x2, y2)) = (corner(i), corner(i + 1));
+                let (_, b1, b2) = sample_uniform_triangle((u0, u.1));
+                (b1 * x1 + b2 * x2, b1 * y1 + b2 * y2)
+            }
+            Aperture::Mask(distribution) => {
+                // image rows go down, the lens y axis up
+                let ((x, y), _) = distribution.sample_continuous(u);
+                (x * 2.0 - 1.0, 1.0 - y * 2.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    fn samples(aperture: &Aperture) -> Vec<(f64, f64)> {
+        let mut rng = SmallRng::seed_from_u64(1);
+        (0..10_000)
+            .map(|_| aperture.sample((rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0))))
+            .collect()
+    }
+
+    #[test]
+    fn test_polygon() {
+        let aperture = ApertureShape::Polygon {
+            blades: 6,
+            rotation: 30.0,
+        }
+        .build()
+        .unwrap();
+        // a hexagon with corners at 0° and 180°, and flat top and bottom sides
+        let apothem = (PI / 6.0).cos();
+        let points = samples(&aperture);
+        assert!(points.iter().all(|&(x, y)| {
+            let r = (x * x + y * y).sqrt();
+            let angle = y.atan2(x).rem_euclid(PI / 3.0) - PI / 6.0;
+            r * angle.cos() <= apothem + 1e-12
+        }));
+        assert!(points.iter().any(|&(x, _)| x > 0.95));
+        assert!(points.iter().all(|&(_, y)| y.abs() <= apothem + 1e-12));
+        // uniform: the upper half gets half of the points
+        let upper = points.iter().filter(|&&(_, y)| y > 0.0).count();
+        assert!((upper as f64 / points.len() as f64 - 0.5).abs() < 0.02);
+        assert!(ApertureShape::Polygon {
+            blades: 2,
+            rotation: 0.0
+        }
+        .build()
+        .is_err());
+    }
+
+    #[test]
+    fn test_mask() {
+        // only the top right quarter of the square is open
+        let values = [0.0, 1.0, 0.0, 0.0];
+        let aperture = Aperture::Mask(Distribution2D::new(&values, 2, 2));
+        assert!(samples(&aperture)
+            .iter()
+            .all(|&(x, y)| (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y)));
+    }
+}
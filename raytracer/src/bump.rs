@@ -0,0 +1,224 @@
+use crate::medium::Medium;
+use crate::sampled_spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::sampler::Sampler;
+use crate::{texture::Texture, Color, HitRecord, Material, Point3, Ray, ScatterRecord, Vec3};
+use std::sync::Arc;
+
+// how far along the surface the slope of a height texture is measured
+const HEIGHT_STEP: f64 = 1e-4;
+
+// the least cosine between the tilted normal and the direction the ray came from
+const MIN_COS: f64 = 1e-3;
+
+// Detail that tilts the shading normal without moving the surface
+pub enum Bump {
+    // In tangent space, x along u, y along v and z out of the surface, each mapped
+    // from [-1, 1] to [0, 1] like the bluish pictures usually are
+    NormalMap(Arc<dyn Texture>),
+    // above the surface, the mean of the channels times `scale`
+    Height {
+        height: Arc<dyn Texture>,
+        scale: f64,
+    },
+}
+
+// `base` shaded with the normals of `bump`, like the grain of leather or the joints
+// between bricks. The light it scatters is up to `base`.
+pub struct Bumped {
+    pub base: Arc<dyn Material>,
+    pub bump: Bump,
+}
+
+impl Bumped {
+    pub fn new(base: Arc<dyn Material>, bump: Bump) -> Self {
+        Self { base, bump }
+    }
+
+    // The outward normal of `bump` at the hit
+    fn outward_normal(&self, hit_record: &HitRecord) -> Vec3 {
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.p);
+        let (dpdu, dpdv) = (hit_record.dpdu, hit_record.dpdv);
+        let n = if hit_record.front {
+            hit_record.normal
+        } else {
+            -hit_record.normal
+        };
+        let bumped = match &self.bump {
+            Bump::NormalMap(map) => {
+                let local = map.value(u, v, p) * 2.0 - 1.0;
+                let t = dpdu - n * (dpdu * n);
+                if t.squared_length() == 0.0 {
+                    return n;
+                }
+                let t = t.unit();
+                // the bitangent follows v, whichever way the texture is mirrored
+                let b = Vec3::cross(n, t);
+                let b = if b * dpdv < 0.0 { -b } else { b };
+                t * local.x + b * local.y + n * local.z
+            }
+            Bump::Height { height, scale } => {
+                let height = |u: f64, v: f64, p: Point3| {
+                    let c = height.value(u, v, p);
+                    (c.x + c.y + c.z) / 3.0 * scale
+                };
+                let h = height(u, v, p);
+                let du = HEIGHT_STEP / dpdu.length().max(1e-12);
+                let dv = HEIGHT_STEP / dpdv.length().max(1e-12);
+                let dhdu = (height(u + du, v, p + dpdu * du) - h) / du;
+                let dhdv = (height(u, v + dv, p + dpdv * dv) - h) / dv;
+                let bumped = Vec3::cross(dpdu + n * dhdu, dpdv + n * dhdv);
+                if bumped * n < 0.0 {
+                    -bumped
+                } else {
+                    bumped
+                }
+            }
+        };
+        if bumped.squared_length() > 0.0 {
+            bumped.unit()
+        } else {
+            n
+        }
+    }
+
+    // The hit with its normal tilted, turned towards unit `wo` as the normals of hits are.
+    // Where `wo` would be below it, it leans over to just above.
+    fn bumped(&self, hit_record: &HitRecord, wo: Vec3) -> HitRecord {
+        let normal = self.outward_normal(hit_record);
+        let mut normal = if hit_record.front { normal } else { -normal };
+        let cos = normal * wo;
+        if cos < MIN_COS {
+            let across = normal - wo * cos;
+            if across.squared_length() > 0.0 {
+                normal = across.unit() * (1.0 - MIN_COS * MIN_COS).sqrt() + wo * MIN_COS;
+            } else {
+                normal = hit_record.normal;
+            }
+        }
+        HitRecord {
+            normal,
+            ..hit_record.clone()
+        }
+    }
+}
+
+impl Material for Bumped {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let bumped = self.bumped(&hit_record, -ray_in.direction.unit());
+        self.base.scatter(ray_in, bumped, sampler)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: Point3) -> Color {
+        self.base.emitted(u, v, p)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        u: f64,
+        v: f64,
+        p: Point3,
+        lambda: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        self.base.emitted_spectrum(u, v, p, lambda)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        self.base.medium()
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        self.base.eval(&self.bumped(hit_record, wo), wo, wi)
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.base.pdf(&self.bumped(hit_record, wo), wo, wi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constant_texture::ConstantTexture;
+    use crate::lambertian::Lambertian;
+
+    // a hit on the xy plane from above or below, mirrored along u
+    fn hit(front: bool, u: f64) -> HitRecord {
+        HitRecord {
+            p: Point3::new(u, 0.0, 0.0),
+            normal: Vec3::new(0.0, 0.0, if front { 1.0 } else { -1.0 }),
+            t: 1.0,
+            u,
+            v: 0.0,
+            dpdu: Vec3::new(-1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 2.0, 0.0),
+            front,
+            wavelength: None,
+            material: Arc::new(Lambertian::new(Color::ones())),
+        }
+    }
+
+    // a ramp rising by `slope` per unit of u
+    struct Ramp {
+        slope: f64,
+    }
+
+    impl Texture for Ramp {
+        fn value(&self, u: f64, _v: f64, _p: Point3) -> Color {
+            Color::ones() * self.slope * u
+        }
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let base: Arc<dyn Material> = Arc::new(Lambertian::new(Color::ones()));
+        let map = |c: Color| {
+            Bumped::new(
+                base.clone(),
+                Bump::NormalMap(Arc::new(ConstantTexture::new(c))),
+            )
+        };
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let flat = map(Color::new(0.5, 0.5, 1.0));
+        assert_eq!(flat.bumped(&hit(true, 0.3), up).normal, up);
+        // leaning along u, which goes along -x here
+        let tilted = map(Color::new(1.0, 0.5, 1.0));
+        let expected = Vec3::new(-1.0, 0.0, 1.0).unit();
+        assert!((tilted.bumped(&hit(true, 0.3), up).normal - expected).length() < 1e-12);
+        // seen from below it still faces the ray
+        assert!((tilted.bumped(&hit(false, 0.3), -up).normal + expected).length() < 1e-12);
+        // and never away from where the ray came from
+        let grazing = Vec3::new(1.0, 0.0, 0.1).unit();
+        let normal = tilted.bumped(&hit(true, 0.3), grazing).normal;
+        assert!((normal * grazing - MIN_COS).abs() < 1e-12);
+        assert!((normal.length() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_height() {
+        let base: Arc<dyn Material> = Arc::new(Lambertian::new(Color::ones()));
+        let bumped = Bumped::new(
+            base,
+            Bump::Height {
+                height: Arc::new(Ramp { slope: 0.5 }),
+                scale: 2.0,
+            },
+        );
+        // rising one unit per unit of u, a unit along -x, it faces +x as much as up
+        let normal = bumped.outward_normal(&hit(true, 0.3));
+        assert!((normal - Vec3::new(1.0, 0.0, 1.0).unit()).length() < 1e-6);
+        assert!((bumped.outward_normal(&hit(false, 0.3)) - normal).length() < 1e-12);
+    }
+}
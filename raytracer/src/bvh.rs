@@ -0,0 +1,265 @@
+// Bounding volume hierarchy over the objects of a scene, built once and then
+// shared by every render of it. The tree refers to the objects by their index,
+// so the scene keeps owning them.
+
+use crate::aabb::{axis, Aabb};
+use crate::{HitRecord, Hittable, Ray, Vec3};
+
+// objects below which a node is not split any further
+const MAX_LEAF_SIZE: usize = 4;
+// candidate split planes per axis for the surface area heuristic
+const BUCKETS: usize = 12;
+
+// Nodes are stored depth first, so the first child of an interior node is the next node
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        second_child: usize,
+        axis: usize,
+    },
+}
+
+pub struct Bvh {
+    nodes: Vec<Node>,
+    // object indices, each leaf owning a range of them
+    indices: Vec<usize>,
+}
+
+struct Item {
+    index: usize,
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+impl Bvh {
+    pub fn new(hittables: &[Box<dyn Hittable>]) -> Self {
+        let mut items: Vec<Item> = hittables
+            .iter()
+            .enumerate()
+            .map(|(index, hittable)| {
+                let bounds = hittable.bounding_box();
+                Item {
+                    index,
+                    bounds,
+                    centroid: bounds.centroid(),
+                }
+            })
+            .collect();
+        let mut bvh = Self {
+            nodes: vec![],
+            indices: vec![],
+        };
+        if !items.is_empty() {
+            bvh.build(&mut items);
+        }
+        bvh
+    }
+
+    fn build(&mut self, items: &mut [Item]) {
+        let bounds = items
+            .iter()
+            .fold(Aabb::empty(), |bounds, item| bounds.union(&item.bounds));
+        let centroids = items.iter().fold(Aabb::empty(), |bounds, item| {
+            bounds.union(&Aabb::new(item.centroid, item.centroid))
+        });
+        let split_axis = centroids.longest_axis();
+        let (low, high) = (centroids.min_on(split_axis), centroids.max_on(split_axis));
+        let mid = if items.len() <= MAX_LEAF_SIZE || high <= low {
+            None
+        } else {
+            split(items, split_axis, low, high)
+        };
+        match mid {
+            None => {
+                self.nodes.push(Node::Leaf {
+                    bounds,
+                    start: self.indices.len(),
+                    count: items.len(),
+                });
+                self.indices.extend(items.iter().map(|item| item.index));
+            }
+            Some(mid) => {
+                let node = self.nodes.len();
+                self.nodes.push(Node::Interior {
+                    bounds,
+                    second_child: 0,
+                    axis: split_axis,
+                });
+                let (first, second) = items.split_at_mut(mid);
+                self.build(first);
+                let second_child = self.nodes.len();
+                if let Node::Interior {
+                    second_child: child,
+                    ..
+                } = &mut self.nodes[node]
+                {
+                    *child = second_child;
+                }
+                self.build(second);
+            }
+        }
+    }
+
+    pub fn hit(
+        &self,
+        hittables: &[Box<dyn Hittable>],
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let d = ray.direction;
+        let inv_direction = Vec3::new(1.0 / d.x, 1.0 / d.y, 1.0 / d.z);
+        let mut t_closest = t_max;
+        let mut hit_record = None;
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            match self.nodes[node] {
+                Node::Leaf {
+                    bounds,
+                    start,
+                    count,
+                } => {
+                    if !bounds.hit(ray, inv_direction, t_min, t_closest) {
+                        continue;
+                    }
+                    for &index in &self.indices[start..start + count] {
+                        if let Some(hr) = hittables[index].hit(ray, t_min, t_closest) {
+                            t_closest = hr.t;
+                            hit_record = Some(hr);
+                        }
+                    }
+                }
+                Node::Interior {
+                    bounds,
+                    second_child,
+                    axis: split_axis,
+                } => {
+                    if !bounds.hit(ray, inv_direction, t_min, t_closest) {
+                        continue;
+                    }
+                    // visit the child on the side the ray comes from first
+                    if axis(inv_direction, split_axis) < 0.0 {
+                        stack.push(node + 1);
+                        stack.push(second_child);
+                    } else {
+                        stack.push(second_child);
+                        stack.push(node + 1);
+                    }
+                }
+            }
+        }
+        hit_record
+    }
+}
+
+// Partitions the items along `split_axis` where the surface area heuristic
+// says it is cheapest, returning the number of items in the first part or
+// `None` when keeping them in one leaf is better
+fn split(items: &mut [Item], split_axis: usize, low: f64, high: f64) -> Option<usize> {
+    let bucket = |item: &Item| {
+        let b = ((axis(item.centroid, split_axis) - low) / (high - low) * BUCKETS as f64) as usize;
+        b.min(BUCKETS - 1)
+    };
+    let mut buckets = [(0, Aabb::empty()); BUCKETS];
+    for item in items.iter() {
+        let b = &mut buckets[bucket(item)];
+        b.0 += 1;
+        b.1 = b.1.union(&item.bounds);
+    }
+    let area = |bounds: &Aabb| {
+        let d = bounds.max - bounds.min;
+        if d.x < 0.0 {
+            0.0
+        } else {
+            2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+        }
+    };
+    // cost of splitting after each bucket, relative to the area of the parent
+    let (best, cost) = (0..BUCKETS - 1)
+        .map(|i| {
+            let (below, above) = buckets.split_at(i + 1);
+            let part = |buckets: &[(usize, Aabb)]| {
+                buckets.iter().fold((0, Aabb::empty()), |(n, bounds), b| {
+                    (n + b.0, bounds.union(&b.1))
+                })
+            };
+            let ((n0, b0), (n1, b1)) = (part(below), part(above));
+            (i, n0 as f64 * area(&b0) + n1 as f64 * area(&b1))
+        })
+        .fold((0, f64::INFINITY), |best, candidate| {
+            if candidate.1 < best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+    let parent = items
+        .iter()
+        .fold(Aabb::empty(), |bounds, item| bounds.union(&item.bounds));
+    // one traversal step costs about as much as an eighth of an intersection
+    if 0.125 * area(&parent) + cost >= items.len() as f64 * area(&parent) {
+        return None;
+    }
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if bucket(&items[i]) <= best {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    if mid == 0 || mid == items.len() {
+        None
+    } else {
+        Some(mid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::example_scene;
+    use crate::{HittableList, Point3};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_same_hits_as_list() {
+        let world = example_scene(1).world;
+        let bvh = Bvh::new(&world.hittables);
+        let mut rng = SmallRng::seed_from_u64(1);
+        for _ in 0..2000 {
+            let origin = Point3::new(
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(0.1..5.0),
+                rng.gen_range(-15.0..15.0),
+            );
+            let direction = Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let ray = Ray::new(origin, direction);
+            let expected = world.hit(&ray, 1e-5, f64::INFINITY).map(|hr| hr.t);
+            let found = bvh
+                .hit(&world.hittables, &ray, 1e-5, f64::INFINITY)
+                .map(|hr| hr.t);
+            assert_eq!(found, expected);
+        }
+        let empty = HittableList { hittables: vec![] };
+        assert!(Bvh::new(&empty.hittables)
+            .hit(
+                &empty.hittables,
+                &Ray::new(Point3::zero(), Vec3::ones()),
+                0.0,
+                1.0
+            )
+            .is_none());
+    }
+}
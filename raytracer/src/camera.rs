@@ -1,51 +1,235 @@
-use crate::{utils, Point3, Ray, Vec3};
-use rand::{rngs::SmallRng, Rng};
+use crate::aperture::ApertureShape;
+use crate::camera_animation::CameraAnimation;
+use crate::equirectangular_camera::EquirectangularCamera;
+use crate::fisheye_camera::FisheyeCamera;
+use crate::orthographic_camera::OrthographicCamera;
+use crate::perspective_camera::PerspectiveCamera;
+use crate::realistic_camera::{load_lens, RealisticCamera};
+use crate::sampler::Sampler;
+use crate::stereo_camera::{Stereo, StereoCamera};
+use crate::{Point3, Ray, Vec3};
+use serde::Deserialize;
 
-pub struct Camera {
+pub trait Camera: Send + Sync {
+    // The ray through (s, t) of the image, both in [0, 1] with t growing upwards.
+    // `None` where the camera sees nothing, like outside the circle of a fisheye.
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray>;
+
+    // About how far apart the rays through two neighboring pixels of an image `height`
+    // pixels high pass by `p`, going by the rays through the center of the lens around
+    // the middle of the image. `None` when `p` is not in front of the camera or lies
+    // outside the cone (or cylinder) around the view through the image corners.
+    fn pixel_footprint(&self, p: Point3, height: u32) -> Option<f64> {
+        let dt = 1.0 / height.max(1) as f64;
+        let a = self.get_ray(0.5, 0.5, &mut CenterSampler)?;
+        let b = self.get_ray(0.5, 0.5 + dt, &mut CenterSampler)?;
+        let d = a.direction.unit();
+        let depth = (p - a.origin) * d;
+        if depth <= 0.0 {
+            return None;
+        }
+        // how far from the view axis the corner rays are at the depth of `p`
+        let axis = a.origin + d * depth;
+        let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)];
+        let radius = corners
+            .iter()
+            .filter_map(|&(s, t)| self.get_ray(s, t, &mut CenterSampler))
+            .filter(|ray| ray.direction * d > 0.0)
+            .map(|ray| {
+                let t = (depth - (ray.origin - a.origin) * d) / (ray.direction * d);
+                (ray.at(t) - axis).length()
+            })
+            .fold(None, |max: Option<f64>, r| {
+                Some(max.map_or(r, |max| max.max(r)))
+            });
+        if matches!(radius, Some(radius) if (p - axis).length() > radius * (1.0 + 1e-9)) {
+            return None;
+        }
+        // the points of the rays closest to `p`
+        let closest = |ray: &Ray| {
+            let d = ray.direction.unit();
+            ray.origin + d * ((p - ray.origin) * d)
+        };
+        Some((closest(&a) - closest(&b)).length())
+    }
+}
+
+// Every dimension in the middle, for rays through the center of the lens
+struct CenterSampler;
+
+impl Sampler for CenterSampler {
+    fn start_pixel_sample(&mut self, _x: u32, _y: u32, _index: u32) {}
+
+    fn get_1d(&mut self) -> f64 {
+        0.5
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (0.5, 0.5)
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(CenterSampler)
+    }
+}
+
+// How a fisheye lens maps the angle from the view direction onto the image
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum FisheyeMapping {
+    // distance from the center proportional to the angle
+    Equidistant,
+    // equal solid angles cover equal areas of the image
+    Equisolid,
+}
+
+fn equidistant() -> FisheyeMapping {
+    FisheyeMapping::Equidistant
+}
+
+fn full_frame() -> f64 {
+    43.27
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type")]
+pub enum Projection {
+    // thin lens with `vfov`, `aperture` and `focus_dist`
+    Perspective,
+    // parallel rays, `height` is the size of the view in scene units
+    Orthographic {
+        height: f64,
+    },
+    // `fov` in degrees across the image circle, which touches the shorter image side
+    Fisheye {
+        fov: f64,
+        #[serde(default = "equidistant")]
+        mapping: FisheyeMapping,
+    },
+    // 360° by 180° panorama around `lookfrom`, best rendered at an aspect ratio of 2
+    Equirectangular,
+    // the lens prescription in the file `lens` (relative to the scene file), focused at
+    // `focus_dist` from the film, with `aperture_diameter` (default: wide open) and
+    // `film_diagonal` in millimeters
+    Realistic {
+        lens: String,
+        aperture_diameter: Option<f64>,
+        #[serde(default = "full_frame")]
+        film_diagonal: f64,
+    },
+}
+
+impl Projection {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Projection::Realistic {
+                aperture_diameter: Some(diameter),
+                ..
+            } if diameter <= 0.0 => Err(format!(
+                "the lens aperture diameter must be positive, not {}",
+                diameter
+            )),
+            Projection::Realistic { film_diagonal, .. } if film_diagonal <= 0.0 => Err(format!(
+                "the film diagonal must be positive, not {}",
+                film_diagonal
+            )),
+            Projection::Orthographic { height } if height <= 0.0 => Err(format!(
+                "the orthographic view height must be positive, not {}",
+                height
+            )),
+            Projection::Fisheye { fov, .. } if !(fov > 0.0 && fov <= 360.0) => Err(format!(
+                "the fisheye field of view must be in (0, 360], not {}",
+                fov
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// A camera frame looking from `lookfrom` towards `lookat`,
+// with `w` pointing backwards like in the book
+pub struct Frame {
     pub origin: Point3,
-    pub horizontal: Vec3,
-    pub vertical: Vec3,
-    pub lower_left_corner: Point3,
-    pub lens_radis: f64,
     pub u: Vec3,
     pub v: Vec3,
+    pub w: Vec3,
 }
 
-impl Camera {
-    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3, vfov: f64, aspect_ratio: f64, aperture: f64, focus_dist: f64) -> Self {
-        let theta = vfov * std::f64::consts::PI / 180_f64; // degree to radian
-        let h = (theta / 2_f64).tan();
-        let viewport_height = 2_f64 * h;
-        let viewport_width = aspect_ratio * viewport_height;
-
+impl Frame {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3) -> Self {
         let w = (lookfrom - lookat).unit();
         let u = Vec3::cross(vup, w).unit();
         let v = Vec3::cross(w, u);
-
-        let origin = lookfrom;
-        let horizontal = u * viewport_width * focus_dist;
-        let vertical = v * viewport_height * focus_dist;
-        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w * focus_dist;
-        let lens_radis = aperture / 2_f64;
-
         Self {
-            origin,
-            horizontal,
-            vertical,
-            lower_left_corner,
+            origin: lookfrom,
             u,
             v,
-            lens_radis,
+            w,
         }
     }
+}
 
-    pub fn get_ray(&self, s: f64, t: f64, rng: &mut SmallRng) -> Ray {
-        let rd = utils::random_in_unit_sphere(rng) * self.lens_radis;
-        let offset = self.u* rd.x + self.v * rd.y;
+// Everything needed to build a `Camera` except the aspect ratio,
+// which is only known once the output resolution is decided.
+#[derive(Clone, Debug)]
+pub struct CameraSettings {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub vfov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    pub projection: Projection,
+    // for the perspective camera
+    pub aperture_shape: ApertureShape,
+    pub cat_eye: f64,
+    // a pair of views instead of one
+    pub stereo: Option<Stereo>,
+    // keyframes for rendering a sequence of frames
+    pub animation: Option<CameraAnimation>,
+}
+
+impl CameraSettings {
+    // `aspect_ratio` of the whole image, with both views of a stereo pair
+    pub fn build(&self, aspect_ratio: f64) -> Result<Box<dyn Camera>, String> {
+        if let Some(stereo) = &self.stereo {
+            return Ok(Box::new(StereoCamera::new(self, stereo, aspect_ratio)?));
+        }
+        let frame = Frame::new(self.lookfrom, self.lookat, self.vup);
+        Ok(match self.projection {
+            Projection::Perspective => Box::new(self.perspective(aspect_ratio)?),
+            Projection::Orthographic { height } => {
+                Box::new(OrthographicCamera::new(frame, height, aspect_ratio))
+            }
+            Projection::Fisheye { fov, mapping } => {
+                Box::new(FisheyeCamera::new(frame, fov, mapping, aspect_ratio))
+            }
+            Projection::Equirectangular => Box::new(EquirectangularCamera::new(frame)),
+            Projection::Realistic {
+                ref lens,
+                aperture_diameter,
+                film_diagonal,
+            } => Box::new(RealisticCamera::new(
+                frame,
+                load_lens(lens)?,
+                aperture_diameter,
+                self.focus_dist,
+                film_diagonal,
+                aspect_ratio,
+            )?),
+        })
+    }
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+    pub fn perspective(&self, aspect_ratio: f64) -> Result<PerspectiveCamera, String> {
+        Ok(PerspectiveCamera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
         )
+        .with_aperture(self.aperture_shape.build()?)
+        .with_cat_eye(self.cat_eye))
     }
 }
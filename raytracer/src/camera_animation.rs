@@ -0,0 +1,118 @@
+use crate::camera::CameraSettings;
+use crate::keyframe::{catmull_rom, interpolate, Interpolation};
+use crate::Point3;
+use serde::Deserialize;
+use std::ops::{Add, Mul, Sub};
+
+// The parameters keyed at one frame, a parameter can be keyed at any subset of the frames
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct CameraKeyframe {
+    pub frame: f64,
+    pub look_from: Option<Point3>,
+    pub look_at: Option<Point3>,
+    pub vfov: Option<f64>,
+    pub focus_dist: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct CameraAnimation {
+    #[serde(default = "catmull_rom")]
+    pub interpolation: Interpolation,
+    // in increasing frame order
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraAnimation {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keyframes.is_empty() {
+            return Err(String::from("the camera animation has no keyframes"));
+        }
+        if self
+            .keyframes
+            .windows(2)
+            .any(|pair| pair[0].frame >= pair[1].frame)
+        {
+            return Err(String::from(
+                "camera keyframes must be in increasing frame order",
+            ));
+        }
+        if self
+            .keyframes
+            .iter()
+            .any(|key| matches!(key.vfov, Some(vfov) if vfov <= 0.0 || vfov >= 180.0))
+        {
+            return Err(String::from("keyframed vfov must be in (0, 180)"));
+        }
+        if self
+            .keyframes
+            .iter()
+            .any(|key| matches!(key.focus_dist, Some(focus_dist) if focus_dist <= 0.0))
+        {
+            return Err(String::from("keyframed focus_dist must be positive"));
+        }
+        Ok(())
+    }
+
+    // `settings` with the keyed parameters replaced by their values at `frame`
+    pub fn apply(&self, settings: &CameraSettings, frame: f64) -> CameraSettings {
+        CameraSettings {
+            lookfrom: self
+                .track(frame, |key| key.look_from)
+                .unwrap_or(settings.lookfrom),
+            lookat: self
+                .track(frame, |key| key.look_at)
+                .unwrap_or(settings.lookat),
+            vfov: self.track(frame, |key| key.vfov).unwrap_or(settings.vfov),
+            focus_dist: self
+                .track(frame, |key| key.focus_dist)
+                .unwrap_or(settings.focus_dist),
+            ..settings.clone()
+        }
+    }
+
+    // One parameter at `frame`, from the keyframes that key it
+    fn track<T>(&self, frame: f64, value: impl Fn(&CameraKeyframe) -> Option<T>) -> Option<T>
+    where
+        T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+    {
+        let keys: Vec<(f64, T)> = self
+            .keyframes
+            .iter()
+            .filter_map(|key| value(key).map(|v| (key.frame, v)))
+            .collect();
+        interpolate(&keys, frame, self.interpolation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::example_scene;
+
+    #[test]
+    fn test_apply() {
+        let animation: CameraAnimation = serde_yaml::from_str(
+            "{interpolation: Linear, keyframes: [
+                {frame: 1, look_from: [0, 0, 10], vfov: 40},
+                {frame: 5, look_from: [10, 0, 0]},
+                {frame: 11, vfov: 20}]}",
+        )
+        .unwrap();
+        animation.validate().unwrap();
+        let settings = example_scene(0).camera;
+        let at = |frame| animation.apply(&settings, frame);
+        assert_eq!(at(3.0).lookfrom, Point3::new(5.0, 0.0, 5.0));
+        assert_eq!(at(8.0).lookfrom, Point3::new(10.0, 0.0, 0.0));
+        // vfov has its own keys, from 40 at frame 1 to 20 at frame 11
+        assert_eq!(at(6.0).vfov, 30.0);
+        // what is not keyed is left alone
+        assert_eq!(at(6.0).lookat, settings.lookat);
+        assert_eq!(at(6.0).focus_dist, settings.focus_dist);
+
+        let unordered: CameraAnimation =
+            serde_yaml::from_str("{keyframes: [{frame: 2, vfov: 30}, {frame: 1, vfov: 40}]}")
+                .unwrap();
+        assert_eq!(unordered.interpolation, Interpolation::CatmullRom);
+        assert!(unordered.validate().is_err());
+    }
+}
@@ -0,0 +1,295 @@
+// Saving and restoring a render in progress.
+//
+// A checkpoint holds the float accumulation buffer, the sample counts and
+// the position in the pass schedule. No generator state needs to be stored:
+// the random stream of every sample is derived from the seed, the pixel and
+// the sample index, so continuing from a checkpoint draws exactly the same
+// samples as an uninterrupted run.
+
+use crate::film::{Film, PixelStats};
+use crate::render::RenderState;
+use crate::Color;
+use std::convert::TryInto;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 3;
+// sample count, mean and second moment of a pixel
+const PIXEL_BYTES: usize = 4 + 6 * 8;
+
+// FNV-1a, stable across runs and platforms unlike `DefaultHasher`
+pub fn fingerprint(description: &str) -> u64 {
+    description
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+pub struct Checkpoint {
+    pub seed: u64,
+    // fingerprint of everything that must not change between the runs
+    pub fingerprint: u64,
+    // the frame being rendered when rendering an animation
+    pub frame: Option<u32>,
+    pub state: RenderState,
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_color(buf: &mut Vec<u8>, color: Color) {
+    for value in &[color.x, color.y, color.z] {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn put_flags(buf: &mut Vec<u8>, flags: &[bool]) {
+    put_u32(buf, flags.len() as u32);
+    buf.extend(flags.iter().map(|&flag| flag as u8));
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < n {
+            return Err(String::from("checkpoint is truncated"));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn color(&mut self) -> Result<Color, String> {
+        Ok(Color::new(self.f64()?, self.f64()?, self.f64()?))
+    }
+
+    fn flags(&mut self) -> Result<Vec<bool>, String> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.iter().map(|&byte| byte != 0).collect())
+    }
+}
+
+impl Checkpoint {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let state = &self.state;
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        put_u32(&mut buf, VERSION);
+        put_u64(&mut buf, self.seed);
+        put_u64(&mut buf, self.fingerprint);
+        match self.frame {
+            Some(frame) => {
+                buf.push(1);
+                put_u32(&mut buf, frame);
+            }
+            None => buf.push(0),
+        }
+        put_u32(&mut buf, state.pass);
+        put_u32(&mut buf, state.spp);
+        put_u32(&mut buf, state.target);
+        put_flags(&mut buf, &state.done);
+        match &state.active {
+            Some(active) => {
+                buf.push(1);
+                put_flags(&mut buf, active);
+            }
+            None => buf.push(0),
+        }
+        put_u32(&mut buf, state.film.width);
+        put_u32(&mut buf, state.film.height);
+        for pixel in state.film.pixels() {
+            put_u32(&mut buf, pixel.count);
+            put_color(&mut buf, pixel.mean);
+            put_color(&mut buf, pixel.m2);
+        }
+        buf
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { data };
+        if reader.take(4)? != MAGIC {
+            return Err(String::from("not a checkpoint file"));
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("unsupported checkpoint version {}", version));
+        }
+        let seed = reader.u64()?;
+        let fingerprint = reader.u64()?;
+        let frame = match reader.take(1)?[0] {
+            0 => None,
+            _ => Some(reader.u32()?),
+        };
+        let pass = reader.u32()?;
+        let spp = reader.u32()?;
+        let target = reader.u32()?;
+        let done = reader.flags()?;
+        let active = match reader.take(1)?[0] {
+            0 => None,
+            _ => Some(Arc::new(reader.flags()?)),
+        };
+        let (width, height) = (reader.u32()?, reader.u32()?);
+        // check the size before allocating, a corrupt header must not ask for gigabytes
+        let n_pixels = width
+            .checked_mul(height)
+            .ok_or_else(|| format!("invalid film size {}x{}", width, height))?
+            as usize;
+        if n_pixels.checked_mul(PIXEL_BYTES) != Some(reader.data.len()) {
+            return Err(format!(
+                "expected {} pixels for a {}x{} film, found {} bytes",
+                n_pixels,
+                width,
+                height,
+                reader.data.len()
+            ));
+        }
+        if let Some(active) = &active {
+            if active.len() != n_pixels {
+                return Err(format!(
+                    "expected {} active flags for a {}x{} film, found {}",
+                    n_pixels,
+                    width,
+                    height,
+                    active.len()
+                ));
+            }
+        }
+        let mut pixels = Vec::with_capacity(n_pixels);
+        for _ in 0..n_pixels {
+            pixels.push(PixelStats {
+                count: reader.u32()?,
+                mean: reader.color()?,
+                m2: reader.color()?,
+            });
+        }
+        Ok(Self {
+            seed,
+            fingerprint,
+            frame,
+            state: RenderState {
+                film: Film::from_pixels(width, height, pixels),
+                pass,
+                spp,
+                target,
+                done,
+                active,
+            },
+        })
+    }
+
+    // write to a temporary file first, so a crash while saving keeps the old checkpoint
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, self.to_bytes())?;
+        std::fs::rename(&tmp, path)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+        Checkpoint::from_bytes(&data).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut film = Film::new(3, 2);
+        let mut stats = PixelStats::default();
+        stats.add_sample(Color::new(0.1, 0.2, 0.3));
+        stats.add_sample(Color::new(0.3, 0.2, 0.1));
+        film.merge_tile(
+            &crate::render::Tile {
+                index: 0,
+                x: 1,
+                y: 1,
+                width: 1,
+                height: 1,
+            },
+            &[stats],
+        );
+        let checkpoint = Checkpoint {
+            seed: 42,
+            fingerprint: fingerprint("settings"),
+            frame: Some(12),
+            state: RenderState {
+                film,
+                pass: 3,
+                spp: 4,
+                target: 8,
+                done: vec![true, false, true],
+                active: Some(Arc::new(vec![false, true, true, false, false, true])),
+            },
+        };
+        let restored = Checkpoint::from_bytes(&checkpoint.to_bytes()).unwrap();
+        assert_eq!(restored.seed, 42);
+        assert_eq!(restored.fingerprint, checkpoint.fingerprint);
+        assert_eq!(restored.frame, Some(12));
+        assert_eq!(restored.state.pass, 3);
+        assert_eq!((restored.state.spp, restored.state.target), (4, 8));
+        assert_eq!(restored.state.done, checkpoint.state.done);
+        assert_eq!(restored.state.active, checkpoint.state.active);
+        assert_eq!(restored.state.film.pixels(), checkpoint.state.film.pixels());
+    }
+
+    #[test]
+    fn test_reject_garbage() {
+        assert!(Checkpoint::from_bytes(b"PNG\x00").is_err());
+        assert!(Checkpoint::from_bytes(b"RTCK\x02\x00").is_err());
+        assert_ne!(fingerprint("a"), fingerprint("b"));
+    }
+
+    #[test]
+    fn test_reject_bad_size() {
+        let checkpoint = Checkpoint {
+            seed: 1,
+            fingerprint: 2,
+            frame: None,
+            state: RenderState::new(2, 2),
+        };
+        let bytes = checkpoint.to_bytes();
+        assert!(Checkpoint::from_bytes(&bytes).is_ok());
+        assert!(Checkpoint::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(Checkpoint::from_bytes(&extra).is_err());
+        // one active flag per pixel
+        let mut active = Checkpoint {
+            seed: 1,
+            fingerprint: 2,
+            frame: None,
+            state: RenderState::new(2, 2),
+        };
+        active.state.active = Some(Arc::new(vec![true; 3]));
+        assert!(Checkpoint::from_bytes(&active.to_bytes()).is_err());
+        // a film of 2^32 pixels, width * height overflows
+        let header = bytes.len() - 4 * PIXEL_BYTES - 8;
+        let mut huge = bytes[..header].to_vec();
+        put_u32(&mut huge, 1 << 16);
+        put_u32(&mut huge, 1 << 16);
+        assert!(Checkpoint::from_bytes(&huge).is_err());
+    }
+}
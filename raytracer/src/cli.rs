@@ -0,0 +1,536 @@
+use crate::integrator::Integrator;
+use crate::sampler::SamplerKind;
+use crate::stereo_camera::{StereoLayout, StereoMode};
+use crate::{Point3, Vec3};
+use image::ImageFormat;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: raytracer [OPTIONS]
+
+Scene:
+    --scene <FILE>           load a .json/.yaml scene instead of the built-in example scene
+    --seed <N>               seed for scene generation and sampling (default 0)
+
+Image:
+    --width <N>              image width in pixels (default 1200)
+    --height <N>             image height in pixels (default: derived from the scene aspect ratio)
+    --output <FILE>          where to write the image (default output/test.png)
+    --frames <FIRST[-LAST]>  render these frames of the scene's animation, to an
+                             --output with a run of # for the frame number
+                             (default output/frame_####.png)
+    --format <FMT>           png, jpg, bmp, tga, tiff or pnm (default: from the output extension)
+
+Render:
+    --spp <N>                samples per pixel, the upper limit in progressive mode (default 500)
+    --depth <N>              maximum ray depth (default 50)
+    --integrator <NAME>      path, normal, mis for path tracing that also samples the
+                             lights, or spectral for mis over wavelengths rather than
+                             RGB (default path)
+    --sampler <NAME>         independent, stratified, halton, sobol or bluenoise (default sobol)
+    --threads <N>            number of worker threads (default: all cores)
+    --tile-size <N>          edge length of the square tiles (default 32)
+    --tile-stats <FILE>      write per-tile render times as CSV, with a run of # for
+                             the frame number when rendering --frames
+
+Progressive rendering:
+    --progressive            render passes of 1, 2, 4, ... spp, writing the image after each pass
+    --time-limit <SECONDS>   stop after this wall-clock time (implies --progressive)
+    --noise-threshold <F>    stop once no pixel has a relative variance above F (implies --progressive)
+    --adaptive               only keep sampling pixels above the noise threshold
+    --min-spp <N>            samples every pixel gets before adaptive sampling starts (default 8)
+    --heatmap <FILE>         write the number of samples per pixel as an image, with a
+                             run of # for the frame number when rendering --frames
+
+Checkpoints:
+    --checkpoint <FILE>      periodically save the render state to FILE
+    --checkpoint-interval <SECONDS>
+                             time between two checkpoints (default 300)
+    --resume <FILE>          continue the render saved in FILE, and keep checkpointing to it;
+                             an animation continues at the frame it was saved in
+
+Camera overrides:
+    --lookfrom <X,Y,Z>       camera position
+    --lookat <X,Y,Z>         point the camera looks at
+    --vup <X,Y,Z>            up direction
+    --vfov <DEGREES>         vertical field of view
+    --aperture <F>           lens aperture, 0 for a pinhole
+    --focus-dist <F>         distance to the plane in focus
+    --blades <N>             polygonal aperture with N diaphragm blades
+    --blade-rotation <DEGREES>
+                             rotation of the polygonal aperture (default 0)
+    --aperture-mask <FILE>   grayscale image of the aperture, brighter is more open
+    --cat-eye <F>            clip the aperture towards the frame corners, 0 for none; the
+                             lens barrel is offset by F aperture radii in the corners, so
+                             1 leaves about 39% of the aperture open there
+
+Stereo:
+    --stereo <LAYOUT>        render both eyes, side-by-side or top-bottom; each view has the
+                             scene's aspect ratio, equirectangular scenes become ODS panoramas
+    --stereo-mode <MODE>     off-axis or toe-in (default off-axis)
+    --interocular <F>        distance between the eyes (default 0.065)
+    --convergence <F>        distance of the plane at screen depth (default: the focus distance)
+
+    -h, --help               print this message
+";
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub help: bool,
+    pub scene: Option<String>,
+    pub seed: Option<u64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub output: String,
+    pub frames: Option<(u32, u32)>,
+    pub format: ImageFormat,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub integrator: Integrator,
+    pub sampler: SamplerKind,
+    pub threads: Option<usize>,
+    pub tile_size: u32,
+    pub tile_stats: Option<String>,
+    pub progressive: bool,
+    pub time_limit: Option<f64>,
+    pub noise_threshold: Option<f64>,
+    pub adaptive: bool,
+    pub min_spp: u32,
+    pub heatmap: Option<String>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: f64,
+    pub resume: Option<String>,
+    pub lookfrom: Option<Point3>,
+    pub lookat: Option<Point3>,
+    pub vup: Option<Vec3>,
+    pub vfov: Option<f64>,
+    pub aperture: Option<f64>,
+    pub focus_dist: Option<f64>,
+    pub blades: Option<u32>,
+    pub blade_rotation: Option<f64>,
+    pub aperture_mask: Option<String>,
+    pub cat_eye: Option<f64>,
+    pub stereo: Option<StereoLayout>,
+    pub stereo_mode: Option<StereoMode>,
+    pub interocular: Option<f64>,
+    pub convergence: Option<f64>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options {
+            help: false,
+            scene: None,
+            seed: None,
+            width: None,
+            height: None,
+            output: String::from("output/test.png"),
+            frames: None,
+            format: ImageFormat::Png,
+            samples_per_pixel: 500,
+            max_depth: 50,
+            integrator: Integrator::Path,
+            sampler: SamplerKind::Sobol,
+            threads: None,
+            tile_size: 32,
+            tile_stats: None,
+            progressive: false,
+            time_limit: None,
+            noise_threshold: None,
+            adaptive: false,
+            min_spp: 8,
+            heatmap: None,
+            checkpoint: None,
+            checkpoint_interval: 300.0,
+            resume: None,
+            lookfrom: None,
+            lookat: None,
+            vup: None,
+            vfov: None,
+            aperture: None,
+            focus_dist: None,
+            blades: None,
+            blade_rotation: None,
+            aperture_mask: None,
+            cat_eye: None,
+            stereo: None,
+            stereo_mode: None,
+            interocular: None,
+            convergence: None,
+        };
+        let mut format = None;
+        let mut output_given = false;
+        let mut depth_given = false;
+        let mut interval_given = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-h" || arg == "--help" {
+                options.help = true;
+                continue;
+            }
+            // accept both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.find('=') {
+                Some(idx) if arg.starts_with("--") => {
+                    (&arg[..idx], Some(arg[idx + 1..].to_string()))
+                }
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || match inline_value.clone() {
+                Some(value) => Ok(value),
+                None => args
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("missing value for {}", flag)),
+            };
+            match flag {
+                "--scene" => options.scene = Some(value()?),
+                "--seed" => options.seed = Some(parse_number(flag, &value()?)?),
+                "--width" => options.width = Some(parse_positive(flag, &value()?)?),
+                "--height" => options.height = Some(parse_positive(flag, &value()?)?),
+                "--output" | "-o" => {
+                    options.output = value()?;
+                    output_given = true;
+                }
+                "--frames" => options.frames = Some(parse_frames(flag, &value()?)?),
+                "--format" => format = Some(value()?),
+                "--spp" => options.samples_per_pixel = parse_positive(flag, &value()?)?,
+                "--depth" => {
+                    options.max_depth = parse_positive(flag, &value()?)?;
+                    depth_given = true;
+                }
+                "--integrator" => options.integrator = value()?.parse()?,
+                "--sampler" => options.sampler = value()?.parse()?,
+                "--threads" => options.threads = Some(parse_positive(flag, &value()?)?),
+                "--tile-size" => options.tile_size = parse_positive(flag, &value()?)?,
+                "--tile-stats" => options.tile_stats = Some(value()?),
+                "--progressive" => options.progressive = true,
+                "--time-limit" => options.time_limit = Some(parse_positive(flag, &value()?)?),
+                "--noise-threshold" => {
+                    options.noise_threshold = Some(parse_positive(flag, &value()?)?)
+                }
+                "--adaptive" => options.adaptive = true,
+                "--min-spp" => options.min_spp = parse_positive(flag, &value()?)?,
+                "--heatmap" => options.heatmap = Some(value()?),
+                "--checkpoint" => options.checkpoint = Some(value()?),
+                "--checkpoint-interval" => {
+                    options.checkpoint_interval = parse_positive(flag, &value()?)?;
+                    interval_given = true;
+                }
+                "--resume" => options.resume = Some(value()?),
+                "--lookfrom" => options.lookfrom = Some(parse_vec3(flag, &value()?)?),
+                "--lookat" => options.lookat = Some(parse_vec3(flag, &value()?)?),
+                "--vup" => options.vup = Some(parse_vec3(flag, &value()?)?),
+                "--vfov" => options.vfov = Some(parse_number(flag, &value()?)?),
+                "--aperture" => options.aperture = Some(parse_number(flag, &value()?)?),
+                "--focus-dist" => options.focus_dist = Some(parse_number(flag, &value()?)?),
+                "--blades" => options.blades = Some(parse_positive(flag, &value()?)?),
+                "--blade-rotation" => options.blade_rotation = Some(parse_number(flag, &value()?)?),
+                "--aperture-mask" => options.aperture_mask = Some(value()?),
+                "--cat-eye" => options.cat_eye = Some(parse_number(flag, &value()?)?),
+                "--stereo" => options.stereo = Some(value()?.parse()?),
+                "--stereo-mode" => options.stereo_mode = Some(value()?.parse()?),
+                "--interocular" => options.interocular = Some(parse_number(flag, &value()?)?),
+                "--convergence" => options.convergence = Some(parse_positive(flag, &value()?)?),
+                _ => return Err(format!("unknown option `{}`", arg)),
+            }
+        }
+        if options.help {
+            return Ok(options);
+        }
+
+        options.progressive |= options.time_limit.is_some() || options.noise_threshold.is_some();
+        if options.adaptive && options.noise_threshold.is_none() {
+            return Err(String::from("--adaptive needs a --noise-threshold"));
+        }
+        if interval_given && options.checkpoint.is_none() && options.resume.is_none() {
+            return Err(String::from(
+                "--checkpoint-interval needs --checkpoint or --resume",
+            ));
+        }
+        if options.adaptive && options.min_spp > options.samples_per_pixel {
+            return Err(String::from("--min-spp must not be larger than --spp"));
+        }
+        if options.frames.is_some() {
+            if !output_given {
+                options.output = String::from("output/frame_####.png");
+            } else if !options.output.contains('#') {
+                return Err(String::from(
+                    "--frames needs an --output with a run of # for the frame number",
+                ));
+            }
+            // every frame writes its own sample heat map and tile times
+            for (flag, path) in &[
+                ("--heatmap", &options.heatmap),
+                ("--tile-stats", &options.tile_stats),
+            ] {
+                if matches!(path, Some(path) if !path.contains('#')) {
+                    return Err(format!(
+                        "--frames needs a {} with a run of # for the frame number",
+                        flag
+                    ));
+                }
+            }
+        }
+        options.format = output_format(&options.output, format.as_deref())?;
+        if depth_given && options.integrator == Integrator::Normal {
+            return Err(String::from(
+                "--depth has no effect with --integrator normal",
+            ));
+        }
+        if let Some(vfov) = options.vfov {
+            if vfov <= 0.0 || vfov >= 180.0 {
+                return Err(format!("--vfov must be in (0, 180), got {}", vfov));
+            }
+        }
+        if let Some(aperture) = options.aperture {
+            if aperture < 0.0 {
+                return Err(String::from("--aperture must not be negative"));
+            }
+        }
+        if let Some(focus_dist) = options.focus_dist {
+            if focus_dist <= 0.0 {
+                return Err(String::from("--focus-dist must be positive"));
+            }
+        }
+        if let Some(blades) = options.blades {
+            if blades < 3 {
+                return Err(format!("--blades must be at least 3, got {}", blades));
+            }
+        }
+        if options.blade_rotation.is_some() && options.blades.is_none() {
+            return Err(String::from("--blade-rotation needs --blades"));
+        }
+        if options.blades.is_some() && options.aperture_mask.is_some() {
+            return Err(String::from(
+                "--blades and --aperture-mask cannot be used together",
+            ));
+        }
+        if let Some(cat_eye) = options.cat_eye {
+            if cat_eye < 0.0 {
+                return Err(String::from("--cat-eye must not be negative"));
+            }
+        }
+        if let Some(interocular) = options.interocular {
+            if interocular < 0.0 {
+                return Err(String::from("--interocular must not be negative"));
+            }
+        }
+        if options.stereo.is_none()
+            && (options.stereo_mode.is_some()
+                || options.interocular.is_some()
+                || options.convergence.is_some())
+        {
+            return Err(String::from(
+                "--stereo-mode, --interocular and --convergence need --stereo",
+            ));
+        }
+        if let (Some(lookfrom), Some(lookat)) = (options.lookfrom, options.lookat) {
+            if lookfrom == lookat {
+                return Err(String::from(
+                    "--lookfrom and --lookat must be different points",
+                ));
+            }
+        }
+        Ok(options)
+    }
+
+    // `output` with the first run of # replaced by the zero-padded frame number
+    pub fn frame_output(&self, frame: u32) -> String {
+        frame_path(&self.output, frame)
+    }
+
+    // width and height of the output, filling in the missing one from the scene's aspect ratio
+    pub fn resolution(&self, aspect_ratio: f64) -> (u32, u32) {
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, ((width as f64 / aspect_ratio) as u32).max(1)),
+            (None, Some(height)) => (((height as f64 * aspect_ratio) as u32).max(1), height),
+            (None, None) => (1200, ((1200.0 / aspect_ratio) as u32).max(1)),
+        }
+    }
+}
+
+fn output_format(output: &str, format: Option<&str>) -> Result<ImageFormat, String> {
+    let from_extension = Path::new(output)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(ImageFormat::from_extension);
+    match format {
+        Some(name) => {
+            let format = ImageFormat::from_extension(name)
+                .ok_or_else(|| format!("unsupported image format `{}`", name))?;
+            match from_extension {
+                Some(ext_format) if ext_format != format => Err(format!(
+                    "--format {} does not match the extension of {}",
+                    name, output
+                )),
+                _ => Ok(format),
+            }
+        }
+        None => from_extension
+            .ok_or_else(|| format!("cannot guess the image format of {}, use --format", output)),
+    }
+}
+
+fn parse_number<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{}` for {}", value, flag))
+}
+
+fn parse_positive<T: FromStr + Default + PartialOrd>(flag: &str, value: &str) -> Result<T, String> {
+    let number: T = parse_number(flag, value)?;
+    if number > T::default() {
+        Ok(number)
+    } else {
+        Err(format!("{} must be positive", flag))
+    }
+}
+
+// `path` with the first run of # replaced by the zero-padded frame number
+pub fn frame_path(path: &str, frame: u32) -> String {
+    match path.find('#') {
+        Some(start) => {
+            let digits = path[start..].chars().take_while(|&c| c == '#').count();
+            format!(
+                "{}{:0width$}{}",
+                &path[..start],
+                frame,
+                &path[start + digits..],
+                width = digits
+            )
+        }
+        None => path.to_string(),
+    }
+}
+
+// a single frame `N` or a range `FIRST-LAST`
+fn parse_frames(flag: &str, value: &str) -> Result<(u32, u32), String> {
+    let (first, last) = match value.find('-') {
+        Some(idx) => (
+            parse_number(flag, &value[..idx])?,
+            parse_number(flag, &value[idx + 1..])?,
+        ),
+        None => {
+            let frame = parse_number(flag, value)?;
+            (frame, frame)
+        }
+    };
+    if first > last {
+        return Err(format!("{} {} ends before it starts", flag, value));
+    }
+    Ok((first, last))
+}
+
+fn parse_vec3(flag: &str, value: &str) -> Result<Vec3, String> {
+    let parts = value
+        .split(',')
+        .map(|part| parse_number(flag, part.trim()))
+        .collect::<Result<Vec<f64>, String>>()?;
+    match parts.as_slice() {
+        [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+        _ => Err(format!(
+            "{} expects three comma separated numbers, got `{}`",
+            flag, value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(options.samples_per_pixel, 500);
+        assert_eq!(options.max_depth, 50);
+        assert_eq!(options.output, "output/test.png");
+        assert_eq!(options.resolution(1.5), (1200, 800));
+    }
+
+    #[test]
+    fn test_values() {
+        let options = parse(&[
+            "--width=640",
+            "--spp",
+            "16",
+            "--lookfrom",
+            "1,2,3",
+            "--output",
+            "out.jpg",
+            "--integrator",
+            "normal",
+            "--sampler",
+            "halton",
+        ])
+        .unwrap();
+        assert_eq!(options.resolution(2.0), (640, 320));
+        assert_eq!(options.samples_per_pixel, 16);
+        assert_eq!(options.lookfrom, Some(Vec3::new(1.0, 2.0, 3.0)));
+        assert_eq!(options.format, ImageFormat::Jpeg);
+        assert_eq!(options.integrator, Integrator::Normal);
+        assert_eq!(options.sampler, SamplerKind::Halton);
+    }
+
+    #[test]
+    fn test_frames() {
+        let options = parse(&["--frames", "1-48"]).unwrap();
+        assert_eq!(options.frames, Some((1, 48)));
+        assert_eq!(options.frame_output(7), "output/frame_0007.png");
+        let options = parse(&["--frames", "5", "-o", "turntable/#.jpg"]).unwrap();
+        assert_eq!(options.frames, Some((5, 5)));
+        assert_eq!(options.frame_output(123), "turntable/123.jpg");
+        assert!(parse(&["--frames", "9-2"]).is_err());
+        assert!(parse(&["--frames", "1-2", "-o", "out.png"]).is_err());
+        assert!(parse(&["--frames", "1-2", "--checkpoint", "a.ckpt"]).is_ok());
+        let options = parse(&["--frames", "1-2", "--heatmap", "spp_##.png"]).unwrap();
+        assert_eq!(
+            frame_path(options.heatmap.as_ref().unwrap(), 2),
+            "spp_02.png"
+        );
+        assert!(parse(&["--frames", "1-2", "--heatmap", "spp.png"]).is_err());
+        assert!(parse(&["--frames", "1-2", "--tile-stats", "tiles.csv"]).is_err());
+    }
+
+    #[test]
+    fn test_stop_conditions_imply_progressive() {
+        assert!(!parse(&[]).unwrap().progressive);
+        let options = parse(&["--time-limit", "1.5"]).unwrap();
+        assert!(options.progressive);
+        assert_eq!(options.time_limit, Some(1.5));
+        assert!(parse(&["--noise-threshold", "0.01"]).unwrap().progressive);
+        assert!(parse(&["--time-limit", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse(&["--spp", "0"]).is_err());
+        assert!(parse(&["--width"]).is_err());
+        assert!(parse(&["--lookat", "1,2"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+        assert!(parse(&["--output", "a.png", "--format", "jpg"]).is_err());
+        assert!(parse(&["--integrator", "normal", "--depth", "3"]).is_err());
+        assert!(parse(&["--vfov", "180"]).is_err());
+        assert!(parse(&["--blades", "2"]).is_err());
+        assert!(parse(&["--blade-rotation", "30"]).is_err());
+        assert!(parse(&["--blades", "6", "--aperture-mask", "heart.png"]).is_err());
+        assert!(parse(&["--cat-eye", "-1"]).is_err());
+        assert!(parse(&["--stereo", "left-right"]).is_err());
+        assert!(parse(&["--interocular", "0.1"]).is_err());
+        assert!(parse(&["--stereo", "top-bottom", "--interocular", "-1"]).is_err());
+        assert!(parse(&["--stereo", "side-by-side", "--convergence", "0"]).is_err());
+        assert!(parse(&["--adaptive"]).is_err());
+        assert!(parse(&["--checkpoint-interval", "60"]).is_err());
+        let adaptive = ["--adaptive", "--noise-threshold", "0.01"];
+        assert!(parse(&[&adaptive[..], &["--spp", "4", "--min-spp", "8"]].concat()).is_err());
+        assert!(parse(&["--spp", "4"]).is_ok());
+    }
+}
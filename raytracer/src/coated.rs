@@ -0,0 +1,473 @@
+use crate::dielectric::Dielectric;
+use crate::fresnel::{fresnel_dielectric, refract};
+use crate::integrator::power_heuristic;
+use crate::onb::Onb;
+use crate::sampler::{IndependentSampler, Sampler};
+use crate::utils::hash_u64;
+use crate::{Color, HitRecord, Material, Ray, ScatterRecord, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// the bounces off the base a walk takes at most
+const MAX_DEPTH: u32 = 10;
+
+// A dielectric coat over any other material, like the varnish on wood or the clear
+// coat of car paint. Light refracts into the coat, loses some of itself to the coat's
+// absorption on the way down, scatters off `base` and bounces between the two until it
+// gets out again. The bounces are a random walk that does not care where in the layer
+// they happen (Guo et al. 2018, "Position-Free Monte Carlo Simulation for Arbitrary
+// Layered BSDFs"), so `eval` and `pdf` are estimates too.
+pub struct Coated {
+    pub base: Arc<dyn Material>,
+    // its index of refraction, roughness and absorption, a dispersion is ignored
+    pub coat: Dielectric,
+    // scales the absorption, 1 loses exp(-absorption) going straight through
+    pub thickness: f64,
+}
+
+// A direction the coat's surface scatters to, the BSDF times the cosine over the
+// density in `weight`. For a smooth surface `pdf` is the chance of the choice made.
+struct CoatSample {
+    wi: Vec3,
+    weight: f64,
+    pdf: f64,
+    specular: bool,
+}
+
+// what the base scattered to, with `pdf` `None` for a specular bounce
+struct BaseSample {
+    wi: Vec3,
+    weight: Color,
+    pdf: Option<f64>,
+    wavelength: Option<f64>,
+}
+
+fn flip(w: Vec3) -> Vec3 {
+    Vec3::new(w.x, w.y, -w.z)
+}
+
+// Russian roulette for a walk that has lost most of its light, false to end it
+fn roulette(beta: &mut Color, depth: u32, sampler: &mut dyn Sampler) -> bool {
+    let max = beta.x.max(beta.y).max(beta.z);
+    if depth <= 3 || max >= 0.25 {
+        return true;
+    }
+    if sampler.get_1d() < 1.0 - max {
+        return false;
+    }
+    *beta = *beta / max;
+    true
+}
+
+// the same random numbers for the same pair of directions, so that estimates of
+// `eval` and `pdf` used for the same path agree
+fn direction_seed(wo: Vec3, wi: Vec3) -> u64 {
+    [wo.x, wo.y, wo.z, wi.x, wi.y, wi.z]
+        .iter()
+        .fold(0, |seed, c| hash_u64(seed ^ c.to_bits()))
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, coat: Dielectric) -> Self {
+        Self {
+            base,
+            coat,
+            thickness: 1.0,
+        }
+    }
+
+    pub fn with_thickness(self, thickness: f64) -> Self {
+        Self { thickness, ..self }
+    }
+
+    // what is left crossing the layer along `w`
+    fn transmittance(&self, w: Vec3) -> Color {
+        let a = self.coat.absorption * (-self.thickness / w.z.abs());
+        Color::new(a.x.exp(), a.y.exp(), a.z.exp())
+    }
+
+    fn smooth(&self) -> bool {
+        self.coat.distribution.effectively_smooth()
+    }
+
+    // `Dielectric` looks at its surface from the side of `wo`, which is below it
+    // for light inside the layer
+    fn coat_frame(&self, wo: Vec3) -> (bool, f64) {
+        if wo.z > 0.0 {
+            (false, self.coat.ir)
+        } else {
+            (true, 1.0 / self.coat.ir)
+        }
+    }
+
+    // The BSDF of the coat's surface, zero if it is smooth
+    fn coat_eval(&self, wo: Vec3, wi: Vec3) -> f64 {
+        match self.coat_frame(wo) {
+            (true, eta) => self.coat.eval(flip(wo), flip(wi), eta),
+            (false, eta) => self.coat.eval(wo, wi, eta),
+        }
+    }
+
+    fn coat_pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        match self.coat_frame(wo) {
+            (true, eta) => self.coat.pdf(flip(wo), flip(wi), eta),
+            (false, eta) => self.coat.pdf(wo, wi, eta),
+        }
+    }
+
+    fn coat_sample(&self, wo: Vec3, u: (f64, f64), uc: f64) -> Option<CoatSample> {
+        let (flipped, eta) = self.coat_frame(wo);
+        let wo = if flipped { flip(wo) } else { wo };
+        let sample = if self.smooth() {
+            let reflectance = fresnel_dielectric(wo.z, eta);
+            if uc < reflectance {
+                CoatSample {
+                    wi: Vec3::new(-wo.x, -wo.y, wo.z),
+                    weight: 1.0,
+                    pdf: reflectance,
+                    specular: true,
+                }
+            } else {
+                CoatSample {
+                    wi: refract(wo, Vec3::new(0.0, 0.0, 1.0), eta)?,
+                    weight: 1.0,
+                    pdf: 1.0 - reflectance,
+                    specular: true,
+                }
+            }
+        } else {
+            let (wi, f, pdf) = self.coat.sample(wo, eta, u, uc)?;
+            if pdf == 0.0 {
+                return None;
+            }
+            CoatSample {
+                wi,
+                weight: f * wi.z.abs() / pdf,
+                pdf,
+                specular: false,
+            }
+        };
+        if flipped {
+            Some(CoatSample {
+                wi: flip(sample.wi),
+                ..sample
+            })
+        } else {
+            Some(sample)
+        }
+    }
+
+    // The base hit by light travelling along `w` down through the layer
+    fn base_sample(
+        &self,
+        hit_record: &HitRecord,
+        onb: &Onb,
+        w: Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Option<BaseSample> {
+        let ray = Ray {
+            wavelength: hit_record.wavelength,
+            ..Ray::new(hit_record.p, onb.local(w))
+        };
+        let (scattered, weight, pdf) = match self.base.scatter(&ray, hit_record.clone(), sampler)? {
+            ScatterRecord::Specular {
+                specular_ray,
+                attenuation,
+            } => (specular_ray, attenuation, None),
+            ScatterRecord::Diffuse {
+                scattered,
+                attenuation,
+                pdf,
+            } => (scattered, attenuation, Some(pdf)),
+        };
+        Some(BaseSample {
+            wi: onb.world_to_local(scattered.direction.unit()),
+            weight,
+            pdf,
+            wavelength: scattered.wavelength,
+        })
+    }
+
+    // the BSDF of the base, without the cosine of `wi`
+    fn base_eval(&self, hit_record: &HitRecord, onb: &Onb, wo: Vec3, wi: Vec3) -> Color {
+        let f = self.base.eval(hit_record, onb.local(wo), onb.local(wi));
+        f / wi.z.abs()
+    }
+
+    fn base_pdf(&self, hit_record: &HitRecord, onb: &Onb, wo: Vec3, wi: Vec3) -> f64 {
+        self.base.pdf(hit_record, onb.local(wo), onb.local(wi))
+    }
+
+    // One walk from `wo` to `wi`, both above the coat, of the light reflected by the
+    // coat and the light that gets to `wi` after some bounces in the layer. Every
+    // bounce off the base adds the light that crosses the coat towards `wi` next,
+    // from either the base or the coat sampling the direction.
+    fn eval_local(
+        &self,
+        hit_record: &HitRecord,
+        onb: &Onb,
+        wo: Vec3,
+        wi: Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::zero();
+        }
+        let mut f = Color::ones() * self.coat_eval(wo, wi);
+        // into the layer from `wo` and, backwards, from `wi`
+        let wos = match self.coat_sample(wo, sampler.get_2d(), sampler.get_1d()) {
+            Some(sample) if sample.wi.z < 0.0 => sample,
+            _ => return f,
+        };
+        let wis = match self.coat_sample(wi, sampler.get_2d(), sampler.get_1d()) {
+            Some(sample) if sample.wi.z < 0.0 => sample,
+            _ => return f,
+        };
+        let mut beta = Color::ones() * wos.weight;
+        let mut w = wos.wi;
+        for depth in 0..MAX_DEPTH {
+            if !roulette(&mut beta, depth, sampler) {
+                break;
+            }
+            beta = Vec3::elemul(beta, self.transmittance(w));
+            let f_base = self.base_eval(hit_record, onb, -w, -wis.wi);
+            if f_base != Color::zero() {
+                let weight = if self.smooth() {
+                    1.0
+                } else {
+                    let base_pdf = self.base_pdf(hit_record, onb, -w, -wis.wi);
+                    power_heuristic(wis.pdf, base_pdf)
+                };
+                // `wis` crossed the coat the other way round, where its BTDF is
+                // larger by the squared index
+                let weight = weight * wis.weight / (self.coat.ir * self.coat.ir);
+                let through = Vec3::elemul(f_base, self.transmittance(wis.wi));
+                f += Vec3::elemul(beta, through) * weight;
+            }
+            let sample = match self.base_sample(hit_record, onb, w, sampler) {
+                Some(sample) if sample.wi.z > 0.0 => sample,
+                _ => break,
+            };
+            beta = Vec3::elemul(beta, sample.weight);
+            w = sample.wi;
+            if !self.smooth() {
+                let f_exit = self.coat_eval(-w, wi);
+                if f_exit > 0.0 {
+                    let weight = match sample.pdf {
+                        // against the coat sampling `-w` from `wi`, as for `wis`
+                        Some(pdf) => power_heuristic(pdf, self.coat_pdf(wi, -w)),
+                        None => 1.0,
+                    };
+                    f += Vec3::elemul(beta, self.transmittance(w)) * (f_exit * weight);
+                }
+            }
+            // back down from the underside of the coat, the light getting out was
+            // counted above
+            beta = Vec3::elemul(beta, self.transmittance(w));
+            match self.coat_sample(-w, sampler.get_2d(), sampler.get_1d()) {
+                Some(sample) if sample.wi.z < 0.0 => {
+                    beta *= sample.weight;
+                    w = sample.wi;
+                }
+                _ => break,
+            }
+        }
+        f
+    }
+
+    // An estimate of the density of `scatter` choosing `wi`, from the coat reflecting
+    // and the base reflecting light that crossed the coat twice. It is mixed with a
+    // uniform density, as the estimate can miss where the walks actually go.
+    fn pdf_local(
+        &self,
+        hit_record: &HitRecord,
+        onb: &Onb,
+        wo: Vec3,
+        wi: Vec3,
+        sampler: &mut dyn Sampler,
+    ) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = self.coat_pdf(wo, wi);
+        let wos = self.coat_sample(wo, sampler.get_2d(), sampler.get_1d());
+        let wis = self.coat_sample(wi, sampler.get_2d(), sampler.get_1d());
+        if let (Some(wos), Some(wis)) = (wos, wis) {
+            if wos.wi.z < 0.0 && wis.wi.z < 0.0 {
+                // the density of the base in the directions inside the coat, which
+                // spread out as they leave it
+                let spread = wi.z / (self.coat.ir * self.coat.ir * wis.wi.z.abs());
+                let base_pdf = self.base_pdf(hit_record, onb, -wos.wi, -wis.wi);
+                if self.smooth() {
+                    pdf += base_pdf * spread;
+                } else if let Some(sample) = self.base_sample(hit_record, onb, wos.wi, sampler) {
+                    if sample.wi.z > 0.0 {
+                        let coat_pdf = self.coat_pdf(-sample.wi, wi);
+                        match sample.pdf {
+                            None => pdf += coat_pdf,
+                            Some(sample_pdf) => {
+                                pdf += power_heuristic(wis.pdf, base_pdf) * base_pdf * spread;
+                                pdf += power_heuristic(sample_pdf, coat_pdf) * coat_pdf;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        0.1 / (4.0 * PI) + 0.9 * pdf
+    }
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let onb = Onb::from_hit(&hit_record);
+        let wo_world = -ray_in.direction.unit();
+        let wo = onb.world_to_local(wo_world);
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let sample = self.coat_sample(wo, sampler.get_2d(), sampler.get_1d())?;
+        let mut beta = Color::ones() * sample.weight;
+        let mut w = sample.wi;
+        let mut specular = sample.specular;
+        let mut wavelength = hit_record.wavelength;
+        let mut depth = 0;
+        while w.z < 0.0 {
+            if depth == MAX_DEPTH || !roulette(&mut beta, depth, sampler) {
+                return None;
+            }
+            depth += 1;
+            beta = Vec3::elemul(beta, self.transmittance(w));
+            let sample = self.base_sample(&hit_record, &onb, w, sampler)?;
+            beta = Vec3::elemul(beta, sample.weight);
+            specular &= sample.pdf.is_none();
+            wavelength = sample.wavelength.or(wavelength);
+            w = sample.wi;
+            if w.z < 0.0 {
+                // through the base, which `eval` and `pdf` know nothing of
+                return Some(ScatterRecord::Specular {
+                    specular_ray: Ray {
+                        wavelength,
+                        ..Ray::new(hit_record.p, onb.local(w))
+                    },
+                    attenuation: beta,
+                });
+            }
+            // up to the underside of the coat, and out or back down
+            beta = Vec3::elemul(beta, self.transmittance(w));
+            let sample = self.coat_sample(-w, sampler.get_2d(), sampler.get_1d())?;
+            beta *= sample.weight;
+            specular &= sample.specular;
+            w = sample.wi;
+        }
+        let scattered = Ray {
+            wavelength,
+            ..Ray::new(hit_record.p, onb.local(w))
+        };
+        if specular {
+            return Some(ScatterRecord::Specular {
+                specular_ray: scattered,
+                attenuation: beta,
+            });
+        }
+        let pdf = self.pdf(&hit_record, wo_world, scattered.direction);
+        Some(ScatterRecord::Diffuse {
+            scattered,
+            attenuation: beta,
+            pdf,
+        })
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.base.is_dispersive()
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let onb = Onb::from_hit(hit_record);
+        let mut sampler = IndependentSampler::new(direction_seed(wo, wi));
+        let wi = onb.world_to_local(wi);
+        let f = self.eval_local(hit_record, &onb, onb.world_to_local(wo), wi, &mut sampler);
+        f * wi.z.abs()
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let onb = Onb::from_hit(hit_record);
+        let mut sampler = IndependentSampler::new(direction_seed(wi, wo));
+        let (wo, wi) = (onb.world_to_local(wo), onb.world_to_local(wi));
+        self.pdf_local(hit_record, &onb, wo, wi, &mut sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::microfacet::TrowbridgeReitz;
+    use crate::warp::{sample_uniform_sphere, uniform_sphere_pdf};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    fn hit(material: Arc<dyn Material>) -> HitRecord {
+        HitRecord {
+            p: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 1.0, 0.0),
+            front: true,
+            wavelength: None,
+            material,
+        }
+    }
+
+    #[test]
+    fn test_sampling_matches_eval() {
+        let base: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.5, 0.2)));
+        let coats = [
+            Dielectric::new(1.5),
+            Dielectric::new(1.5).with_roughness(TrowbridgeReitz::new(0.3, 0.3)),
+            Dielectric::new(1.3)
+                .with_roughness(TrowbridgeReitz::new(0.1, 0.1))
+                .with_absorption(Color::new(0.1, 0.5, 1.0)),
+        ];
+        let mut sampler = IndependentSampler::new(7);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let wo = Vec3::new(0.3, -0.4, 0.7).unit();
+        let ray_in = Ray::new(wo, -wo);
+        for (i, coat) in coats.iter().enumerate() {
+            let coated = Coated::new(base.clone(), Dielectric { ..*coat });
+            let rec = hit(base.clone());
+            let n = 100_000;
+            let (mut sampled, mut specular, mut uniform) = (Color::zero(), 0.0, Color::zero());
+            let mut pdf_integral = 0.0;
+            for _ in 0..n {
+                match coated.scatter(&ray_in, hit(base.clone()), &mut sampler) {
+                    Some(ScatterRecord::Diffuse { attenuation, .. }) => sampled += attenuation,
+                    Some(ScatterRecord::Specular { attenuation, .. }) => specular += attenuation.x,
+                    None => {}
+                }
+                let wi = sample_uniform_sphere((rng.gen(), rng.gen()));
+                uniform += coated.eval(&rec, wo, wi) / uniform_sphere_pdf();
+                pdf_integral += coated.pdf(&rec, wo, wi) / uniform_sphere_pdf();
+            }
+            let (sampled, uniform) = (sampled / n as f64, uniform / n as f64);
+            assert!(
+                (sampled - uniform).length() < 0.02,
+                "coat {}: {:?} {:?}",
+                i,
+                sampled,
+                uniform
+            );
+            // only a smooth coat reflects specularly
+            assert_eq!(specular > 0.0, i == 0);
+            assert!(sampled.x + specular / n as f64 <= 1.0);
+            assert!(pdf_integral / (n as f64) < 1.02);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::fresnel::fresnel_conductor;
+use crate::microfacet::TrowbridgeReitz;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::thin_film::ThinFilm;
+use crate::utils::reflect;
+use crate::{Color, HitRecord, Material, Ray, ScatterRecord, Vec3};
+use serde::Deserialize;
+
+// Measured metals, with eta and k fitted to the red, green and blue primaries
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+}
+
+impl ConductorPreset {
+    // (eta, k)
+    pub fn eta_k(&self) -> (Color, Color) {
+        match self {
+            ConductorPreset::Gold => (
+                Color::new(0.143119, 0.374957, 1.44248),
+                Color::new(3.98316, 2.38572, 1.60322),
+            ),
+            ConductorPreset::Copper => (
+                Color::new(0.200438, 0.924033, 1.10221),
+                Color::new(3.91295, 2.45285, 2.14219),
+            ),
+            ConductorPreset::Aluminium => (
+                Color::new(1.65746, 0.880369, 0.521229),
+                Color::new(9.22387, 6.26952, 4.837),
+            ),
+        }
+    }
+}
+
+// A metal with a rough surface, reflecting by the Fresnel equations of its
+// complex index of refraction `eta + i k`, optionally under an iridescent film
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub distribution: TrowbridgeReitz,
+    pub thin_film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, distribution: TrowbridgeReitz) -> Self {
+        Self {
+            eta,
+            k,
+            distribution,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_thin_film(self, thin_film: ThinFilm) -> Self {
+        Self {
+            thin_film: Some(thin_film),
+            ..self
+        }
+    }
+
+    pub fn preset(preset: ConductorPreset, distribution: TrowbridgeReitz) -> Self {
+        let (eta, k) = preset.eta_k();
+        Self::new(eta, k, distribution)
+    }
+
+    // A metal reflecting `albedo` at normal incidence, by absorption alone with eta = 1
+    pub fn from_reflectance(albedo: Color, roughness: f64) -> Self {
+        let k = |r: f64| {
+            let r = r.clamp(0.0, 0.9999);
+            2.0 * (r / (1.0 - r)).sqrt()
+        };
+        Self::new(
+            Color::ones(),
+            Color::new(k(albedo.x), k(albedo.y), k(albedo.z)),
+            TrowbridgeReitz::new(roughness, roughness),
+        )
+    }
+
+    fn fresnel(&self, cos_theta_i: f64, wavelength: Option<f64>) -> Color {
+        match &self.thin_film {
+            Some(film) => film.reflectance(cos_theta_i, 1.0, self.eta, self.k, wavelength),
+            None => fresnel_conductor(cos_theta_i, self.eta, self.k),
+        }
+    }
+
+    // The BSDF for `wo` and `wi` in the shading frame, both pointing away from the
+    // surface, of the film at `wavelength` if it has one
+    pub fn eval(&self, wo: Vec3, wi: Vec3, wavelength: Option<f64>) -> Color {
+        if wo.z <= 0.0 || wi.z <= 0.0 || self.distribution.effectively_smooth() {
+            return Color::zero();
+        }
+        let wm = wo + wi;
+        if wm.squared_length() == 0.0 {
+            return Color::zero();
+        }
+        let wm = wm.unit();
+        let fresnel = self.fresnel(wo * wm, wavelength);
+        fresnel * (self.distribution.d(wm) * self.distribution.g(wo, wi) / (4.0 * wo.z * wi.z))
+    }
+
+    // The density of `sample` choosing `wi`
+    pub fn pdf(&self, wo: Vec3, wi: Vec3) -> f64 {
+        if wo.z <= 0.0 || wi.z <= 0.0 || self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let wm = wo + wi;
+        if wm.squared_length() == 0.0 {
+            return 0.0;
+        }
+        let wm = wm.unit();
+        self.distribution.pdf(wo, wm) / (4.0 * (wo * wm))
+    }
+
+    // `wi` reflected about a visible microfacet normal, with the BSDF and density there
+    pub fn sample(
+        &self,
+        wo: Vec3,
+        u: (f64, f64),
+        wavelength: Option<f64>,
+    ) -> Option<(Vec3, Color, f64)> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let wm = self.distribution.sample_wm(wo, u);
+        let wi = reflect(-wo, wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some((wi, self.eval(wo, wi, wavelength), self.pdf(wo, wi)))
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let onb = Onb::from_hit(&hit_record);
+        let wo = onb.world_to_local(-ray_in.direction.unit());
+        if self.distribution.effectively_smooth() {
+            return Some(ScatterRecord::Specular {
+                specular_ray: Ray::new(hit_record.p, onb.local(Vec3::new(-wo.x, -wo.y, wo.z))),
+                attenuation: self.fresnel(wo.z, hit_record.wavelength),
+            });
+        }
+        let (wi, f, pdf) = self.sample(wo, sampler.get_2d(), hit_record.wavelength)?;
+        Some(ScatterRecord::Diffuse {
+            scattered: Ray::new(hit_record.p, onb.local(wi)),
+            attenuation: f * (wi.z / pdf),
+            pdf,
+        })
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.thin_film.is_some()
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let onb = Onb::from_hit(hit_record);
+        let wi = onb.world_to_local(wi);
+        self.eval(onb.world_to_local(wo), wi, hit_record.wavelength) * wi.z.abs()
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let onb = Onb::from_hit(hit_record);
+        self.pdf(onb.world_to_local(wo), onb.world_to_local(wi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warp::{sample_uniform_hemisphere, uniform_hemisphere_pdf};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_sampling_matches_eval() {
+        let conductor = Conductor::preset(ConductorPreset::Gold, TrowbridgeReitz::new(0.4, 0.15));
+        let wo = Vec3::new(0.5, 0.2, 0.6).unit();
+        let mut rng = SmallRng::seed_from_u64(1);
+        let n = 200_000;
+        // the albedo estimated by importance sampling and by uniform directions
+        let (mut sampled, mut uniform, mut pdf_integral) = (Color::zero(), Color::zero(), 0.0);
+        for _ in 0..n {
+            if let Some((wi, f, pdf)) = conductor.sample(wo, (rng.gen(), rng.gen()), None) {
+                assert!((conductor.pdf(wo, wi) - pdf).abs() < 1e-9 * pdf.max(1.0));
+                sampled += f * (wi.z / pdf);
+            }
+            let wi = sample_uniform_hemisphere((rng.gen(), rng.gen()));
+            uniform += conductor.eval(wo, wi, None) * (wi.z / uniform_hemisphere_pdf());
+            pdf_integral += conductor.pdf(wo, wi) / uniform_hemisphere_pdf();
+        }
+        let (sampled, uniform) = (sampled / n as f64, uniform / n as f64);
+        assert!((sampled - uniform).length() < 0.02);
+        // energy is lost to absorption and masking, never gained
+        assert!(sampled.x < 1.0 && sampled.y < 1.0 && sampled.z < 1.0);
+        // gold reflects red more than blue
+        assert!(sampled.x > sampled.z);
+        assert!(pdf_integral / (n as f64) < 1.02);
+    }
+
+    #[test]
+    fn test_from_reflectance() {
+        let conductor = Conductor::from_reflectance(Color::new(0.9, 0.5, 0.1), 0.0);
+        let normal = fresnel_conductor(1.0, conductor.eta, conductor.k);
+        assert!((normal - Color::new(0.9, 0.5, 0.1)).length() < 1e-9);
+        assert!(conductor.distribution.effectively_smooth());
+    }
+
+    #[test]
+    fn test_anisotropy_follows_dpdu() {
+        let conductor = Conductor::preset(ConductorPreset::Gold, TrowbridgeReitz::new(0.4, 0.05));
+        let hit = |dpdu| HitRecord {
+            p: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            dpdu,
+            dpdv: Vec3::zero(),
+            front: true,
+            wavelength: None,
+            material: std::sync::Arc::new(Conductor::from_reflectance(Color::ones(), 0.1)),
+        };
+        let rotate = |v: Vec3, angle: f64| {
+            let (sin, cos) = f64::sin_cos(angle);
+            Vec3::new(cos * v.x - sin * v.y, sin * v.x + cos * v.y, v.z)
+        };
+        let wo = Vec3::new(0.6, 0.1, 0.7).unit();
+        let wi = Vec3::new(-0.5, 0.3, 0.8).unit();
+        let reference = Material::eval(&conductor, &hit(Vec3::new(1.0, 0.0, 0.0)), wo, wi);
+        for &angle in &[0.4, 1.3, 2.9] {
+            // the surface and both directions turned about the normal see the same lobe
+            let turned = hit(rotate(Vec3::new(1.0, 0.0, 0.0), angle));
+            let (wo_turned, wi_turned) = (rotate(wo, angle), rotate(wi, angle));
+            let f = Material::eval(&conductor, &turned, wo_turned, wi_turned);
+            assert!((f - reference).length() < 1e-9 * reference.length());
+            let pdf = Material::pdf(&conductor, &turned, wo_turned, wi_turned);
+            let reference_pdf = Material::pdf(&conductor, &hit(Vec3::new(1.0, 0.0, 0.0)), wo, wi);
+            assert!((pdf - reference_pdf).abs() < 1e-9 * reference_pdf);
+            // while turning only the surface turns the lobe away from the directions
+            let f = Material::eval(&conductor, &turned, wo, wi);
+            assert!((f - reference).length() > 1e-3 * reference.length());
+        }
+    }
+}
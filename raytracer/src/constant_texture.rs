@@ -0,0 +1,17 @@
+use crate::{texture::Texture, Color, Point3};
+
+pub struct ConstantTexture {
+    pub color: Color,
+}
+
+impl ConstantTexture {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for ConstantTexture {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        self.color
+    }
+}
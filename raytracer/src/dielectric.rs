@@ -1,13 +1,190 @@
-use crate::{utils, Color, HitRecord, Material, Ray, ScatterRecord};
-use rand::{rngs::SmallRng, Rng};
+use crate::dispersion::Dispersion;
+use crate::fresnel::{fresnel_dielectric, refract};
+use crate::microfacet::TrowbridgeReitz;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::spectrum::{sample_visible_wavelength, wavelength_weight};
+use crate::thin_film::ThinFilm;
+use crate::utils::reflect;
+use crate::{Color, HitRecord, Material, Ray, ScatterRecord, Vec3};
 
+// Glass and other transparent media, smooth or with a rough surface (Walter et al.
+// 2007), optionally tinted by absorption along the path inside, splitting white
+// light into its wavelengths or coated by an iridescent film
 pub struct Dielectric {
     pub ir: f64,
+    // replaces `ir` by an index depending on the wavelength
+    pub dispersion: Option<Dispersion>,
+    pub distribution: TrowbridgeReitz,
+    // per unit length inside, over a distance d the light keeps exp(-absorption d)
+    pub absorption: Color,
+    // on both sides of a smooth surface, ignored on a rough one
+    pub thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
-        Self { ir }
+        Self {
+            ir,
+            dispersion: None,
+            distribution: TrowbridgeReitz::new(0.0, 0.0),
+            absorption: Color::zero(),
+            thin_film: None,
+        }
+    }
+
+    pub fn with_roughness(self, distribution: TrowbridgeReitz) -> Self {
+        Self {
+            distribution,
+            ..self
+        }
+    }
+
+    pub fn with_absorption(self, absorption: Color) -> Self {
+        Self { absorption, ..self }
+    }
+
+    pub fn with_dispersion(self, dispersion: Dispersion) -> Self {
+        Self {
+            dispersion: Some(dispersion),
+            ..self
+        }
+    }
+
+    pub fn with_thin_film(self, thin_film: ThinFilm) -> Self {
+        Self {
+            thin_film: Some(thin_film),
+            ..self
+        }
+    }
+
+    // The reflectance of the smooth surface for `wo` at `cos_theta_i` from the normal
+    fn reflectance(
+        &self,
+        cos_theta_i: f64,
+        eta: f64,
+        front: bool,
+        wavelength: Option<f64>,
+    ) -> Color {
+        match &self.thin_film {
+            Some(film) => {
+                // the indices of the medium of `wo` and of the one beyond
+                let (n1, n3) = if front { (1.0, eta) } else { (1.0 / eta, 1.0) };
+                film.reflectance(
+                    cos_theta_i,
+                    n1,
+                    Color::ones() * n3,
+                    Color::zero(),
+                    wavelength,
+                )
+            }
+            None => Color::ones() * fresnel_dielectric(cos_theta_i, eta),
+        }
+    }
+
+    // What is left of the light that travelled inside up to the hit. Rays leaving
+    // the surface have unit directions, so `t` is the distance they travelled.
+    fn transmittance(&self, hit_record: &HitRecord) -> Color {
+        if hit_record.front {
+            return Color::ones();
+        }
+        let a = self.absorption * -hit_record.t;
+        Color::new(a.x.exp(), a.y.exp(), a.z.exp())
+    }
+
+    // the index of refraction beyond the surface over the one on the side of `wo`
+    fn eta(&self, front: bool, wavelength: Option<f64>) -> f64 {
+        let ir = match (&self.dispersion, wavelength) {
+            (Some(dispersion), Some(wavelength)) => dispersion.ior(wavelength),
+            _ => self.ir,
+        };
+        if front {
+            ir
+        } else {
+            1.0 / ir
+        }
+    }
+
+    // a dispersive medium scatters rays that still carry all colors specularly, as
+    // light sampling cannot know which wavelength `scatter` will pick
+    fn splits(&self, wavelength: Option<f64>) -> bool {
+        self.dispersion.is_some() && wavelength.is_none()
+    }
+
+    // The microfacet normal that scatters `wo` into `wi`, `None` if it faces away from either
+    fn half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+        let etap = if wi.z > 0.0 { 1.0 } else { eta };
+        let wm = wi * etap + wo;
+        if wi.z == 0.0 || wm.squared_length() == 0.0 {
+            return None;
+        }
+        let wm = if wm.z < 0.0 { -wm.unit() } else { wm.unit() };
+        if (wm * wi) * wi.z < 0.0 || wm * wo < 0.0 {
+            return None;
+        }
+        Some(wm)
+    }
+
+    // The BSDF of the rough surface for `wo` and `wi` in the shading frame, with
+    // `wo` above it and `wi` above for reflection or below for transmission
+    pub fn eval(&self, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        if wo.z <= 0.0 || self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let wm = match Dielectric::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+        let fresnel = fresnel_dielectric(wo * wm, eta);
+        let dg = self.distribution.d(wm) * self.distribution.g(wo, wi);
+        if wi.z > 0.0 {
+            dg * fresnel / (4.0 * wo.z * wi.z)
+        } else {
+            let denom = (wi * wm + wo * wm / eta).powi(2) * wi.z * wo.z;
+            dg * (1.0 - fresnel) * ((wi * wm) * (wo * wm) / denom).abs()
+        }
+    }
+
+    // The density of `sample` choosing `wi`
+    pub fn pdf(&self, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+        if wo.z <= 0.0 || self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let wm = match Dielectric::half_vector(wo, wi, eta) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+        let reflectance = fresnel_dielectric(wo * wm, eta);
+        let pdf_wm = self.distribution.pdf(wo, wm);
+        if wi.z > 0.0 {
+            pdf_wm / (4.0 * (wo * wm)) * reflectance
+        } else {
+            let denom = (wi * wm + wo * wm / eta).powi(2);
+            pdf_wm * (wi * wm).abs() / denom * (1.0 - reflectance)
+        }
+    }
+
+    // `wi` reflected or refracted by a visible microfacet normal, picked by its
+    // Fresnel reflectance, with the BSDF and density there
+    pub fn sample(&self, wo: Vec3, eta: f64, u: (f64, f64), uc: f64) -> Option<(Vec3, f64, f64)> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let wm = self.distribution.sample_wm(wo, u);
+        let wi = if uc < fresnel_dielectric(wo * wm, eta) {
+            let wi = reflect(-wo, wm);
+            if wi.z <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wi = refract(wo, wm, eta)?;
+            if wi.z >= 0.0 {
+                return None;
+            }
+            wi
+        };
+        Some((wi, self.eval(wo, wi, eta), self.pdf(wo, wi, eta)))
     }
 }
 
@@ -16,27 +193,137 @@ impl Material for Dielectric {
         &self,
         ray_in: &Ray,
         hit_record: HitRecord,
-        _rng: &mut SmallRng,
+        sampler: &mut dyn Sampler,
     ) -> Option<ScatterRecord> {
-        let refraction_ratio = match hit_record.front {
-            true => 1.0 / self.ir,
-            false => self.ir,
+        // The first dispersive medium a path meets picks the wavelength it carries
+        // from then on, the hero wavelength, weighted by the color it stands for
+        let (wavelength, weight) = if self.splits(ray_in.wavelength) {
+            let wavelength = sample_visible_wavelength(sampler.get_1d());
+            (Some(wavelength), wavelength_weight(wavelength))
+        } else {
+            (ray_in.wavelength, Color::ones())
         };
-        let unit_direction = ray_in.direction.unit();
-        let cos_theta = (-unit_direction * hit_record.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-
-        let cannot_refract = (refraction_ratio * sin_theta) > 1.0
-            || utils::reflectance(cos_theta, refraction_ratio) > _rng.gen_range(0.0..1.0);
-        let direction = match cannot_refract {
-            true => utils::reflect(unit_direction, hit_record.normal),
-            false => utils::refract(unit_direction, hit_record.normal, refraction_ratio),
+        let eta = self.eta(hit_record.front, wavelength);
+        let transmittance = Vec3::elemul(self.transmittance(&hit_record), weight);
+        let wo = -ray_in.direction.unit();
+        let scattered = |direction| Ray {
+            wavelength,
+            ..Ray::new(hit_record.p, direction)
         };
-        let specular_ray = Ray::new(hit_record.p, direction);
-        let attenuation = Color::new(1.0, 1.0, 1.0);
-        return Some(ScatterRecord::Specular {
-            specular_ray,
+        if self.distribution.effectively_smooth() {
+            let normal = hit_record.normal;
+            // a colored reflectance is picked by its mean and weighted by the rest
+            let reflectance = self.reflectance(wo * normal, eta, hit_record.front, wavelength);
+            let p = (reflectance.x + reflectance.y + reflectance.z) / 3.0;
+            let (direction, weight) = if sampler.get_1d() < p {
+                (reflect(-wo, normal), reflectance / p)
+            } else {
+                (
+                    refract(wo, normal, eta)?,
+                    (Color::ones() - reflectance) / (1.0 - p),
+                )
+            };
+            return Some(ScatterRecord::Specular {
+                specular_ray: scattered(direction),
+                attenuation: Vec3::elemul(transmittance, weight),
+            });
+        }
+        let onb = Onb::from_hit(&hit_record);
+        let wo = onb.world_to_local(wo);
+        let (wi, f, pdf) = self.sample(wo, eta, sampler.get_2d(), sampler.get_1d())?;
+        if pdf == 0.0 {
+            return None;
+        }
+        let attenuation = transmittance * (f * wi.z.abs() / pdf);
+        if self.splits(ray_in.wavelength) {
+            return Some(ScatterRecord::Specular {
+                specular_ray: scattered(onb.local(wi)),
+                attenuation,
+            });
+        }
+        Some(ScatterRecord::Diffuse {
+            scattered: scattered(onb.local(wi)),
             attenuation,
-        });
+            pdf,
+        })
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.dispersion.is_some() || self.thin_film.is_some()
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        if self.splits(hit_record.wavelength) {
+            return Color::zero();
+        }
+        let onb = Onb::from_hit(hit_record);
+        let wi = onb.world_to_local(wi);
+        let eta = self.eta(hit_record.front, hit_record.wavelength);
+        let f = self.eval(onb.world_to_local(wo), wi, eta);
+        self.transmittance(hit_record) * (f * wi.z.abs())
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        if self.splits(hit_record.wavelength) {
+            return 0.0;
+        }
+        let onb = Onb::from_hit(hit_record);
+        let eta = self.eta(hit_record.front, hit_record.wavelength);
+        self.pdf(onb.world_to_local(wo), onb.world_to_local(wi), eta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warp::{sample_uniform_sphere, uniform_sphere_pdf};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_sampling_matches_eval() {
+        let glass = Dielectric::new(1.5).with_roughness(TrowbridgeReitz::new(0.3, 0.2));
+        let mut rng = SmallRng::seed_from_u64(1);
+        // entering and leaving the glass
+        for &eta in &[1.5, 1.0 / 1.5] {
+            let wo = Vec3::new(0.3, -0.4, 0.7).unit();
+            let n = 400_000;
+            let (mut sampled, mut uniform, mut pdf_integral) = (0.0, 0.0, 0.0);
+            for _ in 0..n {
+                let sample = glass.sample(wo, eta, (rng.gen(), rng.gen()), rng.gen());
+                if let Some((wi, f, pdf)) = sample {
+                    assert!((glass.pdf(wo, wi, eta) - pdf).abs() < 1e-9 * pdf.max(1.0));
+                    sampled += f * wi.z.abs() / pdf;
+                }
+                let wi = sample_uniform_sphere((rng.gen(), rng.gen()));
+                uniform += glass.eval(wo, wi, eta) * wi.z.abs() / uniform_sphere_pdf();
+                pdf_integral += glass.pdf(wo, wi, eta) / uniform_sphere_pdf();
+            }
+            let (sampled, uniform) = (sampled / n as f64, uniform / n as f64);
+            assert!((sampled - uniform).abs() < 0.03);
+            // nothing is absorbed at the surface, only lost to masking
+            assert!(sampled > 0.8 && sampled < 1.0);
+            assert!(pdf_integral / (n as f64) < 1.02);
+        }
+    }
+
+    #[test]
+    fn test_absorption() {
+        let glass = Dielectric::new(1.5).with_absorption(Color::new(0.0, 0.5, 1.0));
+        let material: std::sync::Arc<dyn Material> = std::sync::Arc::new(Dielectric::new(1.5));
+        let hit = |front| HitRecord {
+            p: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            t: 2.0,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 1.0, 0.0),
+            front,
+            wavelength: None,
+            material: material.clone(),
+        };
+        assert_eq!(glass.transmittance(&hit(true)), Color::ones());
+        let expected = Color::new(1.0, (-1.0_f64).exp(), (-2.0_f64).exp());
+        assert!((glass.transmittance(&hit(false)) - expected).length() < 1e-12);
     }
 }
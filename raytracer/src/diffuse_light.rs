@@ -0,0 +1,62 @@
+use crate::constant_texture::ConstantTexture;
+use crate::light_spectrum::LightSpectrum;
+use crate::rgb_spectrum::RgbSpectrum;
+use crate::sampled_spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::sampler::Sampler;
+use crate::{texture::Texture, Color, HitRecord, Material, Point3, Ray, ScatterRecord};
+use std::sync::Arc;
+
+pub struct DiffuseLight {
+    pub emit: Arc<dyn Texture>,
+    // emitted by the spectral integrator instead of a spectrum of `emit`
+    pub spectrum: Option<LightSpectrum>,
+}
+
+impl DiffuseLight {
+    pub fn with_texture(emit: Arc<dyn Texture>) -> Self {
+        Self {
+            emit,
+            spectrum: None,
+        }
+    }
+
+    // a light of `spectrum`, of its color elsewhere
+    pub fn with_spectrum(spectrum: LightSpectrum) -> Self {
+        Self {
+            emit: Arc::new(ConstantTexture::new(spectrum.to_rgb())),
+            spectrum: Some(spectrum),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _hit_record: HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: Point3) -> Color {
+        self.emit.value(u, v, p)
+    }
+
+    fn emitted_spectrum(
+        &self,
+        u: f64,
+        v: f64,
+        p: Point3,
+        lambda: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        match &self.spectrum {
+            Some(spectrum) => spectrum.sample(lambda),
+            None => RgbSpectrum::new(self.emitted(u, v, p)).sample(lambda),
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,64 @@
+use serde::Deserialize;
+
+// An index of refraction that depends on the wavelength, fitted to a glass
+// with wavelengths in micrometers
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type")]
+pub enum Dispersion {
+    // n = a + b / λ²
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    // n² = 1 + Σ b_i λ² / (λ² - c_i)
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+    // Schott N-BK7, the usual crown glass
+    #[serde(rename = "BK7")]
+    Bk7,
+}
+
+impl Dispersion {
+    // the index of refraction at `lambda` in nm
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let l2 = (lambda / 1000.0).powi(2);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0
+                    + b.iter()
+                        .zip(c.iter())
+                        .map(|(b, c)| b * l2 / (l2 - c))
+                        .sum::<f64>();
+                n2.sqrt()
+            }
+            Dispersion::Bk7 => Dispersion::Sellmeier {
+                b: [1.03961212, 0.231792344, 1.01046945],
+                c: [0.00600069867, 0.0200179144, 103.560653],
+            }
+            .ior(lambda),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ior() {
+        // BK7 at the d line and its Abbe number
+        let bk7 = Dispersion::Bk7;
+        assert!((bk7.ior(587.6) - 1.5168).abs() < 1e-4);
+        let abbe = (bk7.ior(587.6) - 1.0) / (bk7.ior(486.1) - bk7.ior(656.3));
+        assert!((abbe - 64.17).abs() < 0.1);
+        let cauchy = Dispersion::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        assert!((cauchy.ior(500.0) - (1.5046 + 0.0042 / 0.25)).abs() < 1e-12);
+        assert!(cauchy.ior(400.0) > cauchy.ior(700.0));
+    }
+}
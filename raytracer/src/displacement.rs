@@ -0,0 +1,306 @@
+use crate::texture::Texture;
+use crate::triangle_mesh::TriangleMesh;
+use crate::{Material, Point3, Vec3};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// the shortest edge that is split, as a part of the diagonal of the mesh's bounds
+const MIN_EDGE: f64 = 1.0 / 2048.0;
+// the most times a face is split, should its edges stay too long anyway
+const MAX_DEPTH: u32 = 12;
+
+// Heights along the normals of a mesh, the mean of the channels of `map` times `scale`,
+// made out of triangles with edges about `edge_length` pixels long on the image
+pub struct Displacement {
+    pub map: Arc<dyn Texture>,
+    pub scale: f64,
+    pub edge_length: f64,
+}
+
+// A mesh to displace with `material` once the camera is known, see `Scene::tessellate`.
+// It is where it is when the shutter opens, `motion` scales and moves it from there
+// by the time the shutter closes, see `MovingTriangle`.
+pub struct DisplacedMesh {
+    pub mesh: Arc<TriangleMesh>,
+    pub material: Arc<dyn Material>,
+    pub displacement: Displacement,
+    pub motion: Option<(f64, Vec3)>,
+}
+
+// The mesh being split, its faces only added once they are small enough
+struct Tessellation<'a, F> {
+    displacement: &'a Displacement,
+    footprint: F,
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[usize; 3]>,
+    // the vertex in the middle of each split edge, by its ends in increasing order
+    midpoints: HashMap<(usize, usize), usize>,
+    min_edge: f64,
+}
+
+impl<'a, F: Fn(Point3) -> Option<f64>> Tessellation<'a, F> {
+    // Whether an edge is too long for where it is. It only depends on the edge, so
+    // that the faces on either side split it alike.
+    fn splits(&self, i: usize, j: usize) -> bool {
+        let (a, b) = (self.positions[i], self.positions[j]);
+        let length = (b - a).length();
+        if length <= self.min_edge {
+            return false;
+        }
+        match (self.footprint)((a + b) * 0.5) {
+            Some(footprint) => length > footprint * self.displacement.edge_length,
+            None => false,
+        }
+    }
+
+    fn midpoint(&mut self, i: usize, j: usize) -> usize {
+        let key = (i.min(j), i.max(j));
+        if let Some(&m) = self.midpoints.get(&key) {
+            return m;
+        }
+        let m = self.positions.len();
+        self.positions
+            .push((self.positions[i] + self.positions[j]) * 0.5);
+        let normal = self.normals[i] + self.normals[j];
+        self.normals.push(if normal.squared_length() > 0.0 {
+            normal.unit()
+        } else {
+            self.normals[i]
+        });
+        if !self.uvs.is_empty() {
+            let (a, b) = (self.uvs[i], self.uvs[j]);
+            self.uvs.push(((a.0 + b.0) * 0.5, (a.1 + b.1) * 0.5));
+        }
+        self.midpoints.insert(key, m);
+        m
+    }
+
+    // Splits the too long edges of the face and goes on with the faces that makes
+    fn split(&mut self, face: [usize; 3], depth: u32) {
+        let edges = [
+            self.splits(face[0], face[1]),
+            self.splits(face[1], face[2]),
+            self.splits(face[2], face[0]),
+        ];
+        let count = edges.iter().filter(|&&split| split).count();
+        if count == 0 || depth == MAX_DEPTH {
+            self.indices.push(face);
+            return;
+        }
+        let faces = match count {
+            // with the split edge from the first corner to the second
+            1 => {
+                let k = edges.iter().position(|&split| split).unwrap();
+                let [a, b, c] = [face[k], face[(k + 1) % 3], face[(k + 2) % 3]];
+                let m = self.midpoint(a, b);
+                vec![[a, m, c], [m, b, c]]
+            }
+            // with the edge that stays from the third corner to the first
+            2 => {
+                let k = edges.iter().position(|&split| !split).unwrap();
+                let [a, b, c] = [face[(k + 1) % 3], face[(k + 2) % 3], face[k]];
+                let (ab, bc) = (self.midpoint(a, b), self.midpoint(b, c));
+                vec![[ab, b, bc], [a, ab, bc], [a, bc, c]]
+            }
+            _ => {
+                let [a, b, c] = face;
+                let (ab, bc, ca) = (
+                    self.midpoint(a, b),
+                    self.midpoint(b, c),
+                    self.midpoint(c, a),
+                );
+                vec![[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+            }
+        };
+        for face in faces {
+            self.split(face, depth + 1);
+        }
+    }
+}
+
+impl Displacement {
+    // `mesh` split until its edges are short enough where they are, by `footprint` the
+    // size of a pixel around a point, or `None` where it does not matter, and then
+    // displaced. Faces agree on how to split the edges they share, so no cracks open
+    // between them but at seams, where the corners have two normals or texture
+    // coordinates. Shading follows the displaced faces.
+    pub fn tessellate(
+        &self,
+        mesh: &TriangleMesh,
+        footprint: impl Fn(Point3) -> Option<f64>,
+    ) -> TriangleMesh {
+        // nothing to split, and no bounds to scale the shortest edge by
+        if mesh.indices.is_empty() {
+            return mesh.clone();
+        }
+        let normals = if mesh.normals.is_empty() {
+            mesh.smooth_normals()
+        } else {
+            mesh.normals
+                .iter()
+                .map(|&n| {
+                    if n.squared_length() > 0.0 {
+                        n.unit()
+                    } else {
+                        n
+                    }
+                })
+                .collect()
+        };
+        let (min, max) =
+            mesh.positions
+                .iter()
+                .fold((mesh.positions[0], mesh.positions[0]), |(min, max), &p| {
+                    (
+                        Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                        Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                    )
+                });
+        let mut tessellation = Tessellation {
+            displacement: self,
+            footprint,
+            positions: mesh.positions.clone(),
+            normals,
+            uvs: mesh.uvs.clone(),
+            indices: vec![],
+            midpoints: HashMap::new(),
+            min_edge: (max - min).length() * MIN_EDGE,
+        };
+        for &face in &mesh.indices {
+            tessellation.split(face, 0);
+        }
+        let Tessellation {
+            positions,
+            normals,
+            uvs,
+            indices,
+            ..
+        } = tessellation;
+        let positions = positions
+            .iter()
+            .zip(&normals)
+            .enumerate()
+            .map(|(i, (&p, &n))| {
+                let (u, v) = uvs.get(i).copied().unwrap_or((0.0, 0.0));
+                let c = self.map.value(u, v, p);
+                p + n * ((c.x + c.y + c.z) / 3.0 * self.scale)
+            })
+            .collect();
+        let mut displaced = TriangleMesh {
+            positions,
+            normals: vec![],
+            uvs,
+            tangents: vec![],
+            indices,
+        };
+        displaced.normals = displaced.smooth_normals();
+        displaced.generate_tangents();
+        displaced
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constant_texture::ConstantTexture;
+    use crate::Color;
+
+    // the unit square in the xy plane, without normals
+    fn square() -> TriangleMesh {
+        TriangleMesh {
+            positions: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            normals: vec![],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            tangents: vec![],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+        }
+    }
+
+    // Each edge inside the square is shared by two faces, there is no vertex in the
+    // middle of an edge of just one of them
+    fn assert_watertight(mesh: &TriangleMesh) {
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for &[a, b, c] in &mesh.indices {
+            for &(i, j) in &[(a, b), (b, c), (c, a)] {
+                *edges.entry((i.min(j), i.max(j))).or_insert(0) += 1;
+            }
+        }
+        for (&(i, j), &count) in &edges {
+            let (a, b) = (mesh.positions[i], mesh.positions[j]);
+            let on_border =
+                |f: fn(Point3) -> f64| [0.0, 1.0].iter().any(|&x| f(a) == x && f(b) == x);
+            if on_border(|p| p.x) || on_border(|p| p.y) {
+                assert_eq!(count, 1);
+            } else {
+                assert_eq!(count, 2, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_uniform() {
+        let displacement = Displacement {
+            map: Arc::new(ConstantTexture::new(Color::new(0.2, 0.3, 0.4))),
+            scale: 0.5,
+            edge_length: 2.0,
+        };
+        let mesh = displacement.tessellate(&square(), |_| Some(0.05));
+        assert!(mesh.indices.len() > 100);
+        for &[a, b, c] in &mesh.indices {
+            for &(i, j) in &[(a, b), (b, c), (c, a)] {
+                assert!((mesh.positions[i] - mesh.positions[j]).length() <= 0.1);
+            }
+        }
+        // lifted by the mean height off the plane, facing up still
+        for (p, n) in mesh.positions.iter().zip(&mesh.normals) {
+            assert!((p.z - 0.15).abs() < 1e-12);
+            assert!((*n - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        }
+        assert_eq!(mesh.tangents.len(), mesh.positions.len());
+        assert_watertight(&mesh);
+
+        let empty = TriangleMesh {
+            positions: vec![],
+            normals: vec![],
+            uvs: vec![],
+            tangents: vec![],
+            indices: vec![],
+        };
+        let mesh = displacement.tessellate(&empty, |_| Some(0.05));
+        assert!(mesh.positions.is_empty() && mesh.indices.is_empty());
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let displacement = Displacement {
+            map: Arc::new(ConstantTexture::new(Color::zero())),
+            scale: 1.0,
+            edge_length: 1.0,
+        };
+        // finer where the camera is close, at x = 0
+        let footprint = |p: Point3| Some(0.01 + 0.2 * p.x);
+        let mesh = displacement.tessellate(&square(), footprint);
+        let longest = |x: f64| {
+            mesh.indices
+                .iter()
+                .flat_map(|&[a, b, c]| vec![(a, b), (b, c), (c, a)])
+                .map(|(i, j)| (mesh.positions[i], mesh.positions[j]))
+                .filter(|(a, b)| (a.x + b.x) / 2.0 < x)
+                .map(|(a, b)| (a - b).length())
+                .fold(0.0, f64::max)
+        };
+        assert!(longest(0.05) < 0.05);
+        assert!(longest(1.0) > 0.1);
+        assert_watertight(&mesh);
+        // not seen at all, it stays as it is
+        let unseen = displacement.tessellate(&square(), |_| None);
+        assert_eq!(unseen.indices, square().indices);
+    }
+}
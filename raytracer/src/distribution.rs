@@ -0,0 +1,126 @@
+// Piecewise-constant distributions over [0, 1) and [0, 1)², for sampling
+// in proportion to tabulated values such as the pixels of an image.
+
+pub struct Distribution1D {
+    pub func: Vec<f64>,
+    // n + 1 entries from 0 to 1
+    pub cdf: Vec<f64>,
+    pub integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].abs() / n as f64;
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            // all zero: sample uniformly
+            *c = if integral == 0.0 {
+                i as f64 / n as f64
+            } else {
+                *c / integral
+            };
+        }
+        Self {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    // A point in [0, 1) with the density of the function, its pdf and its cell
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // the last cell starting at or before u, which skips empty cells
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.count() - 1);
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+        let pdf = if self.integral > 0.0 {
+            self.func[offset].abs() / self.integral
+        } else {
+            1.0
+        };
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+}
+
+// Rows of `width` values, sampled by picking a row from the marginal
+// distribution and then a column from the row
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(values: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(values.len(), width * height);
+        let conditional: Vec<Distribution1D> = values
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral).collect());
+        Self {
+            conditional,
+            marginal,
+        }
+    }
+
+    // (x, y) in [0, 1)² with y counting rows, and the pdf
+    pub fn sample_continuous(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample_continuous(u.1);
+        let (x, pdf_x, _) = self.conditional[row].sample_continuous(u.0);
+        ((x, y), pdf_x * pdf_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_1d() {
+        let distribution = Distribution1D::new(vec![1.0, 0.0, 3.0]);
+        assert_eq!(distribution.integral, 4.0 / 3.0);
+        let (x, pdf, offset) = distribution.sample_continuous(0.1);
+        assert!((x - 0.4 / 3.0).abs() < 1e-12);
+        assert_eq!((pdf, offset), (0.75, 0));
+        // the empty cell is never picked
+        let (x, pdf, offset) = distribution.sample_continuous(0.25);
+        assert_eq!((x, pdf, offset), (2.0 / 3.0, 2.25, 2));
+    }
+
+    #[test]
+    fn test_2d_frequencies() {
+        let values = [0.0, 1.0, 2.0, 3.0, 0.0, 6.0];
+        let distribution = Distribution2D::new(&values, 3, 2);
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut counts = [0.0; 6];
+        let n = 120_000;
+        for _ in 0..n {
+            let ((x, y), pdf) =
+                distribution.sample_continuous((rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)));
+            let cell = (y * 2.0) as usize * 3 + (x * 3.0) as usize;
+            assert!((pdf - values[cell] * 6.0 / 12.0).abs() < 1e-12);
+            counts[cell] += 1.0;
+        }
+        for (count, value) in counts.iter().zip(&values) {
+            let expected = value / 12.0 * n as f64;
+            assert!(
+                (count - expected).abs() <= 4.0 * expected.sqrt(),
+                "{} vs {}",
+                count,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use crate::camera::{Camera, Frame};
+use crate::sampler::Sampler;
+use crate::{Point3, Ray};
+use std::f64::consts::PI;
+
+// Longitude across the image and latitude up it, `lookat` in the center
+pub struct EquirectangularCamera {
+    pub frame: Frame,
+    // for omnidirectional stereo, how far each ray starts to the right of its
+    // view direction, negative for the left eye
+    pub eye_offset: f64,
+}
+
+impl EquirectangularCamera {
+    pub fn new(frame: Frame) -> Self {
+        Self {
+            frame,
+            eye_offset: 0.0,
+        }
+    }
+
+    pub fn with_eye_offset(self, eye_offset: f64) -> Self {
+        Self { eye_offset, ..self }
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, s: f64, t: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        let longitude = (s - 0.5) * 2.0 * PI;
+        let latitude = (t - 0.5) * PI;
+        let frame = &self.frame;
+        let horizontal = frame.u * longitude.sin() - frame.w * longitude.cos();
+        let direction = horizontal * latitude.cos() + frame.v * latitude.sin();
+        // the eyes turn with the head, on a circle around `lookfrom`
+        let right = frame.u * longitude.cos() + frame.w * longitude.sin();
+        Some(Ray::new(frame.origin + right * self.eye_offset, direction))
+    }
+
+    // The camera sees all around, and a pixel spans the same angle of latitude anywhere
+    fn pixel_footprint(&self, p: Point3, height: u32) -> Option<f64> {
+        Some((p - self.frame.origin).length() * PI / height.max(1) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::Vec3;
+
+    #[test]
+    fn test_directions() {
+        let frame = Frame::new(
+            Point3::zero(),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let camera = EquirectangularCamera::new(frame);
+        let mut sampler = IndependentSampler::new(1);
+        let mut direction = |s, t| camera.get_ray(s, t, &mut sampler).unwrap().direction;
+        assert!((direction(0.5, 0.5) - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+        assert!((direction(0.75, 0.5) - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+        assert!((direction(0.0, 0.5) - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((direction(0.3, 1.0) - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::render::Tile;
+use crate::Color;
+use image::{ImageBuffer, Rgb, RgbImage};
+
+// Running mean and variance of the samples of one pixel (Welford's algorithm)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PixelStats {
+    pub count: u32,
+    pub mean: Color,
+    // sum of squared differences from the mean
+    pub m2: Color,
+}
+
+impl PixelStats {
+    pub fn add_sample(&mut self, color: Color) {
+        self.count += 1;
+        let delta = color - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += Color::elemul(delta, color - self.mean);
+    }
+
+    // combine statistics of two disjoint sets of samples (Chan et al.)
+    pub fn merge(&mut self, other: &PixelStats) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let weight = other.count as f64 / count as f64;
+        self.mean += delta * weight;
+        self.m2 += other.m2 + Color::elemul(delta, delta) * (self.count as f64 * weight);
+        self.count = count;
+    }
+
+    // unbiased sample variance of a single sample
+    pub fn variance(&self) -> Color {
+        if self.count < 2 {
+            return Color::zero();
+        }
+        self.m2 / (self.count - 1) as f64
+    }
+
+    // variance of the pixel estimate relative to its squared value, averaged over channels
+    pub fn relative_variance(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let variance = self.variance() / self.count as f64;
+        let relative = |var: f64, mean: f64| var / (mean * mean + 1e-4);
+        (relative(variance.x, self.mean.x)
+            + relative(variance.y, self.mean.y)
+            + relative(variance.z, self.mean.z))
+            / 3.0
+    }
+}
+
+// Float accumulation buffer of the whole image, rows counted from the top
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<PixelStats>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![PixelStats::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<PixelStats>) -> Self {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn pixels(&self) -> &[PixelStats] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &PixelStats {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    // `stats` holds the tile's pixels in row-major order
+    pub fn merge_tile(&mut self, tile: &Tile, stats: &[PixelStats]) {
+        for (i, pixel) in stats.iter().enumerate() {
+            let x = tile.x + i as u32 % tile.width;
+            let y = tile.y + i as u32 / tile.width;
+            self.pixels[(y * self.width + x) as usize].merge(pixel);
+        }
+    }
+
+    // number of pixels whose relative variance is still above `threshold`
+    pub fn count_noisy(&self, threshold: f64) -> usize {
+        self.pixels
+            .iter()
+            .filter(|pixel| pixel.relative_variance() > threshold)
+            .count()
+    }
+
+    // Pixels that need more samples: the noisy ones and their direct neighbours,
+    // since a pixel can look converged by chance after a few samples.
+    pub fn noisy_mask(&self, threshold: f64) -> Vec<bool> {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let mut mask = vec![false; self.pixels.len()];
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            if pixel.relative_variance() <= threshold {
+                continue;
+            }
+            let (x, y) = (i as i64 % width, i as i64 / width);
+            for ny in (y - 1).max(0)..(y + 2).min(height) {
+                for nx in (x - 1).max(0)..(x + 2).min(width) {
+                    mask[(ny * width + nx) as usize] = true;
+                }
+            }
+        }
+        mask
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|pixel| pixel.count as u64).sum()
+    }
+
+    pub fn min_samples(&self) -> u32 {
+        self.pixels
+            .iter()
+            .map(|pixel| pixel.count)
+            .min()
+            .unwrap_or(0)
+    }
+
+    // Samples per pixel as a heat map, black for the fewest and white for the most
+    pub fn sample_heatmap(&self) -> RgbImage {
+        let max = self
+            .pixels
+            .iter()
+            .map(|pixel| pixel.count)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        // black, blue, red, yellow, white
+        let ramp = [
+            Color::new(0.0, 0.0, 0.0),
+            Color::new(0.1, 0.1, 0.8),
+            Color::new(0.9, 0.1, 0.1),
+            Color::new(1.0, 0.9, 0.1),
+            Color::new(1.0, 1.0, 1.0),
+        ];
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let t = self.pixel(x, y).count as f64 / max as f64 * (ramp.len() - 1) as f64;
+            let i = (t as usize).min(ramp.len() - 2);
+            let color = ramp[i] * (1.0 - (t - i as f64)) + ramp[i + 1] * (t - i as f64);
+            let color = color * 255.999;
+            Rgb([color.x as u8, color.y as u8, color.z as u8])
+        })
+    }
+
+    pub fn to_image(&self) -> RgbImage {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let mean = self.pixel(x, y).mean;
+            // gamma 2
+            let color = Color::new(mean.x.sqrt(), mean.y.sqrt(), mean.z.sqrt()) * 255.999;
+            Rgb([color.x as u8, color.y as u8, color.z as u8])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_of(samples: &[f64]) -> PixelStats {
+        let mut stats = PixelStats::default();
+        for &s in samples {
+            stats.add_sample(Color::new(s, s, s));
+        }
+        stats
+    }
+
+    #[test]
+    fn test_welford() {
+        let stats = stats_of(&[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(stats.count, 4);
+        assert!((stats.mean.x - 2.5).abs() < 1e-12);
+        assert!((stats.variance().x - 5.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut merged = stats_of(&[0.1, 0.5, 0.2]);
+        merged.merge(&stats_of(&[0.9, 0.3]));
+        let all = stats_of(&[0.1, 0.5, 0.2, 0.9, 0.3]);
+        assert_eq!(merged.count, all.count);
+        assert!((merged.mean - all.mean).length() < 1e-12);
+        assert!((merged.m2 - all.m2).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_noisy_mask() {
+        let mut film = Film::new(4, 3);
+        let converged = stats_of(&[0.5, 0.5, 0.5, 0.5]);
+        for pixel in film.pixels.iter_mut() {
+            *pixel = converged;
+        }
+        film.pixels[4 * 2 + 3] = stats_of(&[0.0, 1.0, 0.0, 1.0]);
+        let mask = film.noisy_mask(1e-3);
+        let expected = [
+            false, false, false, false, //
+            false, false, true, true, //
+            false, false, true, true,
+        ];
+        assert_eq!(mask, expected);
+    }
+
+    #[test]
+    fn test_relative_variance_decreases() {
+        let few = stats_of(&[0.2, 0.6, 0.2, 0.6]);
+        let many = stats_of(&[0.2, 0.6].repeat(32));
+        assert!(many.relative_variance() < few.relative_variance());
+        assert_eq!(stats_of(&[0.5]).relative_variance(), f64::INFINITY);
+    }
+}
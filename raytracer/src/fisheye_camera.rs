@@ -0,0 +1,129 @@
+use crate::camera::{Camera, FisheyeMapping, Frame};
+use crate::sampler::Sampler;
+use crate::{Point3, Ray};
+
+// Circular fisheye, the image circle touching the shorter side of the image
+pub struct FisheyeCamera {
+    pub frame: Frame,
+    // half the field of view, in radians
+    pub theta_max: f64,
+    pub mapping: FisheyeMapping,
+    // half the image size relative to the radius of the image circle
+    pub half_width: f64,
+    pub half_height: f64,
+}
+
+impl FisheyeCamera {
+    pub fn new(frame: Frame, fov: f64, mapping: FisheyeMapping, aspect_ratio: f64) -> Self {
+        let (half_width, half_height) = if aspect_ratio >= 1.0 {
+            (aspect_ratio, 1.0)
+        } else {
+            (1.0, 1.0 / aspect_ratio)
+        };
+        Self {
+            frame,
+            theta_max: fov.to_radians() / 2.0,
+            mapping,
+            half_width,
+            half_height,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, s: f64, t: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        let x = (s * 2.0 - 1.0) * self.half_width;
+        let y = (t * 2.0 - 1.0) * self.half_height;
+        // distance from the center, 1 at the edge of the image circle
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * self.theta_max,
+            FisheyeMapping::Equisolid => 2.0 * (r * (self.theta_max / 2.0).sin()).min(1.0).asin(),
+        };
+        let phi = y.atan2(x);
+        let frame = &self.frame;
+        let direction =
+            (frame.u * phi.cos() + frame.v * phi.sin()) * theta.sin() - frame.w * theta.cos();
+        Some(Ray::new(frame.origin, direction))
+    }
+
+    // Going by the angle a pixel spans in the center of the image circle, `None`
+    // outside the field of view
+    fn pixel_footprint(&self, p: Point3, height: u32) -> Option<f64> {
+        let to_p = p - self.frame.origin;
+        let distance = to_p.length();
+        if distance == 0.0 || (-(to_p * self.frame.w) / distance).acos() > self.theta_max {
+            return None;
+        }
+        let angle_per_radius = match self.mapping {
+            FisheyeMapping::Equidistant => self.theta_max,
+            FisheyeMapping::Equisolid => 2.0 * (self.theta_max / 2.0).sin(),
+        };
+        let radius_per_pixel = 2.0 * self.half_height / height.max(1) as f64;
+        Some(distance * angle_per_radius * radius_per_pixel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::Vec3;
+
+    fn angle_from_view(camera: &FisheyeCamera, s: f64, t: f64) -> f64 {
+        let ray = camera
+            .get_ray(s, t, &mut IndependentSampler::new(1))
+            .unwrap();
+        (ray.direction.unit() * Vec3::new(0.0, 0.0, -1.0))
+            .acos()
+            .to_degrees()
+    }
+
+    #[test]
+    fn test_mappings() {
+        let frame = || {
+            Frame::new(
+                Point3::zero(),
+                Point3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            )
+        };
+        let camera = FisheyeCamera::new(frame(), 180.0, FisheyeMapping::Equidistant, 2.0);
+        assert!(angle_from_view(&camera, 0.5, 0.5).abs() < 1e-6);
+        // the top edge is on the image circle, the left and right ones outside
+        assert!((angle_from_view(&camera, 0.5, 1.0) - 90.0).abs() < 1e-6);
+        assert!((angle_from_view(&camera, 0.5, 0.75) - 45.0).abs() < 1e-6);
+        assert!(camera
+            .get_ray(0.0, 0.5, &mut IndependentSampler::new(1))
+            .is_none());
+
+        let camera = FisheyeCamera::new(frame(), 180.0, FisheyeMapping::Equisolid, 1.0);
+        assert!((angle_from_view(&camera, 1.0, 0.5) - 90.0).abs() < 1e-6);
+        // halfway out covers less than half the angle
+        let expected = (2.0 * (0.5 * 45_f64.to_radians().sin()).asin()).to_degrees();
+        assert!((angle_from_view(&camera, 0.75, 0.5) - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_pixel_footprint() {
+        let frame = Frame::new(
+            Point3::zero(),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        // 300 degrees over the 300 pixels across the image circle, a degree per pixel
+        let camera = FisheyeCamera::new(frame, 300.0, FisheyeMapping::Equidistant, 1.0);
+        let footprint = |p: Point3| camera.pixel_footprint(p, 300);
+        let degree = 1_f64.to_radians();
+        assert!((footprint(Point3::new(0.0, 0.0, -2.0)).unwrap() - 2.0 * degree).abs() < 1e-12);
+        // the camera sees a little behind itself, but not straight back
+        assert!(
+            (footprint(Point3::new(1.0, 0.0, 0.5)).unwrap() - 1.25_f64.sqrt() * degree).abs()
+                < 1e-12
+        );
+        assert_eq!(footprint(Point3::new(0.0, 0.0, 1.0)), None);
+    }
+}
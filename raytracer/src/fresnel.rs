@@ -0,0 +1,98 @@
+// Refraction and the exact Fresnel reflectance of unpolarized light. `eta` is the
+// index of refraction on the far side of the surface over the one on the side of
+// the incident direction.
+use crate::{Color, Vec3};
+
+// Reflectance of an interface between dielectrics
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_theta_i = cos_theta_i.min(1.0);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+// Reflectance of a conductor with complex index of refraction `eta + i k`
+// relative to the outside, for the cosine of the angle of incidence
+pub fn fresnel_complex(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i.abs().min(1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let (eta2, k2) = (eta * eta, k * k);
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+    (r_s + r_p) / 2.0
+}
+
+pub fn fresnel_conductor(cos_theta_i: f64, eta: Color, k: Color) -> Color {
+    Color::new(
+        fresnel_complex(cos_theta_i, eta.x, k.x),
+        fresnel_complex(cos_theta_i, eta.y, k.y),
+        fresnel_complex(cos_theta_i, eta.z, k.z),
+    )
+}
+
+// Snell's law for the unit vector `wi` pointing away from the surface and the
+// normal on its side, `None` for total internal reflection
+pub fn refract(wi: Vec3, normal: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = normal * wi;
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-wi / eta + normal * (cos_theta_i / eta - cos_theta_t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresnel_dielectric() {
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-12);
+        // the same both ways through the interface
+        let wi = Vec3::new(0.6, 0.0, 0.8);
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let wt = refract(wi, normal, 1.5).unwrap();
+        assert!((wt.length() - 1.0).abs() < 1e-12);
+        assert!((wi.x - 1.5 * -wt.x).abs() < 1e-12);
+        let back = fresnel_dielectric(-wt.z, 1.0 / 1.5);
+        assert!((fresnel_dielectric(wi.z, 1.5) - back).abs() < 1e-12);
+        // beyond the critical angle from inside
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+        assert!(refract(Vec3::new(0.866, 0.0, 0.5), normal, 1.0 / 1.5).is_none());
+    }
+
+    #[test]
+    fn test_fresnel_complex() {
+        let (eta, k) = (0.2, 3.9);
+        let normal = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assert!((fresnel_complex(1.0, eta, k) - normal).abs() < 1e-12);
+        assert!((fresnel_complex(0.0, eta, k) - 1.0).abs() < 1e-12);
+        // without absorption it is the reflectance of a dielectric
+        let schlick_normal = (0.5_f64 / 2.5).powi(2);
+        assert!((fresnel_complex(1.0, 1.5, 0.0) - schlick_normal).abs() < 1e-12);
+        for i in 0..=10 {
+            let r = fresnel_complex(i as f64 / 10.0, eta, k);
+            assert!((0.0..=1.0).contains(&r));
+        }
+    }
+}
@@ -1,10 +1,19 @@
 use crate::{Material, Point3, Vec3};
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    // How `p` moves with `u` and `v`, along the surface. Normal and bump maps tilt the
+    // normal within the frame they make with the outward normal.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub front: bool,
+    // of the ray that hit
+    pub wavelength: Option<f64>,
     pub material: Arc<dyn Material>,
 }
@@ -1,27 +1,56 @@
+use crate::aabb::Aabb;
 #[allow(unused_imports)]
 use crate::{HitRecord, Point3, Ray, Vec3};
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn bounding_box(&self) -> Aabb;
+
+    // Lights are sampled for direct lighting by the directions towards them from
+    // `origin`, with `pdf_value` the density of those in solid angle
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    fn sample_direction(&self, _origin: Point3, _u: (f64, f64)) -> Option<Vec3> {
+        None
+    }
+
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
 }
 
 pub struct HittableList {
     pub hittables: Vec<Box<dyn Hittable>>,
 }
 
+impl HittableList {
+    pub fn lights(&self) -> Vec<usize> {
+        (0..self.hittables.len())
+            .filter(|&i| self.hittables[i].is_light())
+            .collect()
+    }
+}
+
 impl Hittable for HittableList {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let mut t_closest = t_max;
         let mut hit_record: Option<HitRecord> = None;
         for hittable in &self.hittables {
-            match hittable.hit(&ray, t_min, t_closest) {
-                Some(hr) => {
-                    t_closest = hr.t;
-                    hit_record = Some(hr);
-                }
-                None => {}
+            if let Some(hr) = hittable.hit(ray, t_min, t_closest) {
+                t_closest = hr.t;
+                hit_record = Some(hr);
             }
         }
         hit_record
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.hittables
+            .iter()
+            .fold(Aabb::empty(), |bounds, hittable| {
+                bounds.union(&hittable.bounding_box())
+            })
+    }
 }
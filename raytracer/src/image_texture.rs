@@ -0,0 +1,82 @@
+use crate::{texture::Texture, Color, Point3};
+
+// A picture over the texture coordinates, v going up it, repeating outside [0, 1]²
+// and blended between the four nearest pixels
+pub struct ImageTexture {
+    pub width: usize,
+    pub height: usize,
+    // rows from the top
+    pub pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // Colors are taken to be stored with gamma 2 like the renders, `linear` reads
+    // normal maps and other data as they are
+    pub fn open(path: &str, linear: bool) -> Result<Self, String> {
+        let image = image::open(path)
+            .map_err(|e| format!("cannot read texture {}: {}", path, e))?
+            .to_rgb8();
+        let (width, height) = image.dimensions();
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                let color = Color::new(p.0[0] as f64, p.0[1] as f64, p.0[2] as f64) / 255.0;
+                if linear {
+                    color
+                } else {
+                    Color::elemul(color, color)
+                }
+            })
+            .collect();
+        Ok(Self::new(width as usize, height as usize, pixels))
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Color {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        // pixel centers are at half coordinates
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = self.pixel(x0, y0) * (1.0 - fx) + self.pixel(x0 + 1, y0) * fx;
+        let bottom = self.pixel(x0, y0 + 1) * (1.0 - fx) + self.pixel(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bilinear() {
+        let (black, white) = (Color::zero(), Color::ones());
+        let texture = ImageTexture::new(2, 2, vec![white, black, black, black]);
+        let p = Point3::zero();
+        // the centers of the pixels, the white one on the top left
+        assert_eq!(texture.value(0.25, 0.75, p), white);
+        assert_eq!(texture.value(0.75, 0.25, p), black);
+        assert!((texture.value(0.5, 0.75, p) - white * 0.5).length() < 1e-12);
+        assert!((texture.value(0.5, 0.5, p) - white * 0.25).length() < 1e-12);
+        // and repeated past the edges
+        assert!((texture.value(1.25, -0.25, p) - white).length() < 1e-12);
+        assert!((texture.value(0.0, 0.75, p) - white * 0.5).length() < 1e-12);
+    }
+}
@@ -0,0 +1,491 @@
+use crate::rgb_spectrum::RgbSpectrum;
+use crate::sampled_spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::sampler::Sampler;
+use crate::scatter_record::ScatterRecord;
+use crate::scene::Scene;
+use crate::{Color, HitRecord, Ray, Vec3};
+use std::str::FromStr;
+
+// How the color of a camera ray is computed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Integrator {
+    // recursive path tracing, as in the book
+    Path,
+    // path tracing that also samples the lights, combining both ways of
+    // reaching a light by multiple importance sampling
+    Mis,
+    // `Mis` carrying a few wavelengths per path instead of RGB, with colors turned
+    // into smooth spectra, so that saturated colors and spectral lights mix right
+    Spectral,
+    // visualize shading normals, useful to debug geometry
+    Normal,
+}
+
+impl Integrator {
+    pub fn ray_color(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        depth: u32,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        match self {
+            Integrator::Path => path_color(scene, ray, depth, sampler),
+            Integrator::Mis => mis_color(scene, ray, depth, sampler),
+            Integrator::Spectral => spectral_color(scene, ray, depth, sampler),
+            Integrator::Normal => match scene.hit(ray, 1e-5, f64::INFINITY) {
+                Some(rec) => (rec.normal + Vec3::ones()) * 0.5,
+                None => Color::zero(),
+            },
+        }
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "path" => Ok(Integrator::Path),
+            "mis" => Ok(Integrator::Mis),
+            "spectral" => Ok(Integrator::Spectral),
+            "normal" => Ok(Integrator::Normal),
+            _ => Err(format!(
+                "unknown integrator `{}`, expected one of: path, mis, spectral, normal",
+                s
+            )),
+        }
+    }
+}
+
+fn path_color(scene: &Scene, ray: &Ray, depth: u32, sampler: &mut dyn Sampler) -> Color {
+    if depth == 0 {
+        return Color::zero();
+    }
+    let rec_option = scene.hit(ray, 1e-5, f64::INFINITY);
+    match rec_option {
+        Some(rec) => {
+            let (ray, rec, transmittance) = match walk_medium(scene, ray.clone(), rec, sampler) {
+                Some(walk) => walk,
+                None => return Color::zero(),
+            };
+            let ray = &ray;
+            let material = rec.material.clone();
+            let emitted = material.emitted(rec.u, rec.v, rec.p);
+            let color = match material.scatter(ray, rec, sampler) {
+                Some(ScatterRecord::Specular {
+                    mut specular_ray,
+                    attenuation,
+                }) => {
+                    // the whole path sees the scene at the time of the camera ray
+                    specular_ray.time = ray.time;
+                    specular_ray.wavelength = specular_ray.wavelength.or(ray.wavelength);
+                    emitted
+                        + Vec3::elemul(
+                            path_color(scene, &specular_ray, depth - 1, sampler),
+                            attenuation,
+                        )
+                }
+                Some(ScatterRecord::Diffuse {
+                    mut scattered,
+                    attenuation,
+                    ..
+                }) => {
+                    scattered.time = ray.time;
+                    scattered.wavelength = scattered.wavelength.or(ray.wavelength);
+                    emitted
+                        + Vec3::elemul(
+                            path_color(scene, &scattered, depth - 1, sampler),
+                            attenuation,
+                        )
+                }
+                None => emitted,
+            };
+            Vec3::elemul(color, transmittance)
+        }
+        None => scene.background.color(ray),
+    }
+}
+
+fn mis_color(scene: &Scene, ray: &Ray, depth: u32, sampler: &mut dyn Sampler) -> Color {
+    let mut color = Color::zero();
+    let mut throughput = Color::ones();
+    let mut ray = ray.clone();
+    // density of the scattering that sent `ray`, `None` from the camera or a specular surface
+    let mut scatter_pdf = None;
+    for bounce in 0..depth {
+        let rec = match scene.hit(&ray, 1e-5, f64::INFINITY) {
+            Some(rec) => rec,
+            None => {
+                color += Vec3::elemul(throughput, scene.background.color(&ray));
+                break;
+            }
+        };
+        let rec = match walk_medium(scene, ray.clone(), rec, sampler) {
+            Some((walked, rec, transmittance)) => {
+                if walked != ray {
+                    scatter_pdf = None;
+                }
+                ray = walked;
+                throughput = Vec3::elemul(throughput, transmittance);
+                rec
+            }
+            None => break,
+        };
+        let material = rec.material.clone();
+        let emitted = material.emitted(rec.u, rec.v, rec.p);
+        let weight = match scatter_pdf {
+            Some(pdf) => power_heuristic(pdf, scene.light_pdf(ray.origin, ray.direction)),
+            None => 1.0,
+        };
+        color += Vec3::elemul(throughput, emitted) * weight;
+        // the light found from here is one bounce further
+        if bounce + 1 < depth {
+            let wo = -ray.direction.unit();
+            color += Vec3::elemul(throughput, direct_light(scene, &rec, wo, ray.time, sampler));
+        }
+        let (time, wavelength) = (ray.time, ray.wavelength);
+        let (scattered, attenuation) = match material.scatter(&ray, rec, sampler) {
+            Some(ScatterRecord::Specular {
+                specular_ray,
+                attenuation,
+            }) => {
+                scatter_pdf = None;
+                (specular_ray, attenuation)
+            }
+            Some(ScatterRecord::Diffuse {
+                scattered,
+                attenuation,
+                pdf,
+            }) => {
+                scatter_pdf = Some(pdf);
+                (scattered, attenuation)
+            }
+            None => break,
+        };
+        throughput = Vec3::elemul(throughput, attenuation);
+        ray = Ray {
+            time,
+            wavelength: scattered.wavelength.or(wavelength),
+            ..scattered
+        };
+    }
+    color
+}
+
+fn spectral_color(scene: &Scene, ray: &Ray, depth: u32, sampler: &mut dyn Sampler) -> Color {
+    let mut lambda = SampledWavelengths::sample_visible(sampler.get_1d());
+    let mut radiance = SampledSpectrum::splat(0.0);
+    let mut throughput = SampledSpectrum::splat(1.0);
+    // dispersive materials refract by the index at the hero wavelength
+    let mut ray = Ray {
+        wavelength: Some(lambda.hero()),
+        ..ray.clone()
+    };
+    let mut scatter_pdf = None;
+    for bounce in 0..depth {
+        let rec = match scene.hit(&ray, 1e-5, f64::INFINITY) {
+            Some(rec) => rec,
+            None => {
+                let background = RgbSpectrum::new(scene.background.color(&ray));
+                radiance += throughput * background.sample(&lambda);
+                break;
+            }
+        };
+        let rec = match walk_medium(scene, ray.clone(), rec, sampler) {
+            Some((walked, rec, transmittance)) => {
+                if walked != ray {
+                    scatter_pdf = None;
+                }
+                ray = walked;
+                throughput *= RgbSpectrum::new(transmittance).sample(&lambda);
+                rec
+            }
+            None => break,
+        };
+        let material = rec.material.clone();
+        let emitted = material.emitted_spectrum(rec.u, rec.v, rec.p, &lambda);
+        let weight = match scatter_pdf {
+            Some(pdf) => power_heuristic(pdf, scene.light_pdf(ray.origin, ray.direction)),
+            None => 1.0,
+        };
+        radiance += throughput * emitted * weight;
+        if material.is_dispersive() {
+            lambda.terminate_secondary();
+        }
+        if bounce + 1 < depth {
+            let wo = -ray.direction.unit();
+            if let Some((light, f)) = sample_direct_light(scene, &rec, wo, ray.time, sampler) {
+                let emitted = light
+                    .material
+                    .emitted_spectrum(light.u, light.v, light.p, &lambda);
+                radiance += throughput * RgbSpectrum::new(f).sample(&lambda) * emitted;
+            }
+        }
+        let (scattered, attenuation) = match material.scatter(&ray, rec, sampler) {
+            Some(ScatterRecord::Specular {
+                specular_ray,
+                attenuation,
+            }) => {
+                scatter_pdf = None;
+                (specular_ray, attenuation)
+            }
+            Some(ScatterRecord::Diffuse {
+                scattered,
+                attenuation,
+                pdf,
+            }) => {
+                scatter_pdf = Some(pdf);
+                (scattered, attenuation)
+            }
+            None => break,
+        };
+        throughput *= RgbSpectrum::new(attenuation).sample(&lambda);
+        if throughput.is_black() {
+            break;
+        }
+        ray = Ray {
+            time: ray.time,
+            wavelength: Some(lambda.hero()),
+            ..scattered
+        };
+    }
+    lambda.color_of(radiance)
+}
+
+// the scatterings inside a medium before a walk is given up as lost
+const MAX_MEDIUM_STEPS: usize = 256;
+
+// A ray reaching a surface with a medium from inside went through it, scattering any
+// number of times on the way. The ray and the hit the walk ends with, with its weight,
+// `None` if it never gets out.
+fn walk_medium(
+    scene: &Scene,
+    ray: Ray,
+    rec: HitRecord,
+    sampler: &mut dyn Sampler,
+) -> Option<(Ray, HitRecord, Color)> {
+    let (mut ray, mut rec, mut weight) = (ray, rec, Color::ones());
+    for _ in 0..MAX_MEDIUM_STEPS {
+        let medium = match rec.material.medium() {
+            Some(medium) if !rec.front => medium,
+            _ => return Some((ray, rec, weight)),
+        };
+        let length = ray.direction.length();
+        let (t, w) = medium.sample_distance(rec.t * length, sampler.get_2d());
+        weight = Vec3::elemul(weight, w);
+        let t = match t {
+            Some(t) => t,
+            None => return Some((ray, rec, weight)),
+        };
+        let direction = medium.sample_phase(ray.direction / length, sampler.get_2d());
+        ray = Ray {
+            origin: ray.at(t / length),
+            direction,
+            ..ray
+        };
+        rec = scene.hit(&ray, 1e-5, f64::INFINITY)?;
+    }
+    None
+}
+
+// The light arriving at `rec` straight from a light that the scene samples,
+// weighted against the chance of scattering towards it
+fn direct_light(
+    scene: &Scene,
+    rec: &HitRecord,
+    wo: Vec3,
+    time: f64,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    match sample_direct_light(scene, rec, wo, time, sampler) {
+        Some((light, f)) => {
+            let emitted = light.material.emitted(light.u, light.v, light.p);
+            Vec3::elemul(f, emitted)
+        }
+        None => Color::zero(),
+    }
+}
+
+// The point a sampled light direction from `rec` hits, with the BSDF towards it
+// weighted for MIS and divided by the density of the direction
+fn sample_direct_light(
+    scene: &Scene,
+    rec: &HitRecord,
+    wo: Vec3,
+    time: f64,
+    sampler: &mut dyn Sampler,
+) -> Option<(HitRecord, Color)> {
+    let wi = scene.sample_light(rec.p, sampler)?.unit();
+    let f = rec.material.eval(rec, wo, wi);
+    let light_pdf = scene.light_pdf(rec.p, wi);
+    if f == Color::zero() || light_pdf == 0.0 {
+        return None;
+    }
+    let shadow_ray = Ray {
+        time,
+        ..Ray::new(rec.p, wi)
+    };
+    let light = scene.hit(&shadow_ray, 1e-5, f64::INFINITY)?;
+    let weight = power_heuristic(light_pdf, rec.material.pdf(rec, wo, wi));
+    Some((light, f * (weight / light_pdf)))
+}
+
+// Veach's power heuristic with an exponent of two, the weight of a sample drawn
+// with density `pdf` when `other_pdf` could also have drawn it
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conductor::{Conductor, ConductorPreset};
+    use crate::constant_texture::ConstantTexture;
+    use crate::dielectric::Dielectric;
+    use crate::diffuse_light::DiffuseLight;
+    use crate::lambertian::Lambertian;
+    use crate::light_spectrum::LightSpectrum;
+    use crate::medium::Medium;
+    use crate::microfacet::TrowbridgeReitz;
+    use crate::sampler::IndependentSampler;
+    use crate::scene::{example_scene, Background};
+    use crate::subsurface::Subsurface;
+    use crate::{Hittable, HittableList, Point3, Sphere};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_mis_matches_path() {
+        let hittables: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere {
+                center: Point3::new(0.0, -1000.0, 0.0),
+                radius: 1000.0,
+                material: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            }),
+            Box::new(Sphere {
+                center: Point3::new(2.0, 1.0, 0.0),
+                radius: 1.0,
+                material: Arc::new(Conductor::preset(
+                    ConductorPreset::Gold,
+                    TrowbridgeReitz::new(0.3, 0.3),
+                )),
+            }),
+            Box::new(Sphere {
+                center: Point3::new(-1.0, 4.0, 0.0),
+                radius: 1.0,
+                material: Arc::new(DiffuseLight::with_texture(Arc::new(ConstantTexture::new(
+                    Color::new(0.9, 0.9, 0.9),
+                )))),
+            }),
+        ];
+        let world = HittableList { hittables };
+        let scene = Scene {
+            lights: world.lights(),
+            world,
+            background: Background::Solid(Color::zero()),
+            ..example_scene(0)
+        };
+        assert_eq!(scene.lights, vec![2]);
+        let mut sampler = IndependentSampler::new(1);
+        let origin = Point3::new(0.0, 1.0, 6.0);
+        // onto the ground and onto the gold sphere
+        for &target in &[Point3::new(-0.5, 0.0, 0.0), Point3::new(1.3, 1.3, 0.8)] {
+            let ray = Ray::new(origin, target - origin);
+            let n = 100_000;
+            let (mut path, mut mis) = (Color::zero(), Color::zero());
+            for _ in 0..n {
+                path += Integrator::Path.ray_color(&scene, &ray, 5, &mut sampler);
+                mis += Integrator::Mis.ray_color(&scene, &ray, 5, &mut sampler);
+            }
+            let (path, mis) = (path / n as f64, mis / n as f64);
+            assert!(mis.x > 0.0);
+            assert!((path - mis).length() < 0.05 * mis.length());
+        }
+    }
+
+    #[test]
+    fn test_spectral_matches_mis() {
+        // gray surfaces under a white light look the same in RGB and in spectra
+        let light = DiffuseLight::with_spectrum(LightSpectrum::Measured {
+            samples: vec![[300.0, 0.9], [900.0, 0.9]],
+        });
+        let hittables: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere {
+                center: Point3::new(0.0, -1000.0, 0.0),
+                radius: 1000.0,
+                material: Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            }),
+            Box::new(Sphere {
+                center: Point3::new(-1.0, 4.0, 0.0),
+                radius: 1.0,
+                material: Arc::new(light),
+            }),
+        ];
+        let world = HittableList { hittables };
+        let scene = Scene {
+            lights: world.lights(),
+            world,
+            background: Background::Solid(Color::zero()),
+            ..example_scene(0)
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let origin = Point3::new(0.0, 1.0, 6.0);
+        let ray = Ray::new(origin, Point3::new(-0.5, 0.0, 0.0) - origin);
+        let n = 50_000;
+        let (mut mis, mut spectral) = (Color::zero(), Color::zero());
+        for _ in 0..n {
+            mis += Integrator::Mis.ray_color(&scene, &ray, 5, &mut sampler);
+            spectral += Integrator::Spectral.ray_color(&scene, &ray, 5, &mut sampler);
+        }
+        let (mis, spectral) = (mis / n as f64, spectral / n as f64);
+        assert!(mis.x > 0.0);
+        assert!((mis - spectral).length() < 0.03 * mis.length());
+    }
+
+    #[test]
+    fn test_subsurface_furnace() {
+        // a body that absorbs nothing gives back all the light around it
+        let medium = Medium::new(Color::zero(), Color::ones() * 3.0, 0.3);
+        let hittables: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere {
+            center: Point3::zero(),
+            radius: 1.0,
+            material: Arc::new(Subsurface::new(Dielectric::new(1.4), medium)),
+        })];
+        let world = HittableList { hittables };
+        let scene = Scene {
+            lights: world.lights(),
+            world,
+            background: Background::Solid(Color::ones() * 0.5),
+            ..example_scene(0)
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let origin = Point3::new(0.0, 0.0, 5.0);
+        let ray = Ray::new(origin, Point3::new(0.3, 0.2, 0.0) - origin);
+        for &integrator in &[Integrator::Path, Integrator::Mis, Integrator::Spectral] {
+            let n = 20_000;
+            let mut color = Color::zero();
+            for _ in 0..n {
+                color += integrator.ray_color(&scene, &ray, 50, &mut sampler);
+            }
+            let color = color / n as f64;
+            assert!(
+                (color - Color::ones() * 0.5).length() < 0.02,
+                "{:?} {:?}",
+                integrator,
+                color
+            );
+        }
+    }
+
+    #[test]
+    fn test_power_heuristic() {
+        assert_eq!(power_heuristic(1.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+        assert_eq!(power_heuristic(1.0, 1.0) + power_heuristic(1.0, 1.0), 1.0);
+    }
+}
@@ -0,0 +1,190 @@
+// Interpolation between keyframed values, for anything that can be added and scaled
+// like `f64` and `Vec3`. Frames may be fractional and keys unevenly spaced.
+
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum Interpolation {
+    // straight from key to key, with a kink at every key
+    Linear,
+    // a smooth curve through the keys, with tangents towards the neighbouring keys
+    CatmullRom,
+}
+
+pub fn catmull_rom() -> Interpolation {
+    Interpolation::CatmullRom
+}
+
+// A value of the scene file that is either constant, like `radius: 1`, or keyframed:
+// `radius: {keyframes: [{frame: 1, value: 1}, {frame: 24, value: 2}], interpolation: Linear}`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "AnimatedDescription<T>")]
+pub struct Animated<T> {
+    // sorted by frame, a single key for a constant
+    keys: Vec<(f64, T)>,
+    interpolation: Interpolation,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnimatedDescription<T> {
+    Constant(T),
+    Keyframed {
+        keyframes: Vec<KeyframeDescription<T>>,
+        #[serde(default = "catmull_rom")]
+        interpolation: Interpolation,
+    },
+}
+
+#[derive(Deserialize)]
+struct KeyframeDescription<T> {
+    frame: f64,
+    value: T,
+}
+
+impl<T> TryFrom<AnimatedDescription<T>> for Animated<T> {
+    type Error = String;
+
+    fn try_from(description: AnimatedDescription<T>) -> Result<Self, Self::Error> {
+        match description {
+            AnimatedDescription::Constant(value) => Ok(Self {
+                keys: vec![(0.0, value)],
+                interpolation: Interpolation::Linear,
+            }),
+            AnimatedDescription::Keyframed {
+                keyframes,
+                interpolation,
+            } => {
+                if keyframes.is_empty() {
+                    return Err(String::from("a keyframe track needs at least one keyframe"));
+                }
+                if keyframes
+                    .windows(2)
+                    .any(|pair| pair[0].frame >= pair[1].frame)
+                {
+                    return Err(String::from("keyframes must be in increasing frame order"));
+                }
+                Ok(Self {
+                    keys: keyframes
+                        .into_iter()
+                        .map(|key| (key.frame, key.value))
+                        .collect(),
+                    interpolation,
+                })
+            }
+        }
+    }
+}
+
+impl<T> Animated<T>
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    pub fn is_animated(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    pub fn at(&self, frame: f64) -> T {
+        interpolate(&self.keys, frame, self.interpolation).unwrap()
+    }
+}
+
+// The value at `frame` of the keys sorted by frame, holding the first and last
+// values outside of them. `None` without keys.
+pub fn interpolate<T>(keys: &[(f64, T)], frame: f64, interpolation: Interpolation) -> Option<T>
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    let (first, last) = (keys.first()?, keys.last()?);
+    if frame <= first.0 {
+        return Some(first.1);
+    }
+    if frame >= last.0 {
+        return Some(last.1);
+    }
+    // the segment from key i to key i + 1 contains the frame
+    let i = keys.partition_point(|key| key.0 <= frame) - 1;
+    let ((t1, p1), (t2, p2)) = (keys[i], keys[i + 1]);
+    let u = (frame - t1) / (t2 - t1);
+    Some(match interpolation {
+        Interpolation::Linear => p1 + (p2 - p1) * u,
+        Interpolation::CatmullRom => {
+            // Hermite segment with tangents scaled to its length, the end keys
+            // have only one neighbour to take the tangent from
+            let tangent = |before: usize, after: usize| {
+                let ((t0, p0), (t3, p3)) = (keys[before], keys[after]);
+                (p3 - p0) * ((t2 - t1) / (t3 - t0))
+            };
+            let m1 = tangent(i.saturating_sub(1), i + 1);
+            let m2 = tangent(i, (i + 2).min(keys.len() - 1));
+            let (u2, u3) = (u * u, u * u * u);
+            p1 * (2.0 * u3 - 3.0 * u2 + 1.0)
+                + m1 * (u3 - 2.0 * u2 + u)
+                + p2 * (3.0 * u2 - 2.0 * u3)
+                + m2 * (u3 - u2)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+
+    #[test]
+    fn test_linear() {
+        let keys = [(1.0, 0.0), (3.0, 4.0), (4.0, 2.0)];
+        let at = |frame| interpolate(&keys, frame, Interpolation::Linear).unwrap();
+        assert_eq!(at(0.0), 0.0);
+        assert_eq!(at(2.0), 2.0);
+        assert_eq!(at(3.5), 3.0);
+        assert_eq!(at(9.0), 2.0);
+        assert_eq!(interpolate::<f64>(&[], 1.0, Interpolation::Linear), None);
+    }
+
+    #[test]
+    fn test_catmull_rom() {
+        let keys = [
+            (0.0, Vec3::new(0.0, 0.0, 0.0)),
+            (1.0, Vec3::new(1.0, 1.0, 0.0)),
+            (2.0, Vec3::new(2.0, 0.0, 0.0)),
+            (4.0, Vec3::new(4.0, 0.0, 1.0)),
+        ];
+        let at = |frame| interpolate(&keys, frame, Interpolation::CatmullRom).unwrap();
+        // through every key
+        for &(frame, value) in &keys {
+            assert!((at(frame) - value).length() < 1e-12);
+        }
+        // rounding the corner at the top instead of stopping in it
+        assert!(at(0.9).y < 1.0 && at(1.1).y < 1.0);
+        assert!((at(1.0 + 1e-6) - at(1.0 - 1e-6)).length() < 1e-5);
+        // and smooth across keys, also where they are unevenly spaced
+        let velocity = |frame: f64| (at(frame + 1e-6) - at(frame - 1e-6)) / 2e-6;
+        assert!((velocity(2.0 - 1e-4) - velocity(2.0 + 1e-4)).length() < 1e-2);
+        // points on a line stay on it
+        let line = [(0.0, 0.0), (1.0, 2.0), (3.0, 6.0)];
+        let value = interpolate(&line, 2.0, Interpolation::CatmullRom).unwrap();
+        assert!((value - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_animated() {
+        let constant: Animated<f64> = serde_yaml::from_str("2.5").unwrap();
+        assert!(!constant.is_animated());
+        assert_eq!(constant.at(100.0), 2.5);
+        let track: Animated<Vec3> = serde_yaml::from_str(
+            "{interpolation: Linear, keyframes: [
+                {frame: 1, value: [0, 0, 0]}, {frame: 11, value: [10, 0, 0]}]}",
+        )
+        .unwrap();
+        assert!(track.is_animated());
+        assert_eq!(track.at(4.0), Vec3::new(3.0, 0.0, 0.0));
+        assert!(serde_yaml::from_str::<Animated<f64>>("{keyframes: []}").is_err());
+        assert!(serde_yaml::from_str::<Animated<f64>>(
+            "{keyframes: [{frame: 2, value: 1}, {frame: 1, value: 2}]}"
+        )
+        .is_err());
+    }
+}
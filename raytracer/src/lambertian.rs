@@ -1,25 +1,52 @@
-use crate::{Color, Ray, HitRecord, Material, ScatterRecord};
-use crate::utils::random_unit_vector;
-use rand::rngs::SmallRng;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::warp::{cosine_hemisphere_pdf, sample_cosine_hemisphere};
+use crate::{constant_texture::ConstantTexture, texture::Texture};
+use crate::{Color, HitRecord, Material, Ray, ScatterRecord, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
 
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Self {albedo}
+        Self::with_texture(Arc::new(ConstantTexture::new(albedo)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, hit_record: HitRecord, rng: &mut SmallRng)  -> Option<ScatterRecord> {
-        let scatter_dir = hit_record.normal + random_unit_vector(rng);
-        let scattered = Ray{origin: hit_record.p, direction: scatter_dir};
-        let attenuation = self.albedo;
-        return Some(ScatterRecord::Diffuse{
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        hit_record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let local = sample_cosine_hemisphere(sampler.get_2d());
+        let scatter_dir = Onb::from_hit(&hit_record).local(local);
+        let scattered = Ray::new(hit_record.p, scatter_dir);
+        let attenuation = self.albedo.value(hit_record.u, hit_record.v, hit_record.p);
+        Some(ScatterRecord::Diffuse {
             scattered,
             attenuation,
-        });
+            pdf: cosine_hemisphere_pdf(local.z),
+        })
+    }
+
+    fn eval(&self, hit_record: &HitRecord, _wo: Vec3, wi: Vec3) -> Color {
+        let cos_theta = wi * hit_record.normal;
+        if cos_theta <= 0.0 {
+            return Color::zero();
+        }
+        self.albedo.value(hit_record.u, hit_record.v, hit_record.p) * (cos_theta / PI)
     }
-}
\ No newline at end of file
+
+    fn pdf(&self, hit_record: &HitRecord, _wo: Vec3, wi: Vec3) -> f64 {
+        cosine_hemisphere_pdf(wi * hit_record.normal)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::sampled_spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::spectrum::{cie_xyz, xyz_to_balanced_rgb, CIE_Y_INTEGRAL, LAMBDA_MAX, LAMBDA_MIN};
+use crate::{Color, Vec3};
+use serde::Deserialize;
+
+// The spectral radiance of a light, wavelengths in nm
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "type")]
+pub enum LightSpectrum {
+    // Planck's law for `temperature` in kelvin, one at its peak times `scale`
+    Blackbody {
+        temperature: f64,
+        #[serde(default = "one")]
+        scale: f64,
+    },
+    // Linear between `[wavelength, value]` pairs sorted by wavelength, dark outside them
+    Measured {
+        samples: Vec<[f64; 2]>,
+    },
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+// the emitted radiance of a black body in W/(m² sr m)
+fn planck(lambda: f64, temperature: f64) -> f64 {
+    const C: f64 = 299792458.0;
+    const H: f64 = 6.62606957e-34;
+    const KB: f64 = 1.3806488e-23;
+    let l = lambda * 1e-9;
+    2.0 * H * C * C / (l.powi(5) * ((H * C / (l * KB * temperature)).exp() - 1.0))
+}
+
+impl LightSpectrum {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            LightSpectrum::Blackbody { temperature, scale } => {
+                if *temperature <= 0.0 {
+                    return Err(format!(
+                        "blackbody temperature must be positive, not {}",
+                        temperature
+                    ));
+                }
+                if *scale < 0.0 {
+                    return Err("blackbody scale must not be negative".to_string());
+                }
+            }
+            LightSpectrum::Measured { samples } => {
+                if samples.is_empty() {
+                    return Err("a measured spectrum needs samples".to_string());
+                }
+                if samples.windows(2).any(|pair| pair[0][0] >= pair[1][0]) {
+                    return Err("measured spectrum wavelengths must increase".to_string());
+                }
+                if samples.iter().any(|sample| sample[1] < 0.0) {
+                    return Err("measured spectrum values must not be negative".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn eval(&self, lambda: f64) -> f64 {
+        match self {
+            LightSpectrum::Blackbody { temperature, scale } => {
+                // Wien's displacement law gives the peak
+                let peak = 2.8977721e-3 / temperature * 1e9;
+                scale * planck(lambda, *temperature) / planck(peak, *temperature)
+            }
+            LightSpectrum::Measured { samples } => {
+                let i = samples.partition_point(|sample| sample[0] <= lambda);
+                if i == 0 || i == samples.len() {
+                    // a single sample is a spike nothing can sample
+                    return 0.0;
+                }
+                let ([l0, v0], [l1, v1]) = (samples[i - 1], samples[i]);
+                v0 + (v1 - v0) * (lambda - l0) / (l1 - l0)
+            }
+        }
+    }
+
+    pub fn sample(&self, lambda: &SampledWavelengths) -> SampledSpectrum {
+        SampledSpectrum::from_fn(lambda, |lambda| self.eval(lambda))
+    }
+
+    // The color of the light, for the integrators that work in RGB
+    pub fn to_rgb(&self) -> Color {
+        let steps = 470;
+        let h = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let mut xyz = Vec3::zero();
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + (i as f64 + 0.5) * h;
+            xyz += cie_xyz(lambda) * (self.eval(lambda) * h);
+        }
+        xyz_to_balanced_rgb(xyz / CIE_Y_INTEGRAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blackbody() {
+        let warm = LightSpectrum::Blackbody {
+            temperature: 2700.0,
+            scale: 1.0,
+        };
+        // the peak of a 2700 K body is in the infrared
+        assert!(warm.eval(830.0) < 1.0 && warm.eval(830.0) > warm.eval(600.0));
+        let rgb = warm.to_rgb();
+        assert!(rgb.x > rgb.y && rgb.y > rgb.z);
+        let hot = LightSpectrum::Blackbody {
+            temperature: 10000.0,
+            scale: 2.0,
+        };
+        let peak = 2.8977721e-3 / 10000.0 * 1e9;
+        assert!((hot.eval(peak) - 2.0).abs() < 1e-12);
+        let rgb = hot.to_rgb();
+        assert!(rgb.z > rgb.x);
+        assert!(LightSpectrum::Blackbody {
+            temperature: -1.0,
+            scale: 1.0
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_measured() {
+        let flat = LightSpectrum::Measured {
+            samples: vec![[300.0, 2.0], [900.0, 2.0]],
+        };
+        assert!((flat.to_rgb() - Color::ones() * 2.0).length() < 1e-4);
+        let ramp = LightSpectrum::Measured {
+            samples: vec![[400.0, 0.0], [500.0, 1.0], [600.0, 3.0]],
+        };
+        assert!(ramp.validate().is_ok());
+        assert_eq!(ramp.eval(399.0), 0.0);
+        assert!((ramp.eval(450.0) - 0.5).abs() < 1e-12);
+        assert!((ramp.eval(550.0) - 2.0).abs() < 1e-12);
+        assert_eq!(ramp.eval(601.0), 0.0);
+        let unsorted = LightSpectrum::Measured {
+            samples: vec![[500.0, 1.0], [400.0, 1.0]],
+        };
+        assert!(unsorted.validate().is_err());
+    }
+}
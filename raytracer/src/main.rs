@@ -1,79 +1,92 @@
 #![allow(clippy::float_cmp)]
 
+mod aabb;
+mod aperture;
+mod bump;
+mod bvh;
 mod camera;
+mod camera_animation;
+mod checker_texture;
+mod checkpoint;
+mod cli;
+mod coated;
+mod conductor;
+mod constant_texture;
+mod dielectric;
+mod diffuse_light;
+mod dispersion;
+mod displacement;
+mod distribution;
+mod equirectangular_camera;
+mod film;
+mod fisheye_camera;
+mod fresnel;
 mod hit_record;
 mod hittable;
+mod image_texture;
+mod integrator;
+mod keyframe;
 mod lambertian;
-mod dielectric;
-mod metal;
+mod light_spectrum;
 mod material;
+mod medium;
+mod microfacet;
+mod mix;
+mod moving_sphere;
+mod moving_triangle;
+mod mtl;
+mod noise_texture;
+mod obj;
+mod onb;
+mod orthographic_camera;
+mod perlin;
+mod perspective_camera;
+mod principled;
 mod ray;
+mod realistic_camera;
+mod render;
+mod rgb_spectrum;
+mod sampled_spectrum;
+mod sampler;
 mod scatter_record;
 mod scene;
+mod scene_file;
+mod spectrum;
 mod sphere;
+mod stereo_camera;
+mod subsurface;
+mod texture;
+mod thin_film;
+mod triangle;
+mod triangle_mesh;
 mod utils;
 mod vec3;
+mod warp;
 
+use aperture::ApertureShape;
 use camera::Camera;
+use checkpoint::Checkpoint;
+use cli::{Options, USAGE};
+use film::Film;
 pub use hit_record::HitRecord;
 pub use hittable::{Hittable, HittableList};
-use image::{ImageBuffer, Rgb, RgbImage};
-use indicatif::ProgressBar;
 use material::Material;
-use rand::{rngs::SmallRng, Rng, SeedableRng};
 pub use ray::Ray;
+use render::{report_tile_times, spiral_tiles, write_tile_times};
+use render::{CheckpointSettings, RenderSettings, RenderState, Renderer, StopConditions};
 use scatter_record::ScatterRecord;
 use scene::example_scene;
+use scene_file::load_scene_file;
 pub use sphere::Sphere;
-use std::sync::mpsc::channel;
 use std::sync::Arc;
-use threadpool::ThreadPool;
-use utils::{clamp3, random_in_hemisphere};
+use std::time::Duration;
+use stereo_camera::Stereo;
 pub use vec3::{Color, Point3, Vec3};
 
-fn gamma2_correct(color: Color, samples_per_pixel: u32) -> Color {
-    let scale = 1.0 / (samples_per_pixel as f64);
-    Color {
-        x: (color.x * scale).sqrt(),
-        y: (color.y * scale).sqrt(),
-        z: (color.z * scale).sqrt(),
-    }
-}
-
-fn ray_color(world: &HittableList, ray: &Ray, depth: u32, rng: &mut SmallRng) -> Color {
-    if depth == 0 {
-        return Color::zero();
-    }
-    let rec_option = world.hit(ray, 1e-5, f64::INFINITY);
-    let result = match rec_option {
-        Some(rec) => {
-            let material = rec.material.clone();
-            match material.scatter(ray, rec, rng) {
-                Some(ScatterRecord::Specular {
-                    specular_ray,
-                    attenuation,
-                }) => {
-                    Vec3::elemul(ray_color(world, &specular_ray, depth - 1, rng), attenuation)
-                }
-                Some(ScatterRecord::Diffuse {
-                    scattered,
-                    attenuation,
-                }) => {
-                    Vec3::elemul(ray_color(world, &scattered, depth - 1, rng), attenuation)
-                }
-                None => {
-                    Color::new(0.0, 0.0, 0.0)
-                }
-            }
-            // let target = rec.p + random_in_hemisphere(rec.normal, rng);
-            // ray_color(world, &Ray::new(rec.p, target - rec.p), depth - 1, rng) * 0.5
-        }
-        None => {
-            let t = 0.5 * (ray.direction.unit().y + 1.0);
-            Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
-        }
-    };
-    return clamp3(result);
+fn save_image(film: &Film, path: &str, options: &Options) {
+    film.to_image()
+        .save_with_format(path, options.format)
+        .unwrap();
 }
 
 fn is_ci() -> bool {
@@ -84,99 +97,264 @@ fn main() {
     // get environment variable CI, which is true for GitHub Action
     let is_ci = is_ci();
 
-    // jobs: split image into how many parts
-    // workers: maximum allowed concurrent running threads
-    let (n_jobs, n_workers): (usize, usize) = if is_ci { (32, 2) } else { (16, 2) };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = Options::parse(&args).unwrap_or_else(|err| {
+        eprintln!("error: {}\n\n{}", err, USAGE);
+        std::process::exit(2);
+    });
+    if options.help {
+        print!("{}", USAGE);
+        return;
+    }
 
-    println!(
-        "CI: {}, using {} jobs and {} workers",
-        is_ci, n_jobs, n_workers
-    );
+    // work-stealing pool, one worker per core unless told otherwise
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads.unwrap_or(0))
+        .build()
+        .expect("failed to build thread pool");
+    let n_workers = pool.current_num_threads();
 
-    // create a channel to send objects between threads
-    let (tx, rx) = channel();
-    let pool = ThreadPool::new(n_workers);
+    let resume = options.resume.as_ref().map(|path| {
+        Checkpoint::load(path).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        })
+    });
 
-    // Progress bar
-    let bar = ProgressBar::new(n_jobs as u64);
+    // a fixed default seed, so that the same command renders the same image;
+    // a resumed render has to continue with the seed it was started with
+    let seed = match (&resume, options.seed) {
+        (Some(checkpoint), Some(seed)) if checkpoint.seed != seed => {
+            eprintln!(
+                "error: the checkpoint was rendered with seed {}, not {}",
+                checkpoint.seed, seed
+            );
+            std::process::exit(2);
+        }
+        (Some(checkpoint), _) => checkpoint.seed,
+        (None, seed) => seed.unwrap_or(0),
+    };
+
+    let scene_file = options.scene.as_ref().map(|path| {
+        load_scene_file(path).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        })
+    });
+    // a resumed animation continues at the frame it was saved in, a frame outside
+    // of --frames fails the fingerprint check below
+    let first_frame = match &resume {
+        Some(checkpoint) => checkpoint.frame.filter(
+            |frame| matches!(options.frames, Some((first, last)) if (first..=last).contains(frame)),
+        ),
+        None => options.frames.map(|(first, _)| first),
+    };
+    let mut scene = match &scene_file {
+        Some(file) => file.scene_at(first_frame),
+        None => example_scene(seed),
+    };
 
-    // Image
-    let height = 800;
-    let width = 1200;
+    // Camera
+    let settings = &mut scene.camera;
+    settings.lookfrom = options.lookfrom.unwrap_or(settings.lookfrom);
+    settings.lookat = options.lookat.unwrap_or(settings.lookat);
+    settings.vup = options.vup.unwrap_or(settings.vup);
+    settings.vfov = options.vfov.unwrap_or(settings.vfov);
+    settings.aperture = options.aperture.unwrap_or(settings.aperture);
+    settings.focus_dist = options.focus_dist.unwrap_or(settings.focus_dist);
+    if let Some(blades) = options.blades {
+        settings.aperture_shape = ApertureShape::Polygon {
+            blades,
+            rotation: options.blade_rotation.unwrap_or(0.0),
+        };
+    }
+    if let Some(path) = &options.aperture_mask {
+        settings.aperture_shape = ApertureShape::Image { path: path.clone() };
+    }
+    settings.cat_eye = options.cat_eye.unwrap_or(settings.cat_eye);
+    if let Some(layout) = options.stereo {
+        let mut stereo = settings.stereo.unwrap_or_else(|| Stereo::new(layout));
+        stereo.layout = layout;
+        stereo.mode = options.stereo_mode.unwrap_or(stereo.mode);
+        stereo.interocular = options.interocular.unwrap_or(stereo.interocular);
+        stereo.convergence = options.convergence.or(stereo.convergence);
+        settings.stereo = Some(stereo);
+    }
+
+    // Image, the scene's aspect ratio is the one of each view of a stereo pair
+    let view_aspect_ratio = scene.aspect_ratio;
+    let settings = &scene.camera;
+    let (width, height) = options.resolution(settings.stereo.map_or(view_aspect_ratio, |stereo| {
+        stereo.image_aspect_ratio(view_aspect_ratio)
+    }));
     let aspect_ratio = (width as f64) / (height as f64);
+    let camera_settings = settings.clone();
+    // The scene file to build every frame from when its objects or materials are keyframed,
+    // or when it has displaced meshes that are tessellated for the camera of every frame
+    let animated_scene = matches!(&scene_file, Some(file) if file.is_animated());
+    let scene_file_per_frame = scene_file.as_ref().filter(|_| {
+        animated_scene || (camera_settings.animation.is_some() && !scene.displaced.is_empty())
+    });
+    if options.frames.is_some() && camera_settings.animation.is_none() && !animated_scene {
+        eprintln!("warning: the scene has no animation, all frames will be the same");
+    }
+    // the camera of a frame of the animation, or of the still image
+    let camera_at = |frame: Option<u32>| -> Arc<dyn Camera> {
+        let settings = match (frame, &camera_settings.animation) {
+            (Some(frame), Some(animation)) => animation.apply(&camera_settings, frame as f64),
+            _ => camera_settings.clone(),
+        };
+        Arc::from(settings.build(aspect_ratio).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }))
+    };
+    let frames: Vec<Option<u32>> = match options.frames {
+        Some((first, last)) => (first_frame.unwrap_or(first)..=last).map(Some).collect(),
+        None => vec![None],
+    };
 
+    // the BVH is shared by all frames unless the scene is built for every frame
+    scene.tessellate(camera_at(frames[0]).as_ref(), height);
+    scene.build_bvh();
     // use Arc to pass one instance of World to multiple threads
-    let world = Arc::new(example_scene());
-
-    // Camera
-    let lookfrom = Vec3::new(13.0, 2.0, 3.0);
-    let lookat = Vec3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0;
-    let aperture = 0.1;
-    let camera = Arc::new(Camera::new(lookfrom, lookat, vup, 20.0, aspect_ratio, aperture, dist_to_focus));
+    let scene = Arc::new(scene);
 
     // Render
-    let samples_per_pixel = 500;
-    let max_depth = 50;
-
-    println!("Start");
-
-    for i in 0..n_jobs {
-        let tx = tx.clone();
-        let world_ptr = world.clone();
-        let camera_ptr = camera.clone();
-        pool.execute(move || {
-            // here, we render some of the rows of image in one thread
-            let mut rng = SmallRng::from_entropy();
-            let row_begin = height as usize * i / n_jobs;
-            let row_end = height as usize * (i + 1) / n_jobs;
-            let render_height = row_end - row_begin;
-            // img is a partial image
-            let mut img: RgbImage = ImageBuffer::new(width, render_height as u32);
-            for x in 0..width {
-                // img_y is the row in partial rendered image
-                // y is real position in final image
-                for (img_y, y) in (row_begin..row_end).enumerate() {
-                    let y = y as u32;
-                    let pixel = img.get_pixel_mut(x, img_y as u32);
-                    let mut color = Color::zero();
-                    for _ in 0..samples_per_pixel {
-                        let target_x: f64 = x as f64 + rng.gen_range(0.0..1.0);
-                        let target_y: f64 = y as f64 + rng.gen_range(0.0..1.0);
-                        let u = target_x / (width as f64 - 1.0);
-                        let v = target_y / (height as f64 - 1.0);
-                        let ray = camera_ptr.get_ray(u, v, &mut rng);
-                        color += ray_color(&world_ptr, &ray, max_depth, &mut rng);
+    let settings = RenderSettings {
+        width,
+        height,
+        samples_per_pixel: options.samples_per_pixel,
+        max_depth: options.max_depth,
+        integrator: options.integrator,
+        sampler: options.sampler,
+        seed,
+    };
+    let stop = StopConditions {
+        progressive: options.progressive,
+        time_limit: options.time_limit.map(Duration::from_secs_f64),
+        noise_threshold: options.noise_threshold,
+        adaptive: options.adaptive,
+        min_spp: options.min_spp,
+    };
+    let tiles = spiral_tiles(width, height, options.tile_size);
+
+    // everything that changes which samples are drawn, the time limit may differ between runs
+    let fingerprint = checkpoint::fingerprint(&format!(
+        "{:?} {:?} {:?} {:?} {} {} {:?} {} {}",
+        options.scene,
+        options.frames,
+        camera_settings,
+        settings,
+        options.tile_size,
+        stop.progressive,
+        stop.noise_threshold,
+        stop.adaptive,
+        stop.min_spp
+    ));
+    let mut resumed_state = match resume {
+        Some(checkpoint) => {
+            if checkpoint.fingerprint != fingerprint {
+                eprintln!("error: the checkpoint was written with different render settings");
+                std::process::exit(2);
+            }
+            if let Err(err) = checkpoint.state.validate(width, height, tiles.len()) {
+                eprintln!("error: {}", err);
+                std::process::exit(2);
+            }
+            if let Some(frame) = checkpoint.frame {
+                println!("Resuming frame {}", frame);
+            }
+            println!(
+                "Resuming pass {} at {} spp",
+                checkpoint.state.pass + 1,
+                checkpoint.state.spp
+            );
+            Some(checkpoint.state)
+        }
+        None => None,
+    };
+
+    println!(
+        "CI: {}, rendering {} tiles on {} workers",
+        is_ci,
+        tiles.len(),
+        n_workers
+    );
+
+    let mut renderer = Renderer {
+        pool,
+        scene,
+        camera: camera_at(frames[0]),
+        settings,
+        sampler: Arc::from(settings.sampler.build(seed, settings.samples_per_pixel)),
+        tiles,
+        checkpoint: options
+            .checkpoint
+            .clone()
+            .or_else(|| options.resume.clone())
+            .map(|path| CheckpointSettings {
+                path,
+                interval: Duration::from_secs_f64(options.checkpoint_interval),
+                seed,
+                fingerprint,
+                frame: None,
+            }),
+    };
+
+    for frame in frames {
+        let output = match frame {
+            Some(frame) => {
+                println!("Frame {}", frame);
+                if let Some(checkpoint) = &mut renderer.checkpoint {
+                    checkpoint.frame = Some(frame);
+                }
+                renderer.camera = camera_at(Some(frame));
+                if let Some(file) = scene_file_per_frame {
+                    if Some(frame) != first_frame {
+                        let mut scene = file.scene_at(Some(frame));
+                        scene.tessellate(renderer.camera.as_ref(), height);
+                        scene.build_bvh();
+                        renderer.scene = Arc::new(scene);
                     }
-                    color = gamma2_correct(color, samples_per_pixel) * 255.999;
-                    *pixel = Rgb([color.x as u8, color.y as u8, color.z as u8]);
                 }
+                options.frame_output(frame)
             }
-            // send row range and rendered image to main thread
-            tx.send((row_begin..row_end, img))
-                .expect("failed to send result");
-        });
-    }
+            None => options.output.clone(),
+        };
+        let state = resumed_state
+            .take()
+            .unwrap_or_else(|| RenderState::new(width, height));
 
-    let mut result: RgbImage = ImageBuffer::new(width, height);
+        println!("Start");
 
-    println!("Wait for result..");
-
-    for (rows, data) in rx.iter().take(n_jobs) {
-        // idx is the corrsponding row in partial-rendered image
-        for (idx, row) in rows.enumerate() {
-            for col in 0..width {
-                let row = row as u32;
-                let idx = idx as u32;
-                // *result.get_pixel_mut(col, row) = *data.get_pixel(col, idx);
-                *result.get_pixel_mut(col, height - 1 - row) = *data.get_pixel(col, idx);
-                // Be consistent with the book
+        let (film, tile_results, reason) = renderer.render(state, &stop, |film| {
+            // intermediate results go to the same file, so it always shows the latest pass
+            if options.progressive {
+                save_image(film, &output, &options);
             }
+        });
+        println!("Stopped: {:?}", reason);
+        println!(
+            "{:.1} samples per pixel on average",
+            film.total_samples() as f64 / (width as f64 * height as f64)
+        );
+
+        // with --frames, the paths have a run of # for the frame number
+        let frame_path = |path: &String| match frame {
+            Some(frame) => cli::frame_path(path, frame),
+            None => path.clone(),
+        };
+        report_tile_times(&tile_results, n_workers);
+        if let Some(path) = options.tile_stats.as_ref().map(frame_path) {
+            write_tile_times(&path, &tile_results).expect("failed to write tile stats");
         }
-        bar.inc(1);
-    }
 
-    result.save("output/test.png").unwrap();
-    bar.finish();
+        save_image(&film, &output, &options);
+        if let Some(path) = options.heatmap.as_ref().map(frame_path) {
+            film.sample_heatmap()
+                .save(path)
+                .expect("failed to write heat map");
+        }
+    }
 }
@@ -1,6 +1,58 @@
-use crate::{HitRecord, Ray, ScatterRecord};
-use rand::rngs::SmallRng;
+use crate::medium::Medium;
+use crate::rgb_spectrum::RgbSpectrum;
+use crate::sampled_spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::sampler::Sampler;
+use crate::{Color, HitRecord, Point3, Ray, ScatterRecord, Vec3};
 
 pub trait Material: Send + Sync {
-    fn scatter(&self, ray_in: &Ray, hit_record: HitRecord, rng: &mut SmallRng) -> Option<ScatterRecord>;
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord>;
+
+    fn emitted(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        Color::zero()
+    }
+
+    // the emitted radiance at the wavelengths of a spectral path, by default a
+    // smooth spectrum of the color
+    fn emitted_spectrum(
+        &self,
+        u: f64,
+        v: f64,
+        p: Point3,
+        lambda: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        RgbSpectrum::new(self.emitted(u, v, p)).sample(lambda)
+    }
+
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // whether scattering depends on the wavelength in a way a color cannot carry, like
+    // refraction by a dispersive index or the colors of a thin film, so that a spectral
+    // path carries on with its hero wavelength only
+    fn is_dispersive(&self) -> bool {
+        false
+    }
+
+    // what fills a closed surface of this material, for the light that goes in to
+    // wander through before it reaches the surface again from inside
+    fn medium(&self) -> Option<Medium> {
+        None
+    }
+
+    // The BSDF times the cosine of `wi` with the normal, for unit `wo` towards where the
+    // ray came from and `wi` towards a light. Zero for materials that only scatter specularly.
+    fn eval(&self, _hit_record: &HitRecord, _wo: Vec3, _wi: Vec3) -> Color {
+        Color::zero()
+    }
+
+    // The density in solid angle of `scatter` sending a ray from `wo` towards `wi`
+    fn pdf(&self, _hit_record: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
 }
@@ -0,0 +1,139 @@
+use crate::onb::Onb;
+use crate::warp::sample_henyey_greenstein;
+use crate::{Color, Vec3};
+
+// The same inside all of a closed surface, like the marble of a statue or the wax of
+// a candle. Per unit length, light going through is scattered by `sigma_s` and
+// absorbed by `sigma_a`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Medium {
+    pub sigma_a: Color,
+    pub sigma_s: Color,
+    // of the Henyey-Greenstein phase function, 0 scatters the same in all directions
+    pub g: f64,
+}
+
+impl Medium {
+    pub fn new(sigma_a: Color, sigma_s: Color, g: f64) -> Self {
+        let positive = |c: Color| Color::new(c.x.max(0.0), c.y.max(0.0), c.z.max(0.0));
+        Self {
+            sigma_a: positive(sigma_a),
+            sigma_s: positive(sigma_s),
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    // A medium that looks `albedo` once light scattered its way out of a thick body of
+    // it, going `mean_free_path` on average between the scatterings (Chiang et al. 2016)
+    pub fn from_albedo(albedo: Color, mean_free_path: Color, g: f64) -> Self {
+        let single = |a: f64| {
+            let a = a.clamp(0.0, 1.0);
+            1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+        };
+        let sigma_t = |d: f64| 1.0 / d.max(1e-9);
+        let sigma_t = Color::new(
+            sigma_t(mean_free_path.x),
+            sigma_t(mean_free_path.y),
+            sigma_t(mean_free_path.z),
+        );
+        let sigma_s = Vec3::elemul(
+            sigma_t,
+            Color::new(single(albedo.x), single(albedo.y), single(albedo.z)),
+        );
+        Self::new(sigma_t - sigma_s, sigma_s, g)
+    }
+
+    // Where light going `distance` through the medium first scatters, `None` if it
+    // gets through, with the weight of the sample either way. One of the channels
+    // picks the distance, weighted against the others picking it.
+    pub fn sample_distance(&self, distance: f64, u: (f64, f64)) -> (Option<f64>, Color) {
+        let sigma_t = self.sigma_a + self.sigma_s;
+        let channel = ((u.0 * 3.0) as usize).min(2);
+        let t = -(1.0 - u.1).ln() / [sigma_t.x, sigma_t.y, sigma_t.z][channel];
+        let transmittance = |t: f64| {
+            let a = sigma_t * -t;
+            Color::new(a.x.exp(), a.y.exp(), a.z.exp())
+        };
+        // the density of the sample, the mean over the channels that could have picked it
+        let weight = |f: Color, pdf: Color| {
+            let pdf = (pdf.x + pdf.y + pdf.z) / 3.0;
+            if pdf == 0.0 {
+                Color::zero()
+            } else {
+                f / pdf
+            }
+        };
+        if t >= distance {
+            let transmittance = transmittance(distance);
+            return (None, weight(transmittance, transmittance));
+        }
+        let transmittance = transmittance(t);
+        (
+            Some(t),
+            weight(
+                Vec3::elemul(self.sigma_s, transmittance),
+                Vec3::elemul(sigma_t, transmittance),
+            ),
+        )
+    }
+
+    // The direction light going along unit `direction` scatters into, the phase
+    // function is its own density
+    pub fn sample_phase(&self, direction: Vec3, u: (f64, f64)) -> Vec3 {
+        Onb::from_w(direction).local(sample_henyey_greenstein(u, self.g))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_sample_distance() {
+        let medium = Medium::new(Color::new(0.1, 0.5, 1.0), Color::new(2.0, 1.0, 0.5), 0.0);
+        let sigma_t = medium.sigma_a + medium.sigma_s;
+        let mut rng = SmallRng::seed_from_u64(1);
+        let distance = 0.7;
+        let n = 200_000;
+        let (mut passed, mut scattered) = (Color::zero(), Color::zero());
+        for _ in 0..n {
+            match medium.sample_distance(distance, (rng.gen(), rng.gen())) {
+                (None, weight) => passed += weight,
+                (Some(t), weight) => {
+                    assert!(t < distance);
+                    scattered += weight;
+                }
+            }
+        }
+        let (passed, scattered) = (passed / n as f64, scattered / n as f64);
+        for &(passed, scattered, sigma_s, sigma_t) in &[
+            (passed.x, scattered.x, medium.sigma_s.x, sigma_t.x),
+            (passed.y, scattered.y, medium.sigma_s.y, sigma_t.y),
+            (passed.z, scattered.z, medium.sigma_s.z, sigma_t.z),
+        ] {
+            let transmittance = (-sigma_t * distance).exp();
+            assert!((passed - transmittance).abs() < 0.01);
+            // the share of the light scattered before it got through
+            let expected = sigma_s / sigma_t * (1.0 - transmittance);
+            assert!(
+                (scattered - expected).abs() < 0.01,
+                "{} {}",
+                scattered,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_albedo() {
+        let black = Medium::from_albedo(Color::zero(), Color::ones(), 0.0);
+        assert!(black.sigma_s.length() < 1e-4);
+        let white = Medium::from_albedo(Color::ones(), Color::new(1.0, 0.5, 0.25), 0.0);
+        assert!((white.sigma_a + white.sigma_s - Color::new(1.0, 2.0, 4.0)).length() < 1e-9);
+        assert!(white.sigma_s.x > 0.99 && white.sigma_a.x < 0.01);
+        // a brighter color scatters more of what it does not absorb
+        let gray = Medium::from_albedo(Color::new(0.2, 0.5, 0.8), Color::ones(), 0.0);
+        assert!(gray.sigma_s.x < gray.sigma_s.y && gray.sigma_s.y < gray.sigma_s.z);
+    }
+}
@@ -0,0 +1,125 @@
+// The Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith's
+// masking-shadowing, following pbrt-v4. Directions are in the shading frame with
+// the macro normal along +z, `alpha_x` and `alpha_y` are the roughness along x and y.
+
+use crate::warp::sample_concentric_disk;
+use crate::Vec3;
+use std::f64::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self {
+            alpha_x: alpha_x.max(0.0),
+            alpha_y: alpha_y.max(0.0),
+        }
+    }
+
+    // too smooth to evaluate, scatter as a perfectly smooth surface instead
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    // density of the normals `wm`, projected onto the macro surface it integrates to one
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let (x, y) = (wm.x / self.alpha_x, wm.y / self.alpha_y);
+        let e = x * x + y * y + wm.z * wm.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    // Smith's auxiliary function: the masked microfacet area per visible one
+    fn lambda(&self, w: Vec3) -> f64 {
+        if w.z == 0.0 {
+            return f64::INFINITY;
+        }
+        let (x, y) = (w.x * self.alpha_x, w.y * self.alpha_y);
+        let alpha2_tan2_theta = (x * x + y * y) / (w.z * w.z);
+        ((1.0 + alpha2_tan2_theta).sqrt() - 1.0) / 2.0
+    }
+
+    // the fraction of microfacets seen from `w`
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // the fraction of microfacets seen from both `wo` and `wi`, with height correlation
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of the normals seen from `w`, which `sample_wm` samples
+    pub fn pdf(&self, w: Vec3, wm: Vec3) -> f64 {
+        self.g1(w) / w.z.abs() * self.d(wm) * (w * wm).max(0.0)
+    }
+
+    // A visible normal from `w` (Heitz 2018): sample the projected area of a
+    // hemisphere in the configuration stretched to unit roughness
+    pub fn sample_wm(&self, w: Vec3, u: (f64, f64)) -> Vec3 {
+        let mut wh = Vec3::new(self.alpha_x * w.x, self.alpha_y * w.y, w.z).unit();
+        if wh.z < 0.0 {
+            wh = -wh;
+        }
+        let t1 = if wh.z < 0.99999 {
+            Vec3::cross(Vec3::new(0.0, 0.0, 1.0), wh).unit()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = Vec3::cross(wh, t1);
+        // squeeze the disk onto the part of the hemisphere visible from `wh`
+        let p = sample_concentric_disk(u);
+        let h = (1.0 - p.x * p.x).sqrt();
+        let s = (1.0 + wh.z) / 2.0;
+        let py = (1.0 - s) * h + s * p.y;
+        let pz = (1.0 - p.x * p.x - py * py).max(0.0).sqrt();
+        let nh = t1 * p.x + t2 * py + wh * pz;
+        Vec3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).unit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warp::{sample_uniform_hemisphere, uniform_hemisphere_pdf};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_normalized() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        for &(alpha_x, alpha_y) in &[(0.3, 0.3), (0.6, 0.1), (1.0, 0.5)] {
+            let distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+            let w = Vec3::new(0.4, -0.3, 0.6).unit();
+            let n = 200_000;
+            let (mut projected, mut visible) = (0.0, 0.0);
+            for _ in 0..n {
+                let wm = sample_uniform_hemisphere((rng.gen(), rng.gen()));
+                projected += distribution.d(wm) * wm.z / uniform_hemisphere_pdf();
+                visible += distribution.pdf(w, wm) / uniform_hemisphere_pdf();
+            }
+            assert!((projected / n as f64 - 1.0).abs() < 0.05);
+            assert!((visible / n as f64 - 1.0).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_sample_visible_normals() {
+        // the sampled normals face `w` and follow `pdf`, compared on the mean of wm.x
+        let distribution = TrowbridgeReitz::new(0.5, 0.2);
+        let w = Vec3::new(0.7, 0.1, 0.3).unit();
+        let mut rng = SmallRng::seed_from_u64(2);
+        let n = 200_000;
+        let (mut sampled, mut expected) = (0.0, 0.0);
+        for _ in 0..n {
+            let wm = distribution.sample_wm(w, (rng.gen(), rng.gen()));
+            assert!(wm.z > 0.0 && w * wm > 0.0);
+            sampled += wm.x;
+            let wm = sample_uniform_hemisphere((rng.gen(), rng.gen()));
+            expected += wm.x * distribution.pdf(w, wm) / uniform_hemisphere_pdf();
+        }
+        assert!((sampled / n as f64 - expected / n as f64).abs() < 0.01);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::sampled_spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::sampler::Sampler;
+use crate::{texture::Texture, Color, HitRecord, Material, Point3, Ray, ScatterRecord, Vec3};
+use std::sync::Arc;
+
+// Two materials blended by `amount`, 0 for all of `a` and 1 for all of `b`, like rust
+// patches on a metal. `scatter` picks one of them at random, while `eval` and `pdf`
+// are the blend of both, so that light sampling weighs against either.
+pub struct Mix {
+    pub a: Arc<dyn Material>,
+    pub b: Arc<dyn Material>,
+    // the mean of its channels, clamped to [0, 1]
+    pub amount: Arc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, amount: Arc<dyn Texture>) -> Self {
+        Self { a, b, amount }
+    }
+
+    fn amount(&self, u: f64, v: f64, p: Point3) -> f64 {
+        let c = self.amount.value(u, v, p);
+        ((c.x + c.y + c.z) / 3.0).clamp(0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let t = self.amount(hit_record.u, hit_record.v, hit_record.p);
+        let wo = -ray_in.direction.unit();
+        let material = if sampler.get_1d() < t {
+            &self.b
+        } else {
+            &self.a
+        };
+        // The picked material's own weight averages to the blend, the density of the
+        // blend is what light sampling compares against
+        match material.scatter(ray_in, hit_record.clone(), sampler)? {
+            ScatterRecord::Diffuse {
+                scattered,
+                attenuation,
+                ..
+            } => {
+                let pdf = self.pdf(&hit_record, wo, scattered.direction.unit());
+                Some(ScatterRecord::Diffuse {
+                    scattered,
+                    attenuation,
+                    pdf,
+                })
+            }
+            specular => Some(specular),
+        }
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: Point3) -> Color {
+        let t = self.amount(u, v, p);
+        self.a.emitted(u, v, p) * (1.0 - t) + self.b.emitted(u, v, p) * t
+    }
+
+    fn emitted_spectrum(
+        &self,
+        u: f64,
+        v: f64,
+        p: Point3,
+        lambda: &SampledWavelengths,
+    ) -> SampledSpectrum {
+        let t = self.amount(u, v, p);
+        self.a.emitted_spectrum(u, v, p, lambda) * (1.0 - t)
+            + self.b.emitted_spectrum(u, v, p, lambda) * t
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.a.is_emissive() || self.b.is_emissive()
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.a.is_dispersive() || self.b.is_dispersive()
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let t = self.amount(hit_record.u, hit_record.v, hit_record.p);
+        self.a.eval(hit_record, wo, wi) * (1.0 - t) + self.b.eval(hit_record, wo, wi) * t
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let t = self.amount(hit_record.u, hit_record.v, hit_record.p);
+        self.a.pdf(hit_record, wo, wi) * (1.0 - t) + self.b.pdf(hit_record, wo, wi) * t
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conductor::{Conductor, ConductorPreset};
+    use crate::constant_texture::ConstantTexture;
+    use crate::lambertian::Lambertian;
+    use crate::microfacet::TrowbridgeReitz;
+    use crate::sampler::IndependentSampler;
+    use crate::warp::{sample_uniform_sphere, uniform_sphere_pdf};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_sampling_matches_eval() {
+        let a: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.4, 0.2)));
+        let b: Arc<dyn Material> = Arc::new(Conductor::preset(
+            ConductorPreset::Copper,
+            TrowbridgeReitz::new(0.3, 0.3),
+        ));
+        let mix = Mix::new(
+            a.clone(),
+            b,
+            Arc::new(ConstantTexture::new(Color::new(0.1, 0.3, 0.5))),
+        );
+        let hit = || HitRecord {
+            p: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::new(1.0, 0.0, 0.0),
+            dpdv: Vec3::new(0.0, 1.0, 0.0),
+            front: true,
+            wavelength: None,
+            material: a.clone(),
+        };
+        let mut sampler = IndependentSampler::new(1);
+        let mut rng = SmallRng::seed_from_u64(2);
+        let wo = Vec3::new(0.3, -0.4, 0.7).unit();
+        let ray_in = Ray::new(wo, -wo);
+        let n = 200_000;
+        let (mut sampled, mut uniform, mut pdf_integral) = (Color::zero(), Color::zero(), 0.0);
+        for _ in 0..n {
+            if let Some(ScatterRecord::Diffuse {
+                scattered,
+                attenuation,
+                pdf,
+            }) = mix.scatter(&ray_in, hit(), &mut sampler)
+            {
+                assert!((pdf - mix.pdf(&hit(), wo, scattered.direction)).abs() < 1e-12);
+                sampled += attenuation;
+            }
+            let wi = sample_uniform_sphere((rng.gen(), rng.gen()));
+            uniform += mix.eval(&hit(), wo, wi) / uniform_sphere_pdf();
+            pdf_integral += mix.pdf(&hit(), wo, wi) / uniform_sphere_pdf();
+        }
+        let (sampled, uniform) = (sampled / n as f64, uniform / n as f64);
+        assert!(
+            (sampled - uniform).length() < 0.02,
+            "{:?} {:?}",
+            sampled,
+            uniform
+        );
+        // the metal's samples below the horizon are lost
+        let pdf_integral = pdf_integral / n as f64;
+        assert!(pdf_integral > 0.9 && pdf_integral < 1.02);
+    }
+}
@@ -0,0 +1,69 @@
+use crate::aabb::Aabb;
+use crate::{HitRecord, Hittable, Material, Point3, Ray, Sphere, Vec3};
+use std::sync::Arc;
+
+// A sphere moving and growing linearly while the shutter is open,
+// from `center0` and `radius0` at time 0 to `center1` and `radius1` at time 1
+pub struct MovingSphere {
+    pub center0: Point3,
+    pub center1: Point3,
+    pub radius0: f64,
+    pub radius1: f64,
+    pub material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: f64) -> Point3 {
+        self.center0 + (self.center1 - self.center0) * time
+    }
+
+    pub fn radius(&self, time: f64) -> f64 {
+        self.radius0 + (self.radius1 - self.radius0) * time
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (center, radius) = (self.center(ray.time), self.radius(ray.time));
+        Sphere::hit_at(center, radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bounds = |center: Point3, radius: f64| {
+            let r = Vec3::new(radius, radius, radius);
+            Aabb::new(center - r, center + r)
+        };
+        bounds(self.center0, self.radius0).union(&bounds(self.center1, self.radius1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::Color;
+
+    #[test]
+    fn test_moves_with_time() {
+        let sphere = MovingSphere {
+            center0: Point3::new(0.0, 0.0, -5.0),
+            center1: Point3::new(2.0, 0.0, -5.0),
+            radius0: 0.5,
+            radius1: 0.5,
+            material: Arc::new(Lambertian::new(Color::ones())),
+        };
+        let ray = |time| Ray {
+            time,
+            ..Ray::new(Point3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0))
+        };
+        assert!(sphere.hit(&ray(0.0), 0.0, f64::INFINITY).is_none());
+        let hit = sphere.hit(&ray(1.0), 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-12);
+        assert!(sphere.bounding_box().hit(
+            &ray(0.0),
+            Vec3::new(f64::INFINITY, f64::INFINITY, -1.0),
+            0.0,
+            f64::INFINITY
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::aabb::Aabb;
+use crate::triangle::Triangle;
+use crate::triangle_mesh::TriangleMesh;
+use crate::{HitRecord, Hittable, Material, Point3, Ray, Vec3};
+use std::sync::Arc;
+
+// A face of a mesh that is where `triangle` is at time 0, and by time 1 is scaled by
+// `scale` and then moved by `offset`, linearly in between. The ray is taken back to
+// where the face was at time 0, which leaves the distances along it as they are.
+pub struct MovingTriangle {
+    pub triangle: Triangle,
+    pub scale: f64,
+    pub offset: Vec3,
+}
+
+impl MovingTriangle {
+    pub fn triangles(
+        mesh: &Arc<TriangleMesh>,
+        material: &Arc<dyn Material>,
+        scale: f64,
+        offset: Vec3,
+    ) -> Vec<Box<dyn Hittable>> {
+        (0..mesh.indices.len())
+            .map(|index| {
+                Box::new(MovingTriangle {
+                    triangle: Triangle {
+                        mesh: mesh.clone(),
+                        index,
+                        material: material.clone(),
+                    },
+                    scale,
+                    offset,
+                }) as Box<dyn Hittable>
+            })
+            .collect()
+    }
+
+    // The faces of `mesh`, moving by `motion`, a `scale` and `offset` by time 1, if any
+    pub fn mesh_triangles(
+        mesh: &Arc<TriangleMesh>,
+        material: &Arc<dyn Material>,
+        motion: Option<(f64, Vec3)>,
+    ) -> Vec<Box<dyn Hittable>> {
+        match motion {
+            Some((scale, offset)) => MovingTriangle::triangles(mesh, material, scale, offset),
+            None => TriangleMesh::triangles(mesh, material),
+        }
+    }
+
+    fn placement(&self, time: f64) -> (f64, Vec3) {
+        (1.0 + (self.scale - 1.0) * time, self.offset * time)
+    }
+}
+
+impl Hittable for MovingTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (scale, offset) = self.placement(ray.time);
+        if scale == 0.0 {
+            return None;
+        }
+        let local = Ray {
+            origin: (ray.origin - offset) / scale,
+            direction: ray.direction / scale,
+            ..ray.clone()
+        };
+        let mut rec = self.triangle.hit(&local, t_min, t_max)?;
+        rec.p = rec.p * scale + offset;
+        rec.dpdu *= scale;
+        rec.dpdv *= scale;
+        // turned inside out, the face is seen from its other side
+        if scale < 0.0 {
+            rec.normal = -rec.normal;
+            rec.front = !rec.front;
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let bounds = self.triangle.bounding_box();
+        let placed = |scale: f64, offset: Vec3| {
+            let (a, b) = (bounds.min * scale + offset, bounds.max * scale + offset);
+            Aabb::new(
+                Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+                Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+            )
+        };
+        placed(1.0, Vec3::zero()).union(&placed(self.scale, self.offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::Color;
+
+    #[test]
+    fn test_moves_with_time() {
+        // a unit square in the xy plane, twice as large and 2 along x at time 1
+        let mesh = Arc::new(TriangleMesh {
+            positions: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            normals: vec![],
+            uvs: vec![],
+            tangents: vec![],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+        });
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::ones()));
+        let triangles = MovingTriangle::triangles(&mesh, &material, 2.0, Vec3::new(2.0, 0.0, 0.0));
+        let ray = |x, time| Ray {
+            time,
+            ..Ray::new(Point3::new(x, 0.25, 2.0), Vec3::new(0.0, 0.0, -1.0))
+        };
+        let hit = |x, time| triangles[0].hit(&ray(x, time), 0.0, f64::INFINITY);
+        assert!(hit(0.75, 0.0).is_some());
+        assert!(hit(3.5, 0.0).is_none());
+        // halfway, 1.5 times as large and 1 along x
+        assert!(hit(0.75, 0.5).is_none());
+        let rec = hit(2.0, 0.5).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!((rec.p - Point3::new(2.0, 0.25, 0.0)).length() < 1e-12);
+        assert_eq!(rec.dpdu, Vec3::new(1.5, 0.0, 0.0));
+        assert!(rec.front);
+        let bounds = triangles[0].bounding_box();
+        assert_eq!(bounds.min, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(bounds.max, Point3::new(4.0, 2.0, 0.0));
+    }
+}
@@ -0,0 +1,161 @@
+// Materials of Wavefront MTL files, with the PBR extensions of Exocortex and
+// Blender: `Pr` roughness, `Pm` metallic, `Ps` sheen, `Pc` and `Pcr` clear coat and
+// `aniso`. Texture maps are not read.
+
+use crate::constant_texture::ConstantTexture;
+use crate::diffuse_light::DiffuseLight;
+use crate::principled::Principled;
+use crate::{Color, Material};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MtlMaterial {
+    pub kd: Option<Color>,
+    pub ks: Option<Color>,
+    pub ke: Option<Color>,
+    // the Phong exponent, a roughness for files without `Pr`
+    pub ns: Option<f64>,
+    pub ni: Option<f64>,
+    // opacity, the rest is transmitted
+    pub d: Option<f64>,
+    pub pr: Option<f64>,
+    pub pm: Option<f64>,
+    pub ps: Option<f64>,
+    pub pc: Option<f64>,
+    pub pcr: Option<f64>,
+    pub aniso: Option<f64>,
+}
+
+impl MtlMaterial {
+    // Emitters become lights, everything else a principled material
+    pub fn build(&self) -> Arc<dyn Material> {
+        if let Some(ke) = self.ke {
+            if ke != Color::zero() {
+                return Arc::new(DiffuseLight::with_texture(Arc::new(ConstantTexture::new(
+                    ke,
+                ))));
+            }
+        }
+        let mut principled = Principled::new(self.kd.unwrap_or_else(|| Color::ones() * 0.8));
+        // the GGX roughness matching a Blinn-Phong lobe of the same width
+        let roughness = self
+            .pr
+            .or_else(|| self.ns.map(|ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt().sqrt()));
+        if let Some(roughness) = roughness {
+            principled.roughness = roughness.clamp(0.0, 1.0);
+        }
+        if let Some(ks) = self.ks {
+            // Ks is the reflectance head-on, which Principled scales by 0.08
+            let reflectance = ks * Color::new(0.2126, 0.7152, 0.0722);
+            principled.specular = (reflectance / 0.08).clamp(0.0, 1.0);
+        }
+        principled.metallic = self.pm.unwrap_or(0.0).clamp(0.0, 1.0);
+        principled.sheen = self.ps.unwrap_or(0.0).clamp(0.0, 1.0);
+        principled.clearcoat = self.pc.unwrap_or(0.0).clamp(0.0, 1.0);
+        principled.clearcoat_gloss = 1.0 - self.pcr.unwrap_or(0.0).clamp(0.0, 1.0);
+        principled.anisotropic = self.aniso.unwrap_or(0.0).clamp(0.0, 1.0);
+        principled.transmission = 1.0 - self.d.unwrap_or(1.0).clamp(0.0, 1.0);
+        principled.ior = self.ni.unwrap_or(1.5).max(1.0);
+        Arc::new(principled)
+    }
+}
+
+// The materials by name. `source` names the file in errors.
+pub fn parse_mtl(content: &str, source: &str) -> Result<HashMap<String, MtlMaterial>, String> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+    for (number, line) in content.lines().enumerate() {
+        let error = |message: &str| format!("{}:{}: {}", source, number + 1, message);
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let values: Vec<&str> = words.collect();
+        let number = |i: usize| -> Result<f64, String> {
+            values
+                .get(i)
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| error(&format!("expected a number after {}", keyword)))
+        };
+        let color = || -> Result<Color, String> {
+            let r = number(0)?;
+            // a single value is a gray
+            match values.len() {
+                1 => Ok(Color::new(r, r, r)),
+                _ => Ok(Color::new(r, number(1)?, number(2)?)),
+            }
+        };
+        if keyword == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                materials.insert(name, material);
+            }
+            let name = values.join(" ");
+            if name.is_empty() {
+                return Err(error("newmtl without a name"));
+            }
+            current = Some((name, MtlMaterial::default()));
+            continue;
+        }
+        let material = match &mut current {
+            Some((_, material)) => material,
+            None => return Err(error(&format!("{} before any newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => material.kd = Some(color()?),
+            "Ks" => material.ks = Some(color()?),
+            "Ke" => material.ke = Some(color()?),
+            "Ns" => material.ns = Some(number(0)?),
+            "Ni" => material.ni = Some(number(0)?),
+            "d" => material.d = Some(number(0)?),
+            "Tr" => material.d = Some(1.0 - number(0)?),
+            "Pr" => material.pr = Some(number(0)?),
+            "Pm" => material.pm = Some(number(0)?),
+            "Ps" => material.ps = Some(number(0)?),
+            "Pc" => material.pc = Some(number(0)?),
+            "Pcr" => material.pcr = Some(number(0)?),
+            "aniso" => material.aniso = Some(number(0)?),
+            // Ka, illum, the texture maps and everything else have no say here
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mtl() {
+        let content = "# a comment\n\
+                       newmtl gold\n\
+                       \tKd 1.0 0.78 0.34\n\
+                       \tPr 0.25\n\
+                       \tPm 1\n\
+                       \tPs 0.1\n\
+                       newmtl old plastic\n\
+                       \tKd 0.5\n\
+                       \tNs 98\n\
+                       \td 0.25\n\
+                       \tmap_Kd plastic.png\n";
+        let materials = parse_mtl(content, "test.mtl").unwrap();
+        assert_eq!(materials.len(), 2);
+        let gold = &materials["gold"];
+        assert_eq!(gold.kd, Some(Color::new(1.0, 0.78, 0.34)));
+        assert_eq!(
+            (gold.pr, gold.pm, gold.ps),
+            (Some(0.25), Some(1.0), Some(0.1))
+        );
+        let plastic = &materials["old plastic"];
+        assert_eq!(plastic.kd, Some(Color::new(0.5, 0.5, 0.5)));
+        assert_eq!((plastic.ns, plastic.d), (Some(98.0), Some(0.25)));
+        assert!(parse_mtl("Kd 1 1 1\n", "test.mtl").is_err());
+        let error = parse_mtl("newmtl a\nPr rough\n", "test.mtl").unwrap_err();
+        assert!(error.starts_with("test.mtl:2:"));
+    }
+}
@@ -0,0 +1,27 @@
+use crate::perlin::Perlin;
+use crate::{texture::Texture, Color, Point3};
+
+// Gray clouds of Perlin noise in [0, 1], in features about `1 / scale` across,
+// with finer detail from more `octaves`
+pub struct NoiseTexture {
+    pub noise: Perlin,
+    pub scale: f64,
+    pub octaves: u32,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64, octaves: u32) -> Self {
+        Self {
+            noise: Perlin::new(0),
+            scale,
+            octaves,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: Point3) -> Color {
+        let n = self.noise.fractal(p * self.scale, self.octaves);
+        Color::ones() * (0.5 * (1.0 + n)).clamp(0.0, 1.0)
+    }
+}
@@ -0,0 +1,253 @@
+// Meshes of Wavefront OBJ files: `v`, `vt`, `vn` and faces with any number of
+// corners, split into fans, and the materials of `mtllib` picked by `usemtl`.
+// Groups, smoothing groups and the rest are skipped. Meshes with both normals and
+// texture coordinates get tangents for normal maps.
+
+use crate::mtl::{parse_mtl, MtlMaterial};
+use crate::triangle_mesh::TriangleMesh;
+use crate::{Point3, Vec3};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct ObjMesh {
+    pub mesh: TriangleMesh,
+    // the `usemtl` of its faces, `None` before any
+    pub material: Option<String>,
+}
+
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: HashMap<String, MtlMaterial>,
+}
+
+// The faces of one material, with a vertex for every distinct corner
+struct MeshBuilder {
+    mesh: TriangleMesh,
+    material: Option<String>,
+    corners: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    // attributes only some corners have are dropped
+    all_normals: bool,
+    all_uvs: bool,
+}
+
+impl MeshBuilder {
+    fn new(material: Option<String>) -> Self {
+        Self {
+            mesh: TriangleMesh {
+                positions: vec![],
+                normals: vec![],
+                uvs: vec![],
+                tangents: vec![],
+                indices: vec![],
+            },
+            material,
+            corners: HashMap::new(),
+            all_normals: true,
+            all_uvs: true,
+        }
+    }
+
+    fn finish(mut self) -> ObjMesh {
+        if !self.all_normals {
+            self.mesh.normals.clear();
+        }
+        if !self.all_uvs {
+            self.mesh.uvs.clear();
+        }
+        self.mesh.generate_tangents();
+        ObjMesh {
+            mesh: self.mesh,
+            material: self.material,
+        }
+    }
+}
+
+pub fn load_obj(path: &str) -> Result<ObjModel, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let (meshes, libraries) = parse_obj(&content, path)?;
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    let mut materials = HashMap::new();
+    for library in libraries {
+        let mtl_path = directory.join(&library);
+        let mtl_path = mtl_path.to_string_lossy();
+        let content = std::fs::read_to_string(mtl_path.as_ref())
+            .map_err(|e| format!("cannot read {}: {}", mtl_path, e))?;
+        materials.extend(parse_mtl(&content, &mtl_path)?);
+    }
+    for mesh in &meshes {
+        if let Some(name) = &mesh.material {
+            if !materials.contains_key(name) {
+                return Err(format!("{}: unknown material {}", path, name));
+            }
+        }
+    }
+    Ok(ObjModel { meshes, materials })
+}
+
+// The meshes and the names of the material libraries. `source` names the file in errors.
+fn parse_obj(content: &str, source: &str) -> Result<(Vec<ObjMesh>, Vec<String>), String> {
+    let (mut positions, mut normals, mut uvs) = (vec![], vec![], vec![]);
+    let mut libraries = vec![];
+    let mut meshes = vec![];
+    let mut builder = MeshBuilder::new(None);
+    for (number, line) in content.lines().enumerate() {
+        let error = |message: &str| format!("{}:{}: {}", source, number + 1, message);
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let values: Vec<&str> = words.collect();
+        let numbers = |count: usize| -> Result<Vec<f64>, String> {
+            let numbers: Vec<f64> = values
+                .iter()
+                .take(count)
+                .map(|value| value.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| error(&format!("expected numbers after {}", keyword)))?;
+            if numbers.len() < count {
+                return Err(error(&format!(
+                    "expected {} numbers after {}",
+                    count, keyword
+                )));
+            }
+            Ok(numbers)
+        };
+        match keyword {
+            "v" => {
+                let v = numbers(3)?;
+                positions.push(Point3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let n = numbers(3)?;
+                normals.push(Vec3::new(n[0], n[1], n[2]));
+            }
+            "vt" => {
+                let t = numbers(2)?;
+                uvs.push((t[0], t[1]));
+            }
+            "mtllib" => libraries.push(values.join(" ")),
+            "usemtl" => {
+                let material = Some(values.join(" "));
+                if material != builder.material {
+                    let previous = std::mem::replace(&mut builder, MeshBuilder::new(material));
+                    if !previous.mesh.indices.is_empty() {
+                        meshes.push(previous.finish());
+                    }
+                }
+            }
+            "f" => {
+                if values.len() < 3 {
+                    return Err(error("a face needs at least three corners"));
+                }
+                let mut corners = Vec::with_capacity(values.len());
+                for value in &values {
+                    let mut parts = value.split('/');
+                    // 1-based, negative counts back from the last one so far
+                    let mut index =
+                        |count: usize, required: bool| -> Result<Option<usize>, String> {
+                            let part = parts.next().unwrap_or("");
+                            if part.is_empty() && !required {
+                                return Ok(None);
+                            }
+                            let i: i64 = part
+                                .parse()
+                                .map_err(|_| error(&format!("bad face corner {}", value)))?;
+                            let i = if i < 0 { count as i64 + i } else { i - 1 };
+                            if i < 0 || i >= count as i64 {
+                                return Err(error(&format!("face corner {} out of range", value)));
+                            }
+                            Ok(Some(i as usize))
+                        };
+                    let position = index(positions.len(), true)?.unwrap_or_default();
+                    let uv = index(uvs.len(), false)?;
+                    let normal = index(normals.len(), false)?;
+                    let mesh = &mut builder.mesh;
+                    let next = mesh.positions.len();
+                    let corner = *builder
+                        .corners
+                        .entry((position, uv, normal))
+                        .or_insert(next);
+                    if corner == next {
+                        mesh.positions.push(positions[position]);
+                        mesh.uvs.push(uv.map_or((0.0, 0.0), |i| uvs[i]));
+                        mesh.normals
+                            .push(normal.map_or(Vec3::zero(), |i| normals[i]));
+                        builder.all_uvs &= uv.is_some();
+                        builder.all_normals &= normal.is_some();
+                    }
+                    corners.push(corner);
+                }
+                for i in 1..corners.len() - 1 {
+                    builder
+                        .mesh
+                        .indices
+                        .push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+    if !builder.mesh.indices.is_empty() {
+        meshes.push(builder.finish());
+    }
+    Ok((meshes, libraries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_obj() {
+        let content = "mtllib box.mtl\n\
+                       v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+                       vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+                       vn 0 0 1\n\
+                       f 1/1/1 2/2/1 3/3/1 4/4/1\n\
+                       usemtl red\n\
+                       f -4 -2 -1\n";
+        let (meshes, libraries) = parse_obj(content, "box.obj").unwrap();
+        assert_eq!(libraries, vec!["box.mtl".to_string()]);
+        assert_eq!(meshes.len(), 2);
+        // the quad is a fan of two triangles sharing four vertices
+        let quad = &meshes[0].mesh;
+        assert_eq!(quad.indices, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(quad.positions.len(), 4);
+        assert_eq!(quad.uvs[2], (1.0, 1.0));
+        assert_eq!(quad.normals[3], Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(meshes[0].material, None);
+        let red = &meshes[1];
+        assert_eq!(red.material, Some("red".to_string()));
+        assert_eq!(red.mesh.positions[0], Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(red.mesh.positions[2], Point3::new(0.0, 1.0, 0.0));
+        assert!(red.mesh.uvs.is_empty() && red.mesh.normals.is_empty());
+
+        assert!(parse_obj("v 0 0 0\nf 1 2 3\n", "bad.obj").is_err());
+        let error = parse_obj("v 0 0\n", "bad.obj").unwrap_err();
+        assert!(error.starts_with("bad.obj:1:"));
+    }
+
+    #[test]
+    fn test_load_obj() {
+        let model = load_obj("../objects/cube.obj").unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].mesh.indices.len(), 12);
+        let spot = load_obj("../objects/spot_triangulated.obj").unwrap();
+        let mesh = &spot.meshes[0].mesh;
+        assert_eq!(mesh.indices.len(), 5856);
+        assert_eq!(mesh.uvs.len(), mesh.positions.len());
+        // flat faces follow their texture without tangents
+        assert!(mesh.normals.is_empty() && mesh.tangents.is_empty());
+        let smooth = load_obj("../objects/spot_triangulated_good.obj").unwrap();
+        let mesh = &smooth.meshes[0].mesh;
+        assert_eq!(mesh.tangents.len(), mesh.positions.len());
+        for (&(t, sign), &n) in mesh.tangents.iter().zip(&mesh.normals) {
+            assert!((t.length() - 1.0).abs() < 1e-9 && (t * n.unit()).abs() < 1e-9);
+            assert!(sign == 1.0 || sign == -1.0);
+        }
+        assert!(load_obj("../objects/missing.obj").is_err());
+    }
+}
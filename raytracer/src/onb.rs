@@ -0,0 +1,106 @@
+use crate::{HitRecord, Vec3};
+
+// Orthonormal basis with `w` along a given direction, for turning
+// directions sampled around +z into world space
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    // Duff et al., "Building an Orthonormal Basis, Revisited", without branches
+    // on the direction except for its sign
+    pub fn from_w(n: Vec3) -> Self {
+        let w = n.unit();
+        let sign = 1.0_f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        Self {
+            u: Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vec3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+
+    // The shading frame of a hit, with `u` along dpdu made orthogonal to the normal
+    // so that anisotropic lobes follow the surface parametrization. Falls back to
+    // `from_w` where dpdu is missing or along the normal.
+    pub fn from_hit(hit_record: &HitRecord) -> Self {
+        let w = hit_record.normal.unit();
+        let dpdu = hit_record.dpdu;
+        let tangent = dpdu - w * (dpdu * w);
+        if tangent.squared_length() <= 1e-12 * dpdu.squared_length().max(f64::MIN_POSITIVE) {
+            return Self::from_w(w);
+        }
+        let u = tangent.unit();
+        Self {
+            u,
+            v: Vec3::cross(w, u),
+            w,
+        }
+    }
+
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    // the inverse of `local`
+    pub fn world_to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a * self.u, a * self.v, a * self.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orthonormal() {
+        for &n in &[
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 2.0, -3.0),
+            Vec3::new(-0.3, 0.1, 0.0),
+        ] {
+            let onb = Onb::from_w(n);
+            assert!((onb.u.length() - 1.0).abs() < 1e-12);
+            assert!((onb.v.length() - 1.0).abs() < 1e-12);
+            assert!((onb.u * onb.v).abs() < 1e-12);
+            assert!((onb.u * onb.w).abs() < 1e-12);
+            assert!((Vec3::cross(onb.u, onb.v) - onb.w).length() < 1e-12);
+            assert!((onb.local(Vec3::new(0.0, 0.0, 2.0)) - onb.w * 2.0).length() < 1e-12);
+            let a = Vec3::new(0.3, -1.0, 2.0);
+            assert!((onb.local(onb.world_to_local(a)) - a).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_from_hit() {
+        let hit = |normal, dpdu| HitRecord {
+            p: Vec3::zero(),
+            normal,
+            t: 1.0,
+            u: 0.0,
+            v: 0.0,
+            dpdu,
+            dpdv: Vec3::zero(),
+            front: true,
+            wavelength: None,
+            material: std::sync::Arc::new(crate::lambertian::Lambertian::new(Vec3::ones())),
+        };
+        // dpdu is projected onto the tangent plane
+        let normal = Vec3::new(0.0, 0.6, 0.8);
+        let onb = Onb::from_hit(&hit(normal, Vec3::new(2.0, 1.0, 0.0)));
+        assert!((onb.w - normal).length() < 1e-12);
+        assert!(onb.u.x > 0.0 && (onb.u * normal).abs() < 1e-12);
+        assert!((onb.u.length() - 1.0).abs() < 1e-12);
+        assert!((Vec3::cross(onb.u, onb.v) - onb.w).length() < 1e-12);
+        // and ignored when it is missing or along the normal
+        for &dpdu in &[Vec3::zero(), normal * 3.0] {
+            let onb = Onb::from_hit(&hit(normal, dpdu));
+            assert!((onb.u - Onb::from_w(normal).u).length() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::camera::{Camera, Frame};
+use crate::sampler::Sampler;
+use crate::{Point3, Ray, Vec3};
+
+// Parallel rays along the view direction, from a rectangle centered on `lookfrom`
+pub struct OrthographicCamera {
+    pub lower_left_corner: Point3,
+    pub horizontal: Vec3,
+    pub vertical: Vec3,
+    pub direction: Vec3,
+}
+
+impl OrthographicCamera {
+    pub fn new(frame: Frame, height: f64, aspect_ratio: f64) -> Self {
+        let horizontal = frame.u * height * aspect_ratio;
+        let vertical = frame.v * height;
+        Self {
+            lower_left_corner: frame.origin - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -frame.w,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, s: f64, t: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        Some(Ray::new(
+            self.lower_left_corner + self.horizontal * s + self.vertical * t,
+            self.direction,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    #[test]
+    fn test_parallel_rays() {
+        let frame = Frame::new(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let camera = OrthographicCamera::new(frame, 2.0, 2.0);
+        let mut sampler = IndependentSampler::new(1);
+        let corner = camera.get_ray(1.0, 1.0, &mut sampler).unwrap();
+        assert!((corner.origin - Point3::new(2.0, 1.0, 5.0)).length() < 1e-12);
+        assert!((corner.direction - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-12);
+        let center = camera.get_ray(0.5, 0.5, &mut sampler).unwrap();
+        assert_eq!(center.direction, corner.direction);
+    }
+
+    #[test]
+    fn test_pixel_footprint() {
+        let frame = Frame::new(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let camera = OrthographicCamera::new(frame, 2.0, 2.0);
+        for &p in &[Point3::zero(), Point3::new(1.0, -0.5, -20.0)] {
+            assert!((camera.pixel_footprint(p, 40).unwrap() - 0.05).abs() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{Point3, Vec3};
+use rand::{rngs::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+// Perlin's gradient noise, as in "Ray Tracing: The Next Week": random unit gradients
+// on a lattice, hashed by permutations of the lattice coordinates and blended with a
+// smoothstep. The same seed gives the same noise.
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| {
+                let v = Vec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                );
+                if v.squared_length() > 0.0 {
+                    v.unit()
+                } else {
+                    Vec3::new(1.0, 0.0, 0.0)
+                }
+            })
+            .collect();
+        let permutation = |rng: &mut SmallRng| {
+            let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+            perm.shuffle(rng);
+            perm
+        };
+        let perm_x = permutation(&mut rng);
+        let perm_y = permutation(&mut rng);
+        let perm_z = permutation(&mut rng);
+        Self {
+            gradients,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    // Roughly in [-1, 1], zero on the lattice points
+    pub fn noise(&self, p: Point3) -> f64 {
+        let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - fx, p.y - fy, p.z - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        let wrap = |n: i64| (n & (POINT_COUNT as i64 - 1)) as usize;
+        // Hermite smoothing hides the lattice
+        let (uu, vv, ww) = (
+            u * u * (3.0 - 2.0 * u),
+            v * v * (3.0 - 2.0 * v),
+            w * w * (3.0 - 2.0 * w),
+        );
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[self.perm_x[wrap(i + di)]
+                        ^ self.perm_y[wrap(j + dj)]
+                        ^ self.perm_z[wrap(k + dk)]];
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+                    sum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * (gradient * weight);
+                }
+            }
+        }
+        sum
+    }
+
+    // Octaves of noise at doubling frequencies and halving amplitudes
+    pub fn fractal(&self, p: Point3, octaves: u32) -> f64 {
+        let (mut sum, mut p, mut amplitude) = (0.0, p, 1.0);
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(p);
+            p *= 2.0;
+            amplitude *= 0.5;
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise() {
+        let perlin = Perlin::new(1);
+        assert_eq!(perlin.noise(Point3::new(3.0, -2.0, 7.0)), 0.0);
+        let p = Point3::new(0.3, 1.7, -4.2);
+        assert_eq!(perlin.noise(p), Perlin::new(1).noise(p));
+        assert_ne!(perlin.noise(p), Perlin::new(2).noise(p));
+        // smooth: close points have close values
+        let q = p + Vec3::new(1e-4, 0.0, 0.0);
+        assert!((perlin.noise(p) - perlin.noise(q)).abs() < 1e-3);
+        let mut rng = SmallRng::seed_from_u64(3);
+        for _ in 0..1000 {
+            let p = Point3::new(rng.gen(), rng.gen(), rng.gen()) * 20.0;
+            assert!(perlin.noise(p).abs() <= 1.0);
+            assert!(perlin.fractal(p, 4).abs() <= 2.0);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::aperture::Aperture;
+use crate::camera::{Camera, Frame};
+use crate::sampler::Sampler;
+use crate::{Point3, Ray, Vec3};
+
+// Thin lens camera, a pinhole when the aperture is zero
+pub struct PerspectiveCamera {
+    pub origin: Point3,
+    pub horizontal: Vec3,
+    pub vertical: Vec3,
+    pub lower_left_corner: Point3,
+    pub lens_radius: f64,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub aperture: Aperture,
+    // offset of the lens barrel's opening in the frame corners, in aperture radii
+    pub cat_eye: f64,
+    pub aspect_ratio: f64,
+}
+
+impl PerspectiveCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        vfov: f64,
+        aspect_ratio: f64,
+        aperture: f64,
+        focus_dist: f64,
+    ) -> Self {
+        let theta = vfov * std::f64::consts::PI / 180_f64; // degree to radian
+        let h = (theta / 2_f64).tan();
+        let viewport_height = 2_f64 * h;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        let Frame { origin, u, v, w } = Frame::new(lookfrom, lookat, vup);
+        let horizontal = u * viewport_width * focus_dist;
+        let vertical = v * viewport_height * focus_dist;
+        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - w * focus_dist;
+        let lens_radius = aperture / 2_f64;
+
+        Self {
+            origin,
+            horizontal,
+            vertical,
+            lower_left_corner,
+            u,
+            v,
+            lens_radius,
+            aperture: Aperture::Circle,
+            cat_eye: 0.0,
+            aspect_ratio,
+        }
+    }
+
+    pub fn with_aperture(self, aperture: Aperture) -> Self {
+        Self { aperture, ..self }
+    }
+
+    pub fn with_cat_eye(self, cat_eye: f64) -> Self {
+        Self { cat_eye, ..self }
+    }
+
+    // Moves the image window within the plane in focus, like a shift lens
+    pub fn with_shift(self, shift: Vec3) -> Self {
+        Self {
+            lower_left_corner: self.lower_left_corner + shift,
+            ..self
+        }
+    }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let (x, y) = self.aperture.sample(sampler.get_2d());
+        if self.cat_eye > 0.0 {
+            // Optical vignetting: away from the center of the frame the opening of the
+            // lens barrel covers part of the aperture, as a unit circle shifted towards
+            // the center. Out-of-focus highlights turn into cat's eyes and the frame
+            // edges get darker, since the blocked rays carry no light.
+            let corner = (self.aspect_ratio * self.aspect_ratio + 1.0).sqrt() / 2.0;
+            let shift = self.cat_eye / corner;
+            let (dx, dy) = ((s - 0.5) * self.aspect_ratio * shift, (t - 0.5) * shift);
+            if (x + dx) * (x + dx) + (y + dy) * (y + dy) > 1.0 {
+                return None;
+            }
+        }
+        let offset = (self.u * x + self.v * y) * self.lens_radius;
+
+        Some(Ray::new(
+            self.origin + offset,
+            self.lower_left_corner + self.horizontal * s + self.vertical * t - self.origin - offset,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    #[test]
+    fn test_lens_is_a_disk() {
+        let lookat = Point3::new(0.0, 0.0, -1.0);
+        let camera = PerspectiveCamera::new(
+            Point3::zero(),
+            lookat,
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            2.0,
+            1.0,
+        );
+        let mut sampler = IndependentSampler::new(1);
+        for _ in 0..1000 {
+            let ray = camera.get_ray(0.5, 0.5, &mut sampler).unwrap();
+            // rays start on the lens in the plane of the camera and meet in focus
+            assert!(ray.origin.length() <= 1.0 + 1e-12);
+            assert_eq!(ray.origin.z, 0.0);
+            let t = -1.0 / ray.direction.z;
+            assert!((ray.origin + ray.direction * t - lookat).length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_pixel_footprint() {
+        let camera = PerspectiveCamera::new(
+            Point3::zero(),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            0.5,
+            1.0,
+        );
+        // two units up the image at a distance of one, over 100 pixels
+        let footprint = |p: Point3| camera.pixel_footprint(p, 100).unwrap();
+        assert!((footprint(Point3::new(0.0, 0.0, -1.0)) - 0.02).abs() < 1e-4);
+        assert!((footprint(Point3::new(0.0, 0.0, -5.0)) - 0.1).abs() < 1e-3);
+        // nothing behind the camera, on its plane or outside the view
+        for &p in &[
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(3.0, 0.0, -1.0),
+        ] {
+            assert_eq!(camera.pixel_footprint(p, 100), None);
+        }
+        assert!(camera
+            .pixel_footprint(Point3::new(0.9, 0.9, -1.0), 100)
+            .is_some());
+    }
+
+    #[test]
+    fn test_cat_eye() {
+        let camera = PerspectiveCamera::new(
+            Point3::zero(),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            2.0,
+            1.0,
+        )
+        .with_cat_eye(1.0);
+        let mut sampler = IndependentSampler::new(1);
+        let mut open = |s, t| {
+            (0..4000)
+                .filter_map(|_| camera.get_ray(s, t, &mut sampler))
+                .collect::<Vec<_>>()
+        };
+        // nothing is blocked in the center, about 40% of the lens is left in the corners
+        assert_eq!(open(0.5, 0.5).len(), 4000);
+        let corner = open(1.0, 1.0);
+        let fraction = corner.len() as f64 / 4000.0;
+        assert!((fraction - 0.391).abs() < 0.03, "{}", fraction);
+        // and what is left is on the side towards the center
+        assert!(corner
+            .iter()
+            .all(|ray| ray.origin.x + ray.origin.y <= 1e-12));
+    }
+}
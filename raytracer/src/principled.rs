@@ -0,0 +1,401 @@
+use crate::dielectric::Dielectric;
+use crate::microfacet::TrowbridgeReitz;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::utils::reflect;
+use crate::warp::{cosine_hemisphere_pdf, sample_cosine_hemisphere};
+use crate::{constant_texture::ConstantTexture, texture::Texture};
+use crate::{Color, HitRecord, Material, Ray, ScatterRecord, Vec3};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// The Disney principled BSDF (Burley 2012, 2015): a diffuse base with sheen, a
+// specular layer that turns into a metal with `metallic`, a clear coat on top and
+// rough glass below with `transmission`. Parameters are in [0, 1].
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: f64,
+    pub roughness: f64,
+    // the reflectance of the dielectric specular, 0.5 for an index of refraction of 1.5
+    pub specular: f64,
+    // how much the dielectric specular takes the hue of the base color
+    pub specular_tint: f64,
+    // a soft rim for cloth
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    pub clearcoat: f64,
+    // 0 for a satin, 1 for a glossy clear coat
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    // stretches the highlights along the tangent
+    pub anisotropic: f64,
+    // of the glass with `transmission`
+    pub ior: f64,
+}
+
+// the lobes in the order `Principled::lobe_probabilities` gives them
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const CLEARCOAT: usize = 2;
+const TRANSMISSION: usize = 3;
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+fn lerp(t: f64, a: Color, b: Color) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+fn luminance(color: Color) -> f64 {
+    color * Color::new(0.2126, 0.7152, 0.0722)
+}
+
+// The GTR1 (Berry) distribution of the clear coat, with its long tails
+fn gtr1(cos_theta: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta * cos_theta))
+}
+
+fn sample_gtr1(u: (f64, f64), alpha: f64) -> Vec3 {
+    let a2 = alpha * alpha;
+    let cos_theta = ((1.0 - a2.powf(1.0 - u.0)) / (1.0 - a2)).max(0.0).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+impl Principled {
+    // a plastic-like material of `base_color`
+    pub fn new(base_color: Color) -> Self {
+        Self::with_texture(Arc::new(ConstantTexture::new(base_color)))
+    }
+
+    pub fn with_texture(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            anisotropic: 0.0,
+            ior: 1.5,
+        }
+    }
+
+    fn distribution(&self) -> TrowbridgeReitz {
+        let aspect = (1.0 - 0.9 * self.anisotropic).sqrt();
+        let alpha = self.roughness * self.roughness;
+        // never quite smooth, so that all lobes can be evaluated
+        TrowbridgeReitz::new((alpha / aspect).max(1e-3), (alpha * aspect).max(1e-3))
+    }
+
+    fn glass(&self) -> Dielectric {
+        Dielectric::new(self.ior).with_roughness(self.distribution())
+    }
+
+    fn clearcoat_alpha(&self) -> f64 {
+        0.1 * (1.0 - self.clearcoat_gloss) + 0.001 * self.clearcoat_gloss
+    }
+
+    // the weights of the diffuse and the glass under the specular
+    fn diffuse_weight(&self) -> f64 {
+        (1.0 - self.metallic) * (1.0 - self.transmission)
+    }
+
+    fn transmission_weight(&self) -> f64 {
+        (1.0 - self.metallic) * self.transmission
+    }
+
+    // The specular color head-on, from the dielectric's reflectance to the metal's color
+    fn specular_color(&self, base: Color) -> Color {
+        let tint = if luminance(base) > 0.0 {
+            base / luminance(base)
+        } else {
+            Color::ones()
+        };
+        let dielectric = lerp(self.specular_tint, Color::ones(), tint) * (self.specular * 0.08);
+        lerp(self.metallic, dielectric, base)
+    }
+
+    // Inside something that transmits there is only the glass. Seen from outside the
+    // lobes are picked by roughly how much light they reflect towards `wo`.
+    fn lobe_probabilities(&self, base: Color, wo: Vec3, front: bool) -> Option<[f64; 4]> {
+        if !front && self.transmission > 0.0 {
+            return Some([0.0, 0.0, 0.0, 1.0]);
+        }
+        let fresnel = schlick_weight(wo.z);
+        let mut weights = [0.0; 4];
+        weights[DIFFUSE] = self.diffuse_weight() * (luminance(base) + 0.25 * self.sheen);
+        weights[SPECULAR] = (1.0 - self.transmission_weight())
+            * luminance(lerp(fresnel, self.specular_color(base), Color::ones()));
+        weights[CLEARCOAT] = 0.25 * self.clearcoat * (0.04 + 0.96 * fresnel);
+        weights[TRANSMISSION] = self.transmission_weight();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        for weight in &mut weights {
+            *weight /= total;
+        }
+        Some(weights)
+    }
+
+    fn eta(&self, front: bool) -> f64 {
+        if front {
+            self.ior
+        } else {
+            1.0 / self.ior
+        }
+    }
+
+    // The BSDF for `wo` and `wi` in the shading frame, with `wo` above the surface
+    pub fn eval(&self, base: Color, wo: Vec3, wi: Vec3, front: bool) -> Color {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Color::zero();
+        }
+        let glass = self.glass().eval(wo, wi, self.eta(front));
+        if !front && self.transmission > 0.0 {
+            return Color::ones() * glass;
+        }
+        if wi.z < 0.0 {
+            // light entering the glass takes its color
+            return base * (self.transmission_weight() * glass);
+        }
+        let wm = (wo + wi).unit();
+        let cos_d = wi * wm;
+        let mut f = Color::ones() * (self.transmission_weight() * glass);
+
+        if self.diffuse_weight() > 0.0 {
+            let (fl, fv) = (schlick_weight(wi.z), schlick_weight(wo.z));
+            // retro-reflection at grazing angles of rough surfaces
+            let rr = 2.0 * self.roughness * cos_d * cos_d;
+            let retro = rr * (fl + fv + fl * fv * (rr - 1.0));
+            let diffuse = base * (((1.0 - fl / 2.0) * (1.0 - fv / 2.0) + retro) / PI);
+            let tint = if luminance(base) > 0.0 {
+                base / luminance(base)
+            } else {
+                Color::ones()
+            };
+            let sheen =
+                lerp(self.sheen_tint, Color::ones(), tint) * (self.sheen * schlick_weight(cos_d));
+            f += (diffuse + sheen) * self.diffuse_weight();
+        }
+
+        let distribution = self.distribution();
+        let fresnel = lerp(
+            schlick_weight(cos_d),
+            self.specular_color(base),
+            Color::ones(),
+        );
+        let dg = distribution.d(wm) * distribution.g(wo, wi);
+        f += fresnel * ((1.0 - self.transmission_weight()) * dg / (4.0 * wo.z * wi.z));
+
+        if self.clearcoat > 0.0 {
+            let coat = TrowbridgeReitz::new(0.25, 0.25);
+            let d = gtr1(wm.z, self.clearcoat_alpha());
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            let g = coat.g1(wo) * coat.g1(wi);
+            f += Color::ones() * (0.25 * self.clearcoat * d * fresnel * g / (4.0 * wo.z * wi.z));
+        }
+        f
+    }
+
+    // The density of `sample` choosing `wi`
+    pub fn pdf(&self, base: Color, wo: Vec3, wi: Vec3, front: bool) -> f64 {
+        let probabilities = match self.lobe_probabilities(base, wo, front) {
+            Some(probabilities) => probabilities,
+            None => return 0.0,
+        };
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = probabilities[TRANSMISSION] * self.glass().pdf(wo, wi, self.eta(front));
+        if wi.z > 0.0 {
+            let wm = (wo + wi).unit();
+            pdf += probabilities[DIFFUSE] * cosine_hemisphere_pdf(wi.z);
+            pdf += probabilities[SPECULAR] * self.distribution().pdf(wo, wm) / (4.0 * (wo * wm));
+            pdf += probabilities[CLEARCOAT] * gtr1(wm.z, self.clearcoat_alpha()) * wm.z
+                / (4.0 * (wo * wm));
+        }
+        pdf
+    }
+
+    // `wi` from one of the lobes, picked by `uc`
+    pub fn sample(
+        &self,
+        base: Color,
+        wo: Vec3,
+        front: bool,
+        u: (f64, f64),
+        uc: f64,
+    ) -> Option<Vec3> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let probabilities = self.lobe_probabilities(base, wo, front)?;
+        let mut lobe = TRANSMISSION;
+        let mut cdf = 0.0;
+        for (i, &probability) in probabilities.iter().enumerate() {
+            if probability > 0.0 && uc < cdf + probability {
+                lobe = i;
+                break;
+            }
+            cdf += probability;
+        }
+        let wi = match lobe {
+            DIFFUSE => sample_cosine_hemisphere(u),
+            SPECULAR => reflect(-wo, self.distribution().sample_wm(wo, u)),
+            CLEARCOAT => reflect(-wo, sample_gtr1(u, self.clearcoat_alpha())),
+            _ => {
+                // the rest of `uc` decides between reflection and refraction
+                let uc = ((uc - cdf) / probabilities[TRANSMISSION]).clamp(0.0, 1.0);
+                self.glass().sample(wo, self.eta(front), u, uc)?.0
+            }
+        };
+        if lobe != TRANSMISSION && wi.z <= 0.0 {
+            return None;
+        }
+        Some(wi)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let base = self
+            .base_color
+            .value(hit_record.u, hit_record.v, hit_record.p);
+        let onb = Onb::from_hit(&hit_record);
+        let wo = onb.world_to_local(-ray_in.direction.unit());
+        let front = hit_record.front;
+        let wi = self.sample(base, wo, front, sampler.get_2d(), sampler.get_1d())?;
+        let pdf = self.pdf(base, wo, wi, front);
+        if pdf == 0.0 {
+            return None;
+        }
+        Some(ScatterRecord::Diffuse {
+            scattered: Ray::new(hit_record.p, onb.local(wi)),
+            attenuation: self.eval(base, wo, wi, front) * (wi.z.abs() / pdf),
+            pdf,
+        })
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let base = self
+            .base_color
+            .value(hit_record.u, hit_record.v, hit_record.p);
+        let onb = Onb::from_hit(hit_record);
+        let wi = onb.world_to_local(wi);
+        let f = self.eval(base, onb.world_to_local(wo), wi, hit_record.front);
+        f * wi.z.abs()
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let base = self
+            .base_color
+            .value(hit_record.u, hit_record.v, hit_record.p);
+        let onb = Onb::from_hit(hit_record);
+        let (wo, wi) = (onb.world_to_local(wo), onb.world_to_local(wi));
+        self.pdf(base, wo, wi, hit_record.front)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warp::{sample_uniform_sphere, uniform_sphere_pdf};
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_sampling_matches_eval() {
+        let base = Color::new(0.8, 0.4, 0.2);
+        let materials = [
+            Principled::new(base),
+            Principled {
+                metallic: 1.0,
+                roughness: 0.3,
+                anisotropic: 0.8,
+                ..Principled::new(base)
+            },
+            Principled {
+                sheen: 1.0,
+                clearcoat: 1.0,
+                clearcoat_gloss: 0.5,
+                roughness: 0.8,
+                ..Principled::new(base)
+            },
+            Principled {
+                transmission: 0.7,
+                // rough enough for the uniform estimate to converge
+                roughness: 0.5,
+                specular_tint: 0.5,
+                ..Principled::new(base)
+            },
+        ];
+        let mut rng = SmallRng::seed_from_u64(1);
+        let wo = Vec3::new(0.3, -0.4, 0.7).unit();
+        for (i, material) in materials.iter().enumerate() {
+            for &front in &[true, false] {
+                let n = 200_000;
+                let (mut sampled, mut uniform, mut pdf_integral) =
+                    (Color::zero(), Color::zero(), 0.0);
+                for _ in 0..n {
+                    let u = (rng.gen(), rng.gen());
+                    if let Some(wi) = material.sample(base, wo, front, u, rng.gen()) {
+                        let pdf = material.pdf(base, wo, wi, front);
+                        assert!(pdf > 0.0);
+                        sampled += material.eval(base, wo, wi, front) * (wi.z.abs() / pdf);
+                    }
+                    let wi = sample_uniform_sphere((rng.gen(), rng.gen()));
+                    let f = material.eval(base, wo, wi, front);
+                    uniform += f * (wi.z.abs() / uniform_sphere_pdf());
+                    pdf_integral += material.pdf(base, wo, wi, front) / uniform_sphere_pdf();
+                }
+                let (sampled, uniform) = (sampled / n as f64, uniform / n as f64);
+                assert!(
+                    (sampled - uniform).length() < 0.03,
+                    "material {}: {:?} {:?}",
+                    i,
+                    sampled,
+                    uniform
+                );
+                // no more light leaves than comes in
+                assert!(sampled.x < 1.0 && sampled.y < 1.0 && sampled.z < 1.0);
+                assert!(pdf_integral / (n as f64) < 1.02);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lobe_probabilities() {
+        let wo = Vec3::new(0.0, 0.0, 1.0);
+        let plastic = Principled::new(Color::new(0.5, 0.5, 0.5));
+        let p = plastic
+            .lobe_probabilities(Color::ones() * 0.5, wo, true)
+            .unwrap();
+        assert!((p.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!(p[DIFFUSE] > p[SPECULAR] && p[CLEARCOAT] == 0.0 && p[TRANSMISSION] == 0.0);
+        let metal = Principled {
+            metallic: 1.0,
+            ..Principled::new(Color::ones())
+        };
+        let p = metal.lobe_probabilities(Color::ones(), wo, true).unwrap();
+        assert_eq!(p[SPECULAR], 1.0);
+        let glass = Principled {
+            transmission: 1.0,
+            ..Principled::new(Color::ones())
+        };
+        let p = glass.lobe_probabilities(Color::ones(), wo, false).unwrap();
+        assert_eq!(p, [0.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -4,11 +4,21 @@ use crate::{Point3, Vec3};
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    // when the ray is sent while the shutter is open, from 0 to 1
+    pub time: f64,
+    // the one wavelength in nm the ray carries once a dispersive medium split it,
+    // `None` while it carries all of red, green and blue
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            time: 0.0,
+            wavelength: None,
+        }
     }
 
     pub fn at(&self, t: f64) -> Point3 {
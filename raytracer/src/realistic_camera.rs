@@ -0,0 +1,557 @@
+// A camera made of real lens elements, after the realistic camera of pbrt.
+// Rays are traced from the film through every spherical surface of a lens
+// prescription, which gives the lens' own distortion, vignetting and focus
+// breathing instead of the ideal thin lens of `PerspectiveCamera`.
+//
+// The lens lives in its own space with the film at z = 0 and the scene
+// towards -z, so that the camera frame maps it to the scene as it is.
+
+use crate::camera::{Camera, Frame};
+use crate::fresnel::refract;
+use crate::sampler::Sampler;
+use crate::{Point3, Ray, Vec3};
+
+// radial segments of the film, each with its own exit pupil
+const PUPIL_SEGMENTS: usize = 64;
+// rays per axis traced to find the exit pupil of a segment
+const PUPIL_GRID: usize = 128;
+// tries to find a point of the exit pupil that lets the ray through
+const PUPIL_TRIES: usize = 8;
+// offsets of the retries within the pupil bounds, the R2 sequence of Roberts 2018
+const PUPIL_OFFSET: (f64, f64) = (0.754_877_666_246_692_8, 0.569_840_290_998_053_3);
+
+// One surface of the prescription, in scene units. Scene units are taken to be meters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LensElement {
+    // 0 for the aperture stop, positive when the center is on the film side
+    pub curvature_radius: f64,
+    // distance to the next surface towards the film
+    pub thickness: f64,
+    // index of refraction of the medium on the film side, 0 or 1 for air
+    pub eta: f64,
+    pub aperture_radius: f64,
+}
+
+// Reads a prescription in pbrt's format: one surface per line from the front
+// of the lens, with radius, thickness, index of refraction and aperture diameter
+// in millimeters. Lines starting with '#' are comments.
+pub fn load_lens(path: &str) -> Result<Vec<LensElement>, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("cannot read lens {}: {}", path, e))?;
+    let mut elements = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .ok()
+            .filter(|values| values.len() == 4)
+            .ok_or_else(|| {
+                format!(
+                    "{}:{}: expected radius, thickness, ior and aperture",
+                    path,
+                    number + 1
+                )
+            })?;
+        elements.push(LensElement {
+            curvature_radius: values[0] / 1000.0,
+            thickness: values[1] / 1000.0,
+            eta: values[2],
+            aperture_radius: values[3] / 2000.0,
+        });
+    }
+    if elements.is_empty() {
+        return Err(format!("lens {} has no surfaces", path));
+    }
+    Ok(elements)
+}
+
+// Bounds of the rear element area that lets light through to one film segment,
+// for a film point on the +x axis
+#[derive(Clone, Debug)]
+struct ExitPupil {
+    min: (f64, f64),
+    max: (f64, f64),
+    // the part of the bounds that light actually passes
+    area: f64,
+}
+
+impl ExitPupil {
+    // chance that one of the `PUPIL_TRIES` points of the bounds lets light through
+    fn found(&self) -> f64 {
+        let bounds = (self.max.0 - self.min.0) * (self.max.1 - self.min.1);
+        if bounds <= 0.0 {
+            return 0.0;
+        }
+        let miss = (1.0 - self.area / bounds).clamp(0.0, 1.0);
+        1.0 - miss.powi(PUPIL_TRIES as i32)
+    }
+}
+
+pub struct RealisticCamera {
+    pub frame: Frame,
+    elements: Vec<LensElement>,
+    film_width: f64,
+    film_height: f64,
+    exit_pupils: Vec<ExitPupil>,
+}
+
+impl RealisticCamera {
+    // `aperture_diameter` and `film_diagonal` in millimeters, `focus_dist` from the film
+    pub fn new(
+        frame: Frame,
+        mut elements: Vec<LensElement>,
+        aperture_diameter: Option<f64>,
+        focus_dist: f64,
+        film_diagonal: f64,
+        aspect_ratio: f64,
+    ) -> Result<Self, String> {
+        if let Some(diameter) = aperture_diameter {
+            let stop = elements
+                .iter_mut()
+                .find(|element| element.curvature_radius == 0.0)
+                .ok_or("the lens has no aperture stop to set the aperture of")?;
+            let radius = diameter / 2000.0;
+            if radius > stop.aperture_radius {
+                return Err(format!(
+                    "the aperture of {} mm is wider than the lens allows ({} mm)",
+                    diameter,
+                    stop.aperture_radius * 2000.0
+                ));
+            }
+            stop.aperture_radius = radius;
+        }
+        let film_diagonal = film_diagonal / 1000.0;
+        elements.last_mut().unwrap().thickness = 0.0;
+        let mut camera = Self {
+            frame,
+            elements,
+            film_width: film_diagonal * aspect_ratio / (aspect_ratio * aspect_ratio + 1.0).sqrt(),
+            film_height: film_diagonal / (aspect_ratio * aspect_ratio + 1.0).sqrt(),
+            exit_pupils: vec![],
+        };
+        let film_distance = camera.focus_thick_lens(focus_dist)?;
+        camera.elements.last_mut().unwrap().thickness = film_distance;
+        camera.exit_pupils = (0..PUPIL_SEGMENTS)
+            .map(|i| {
+                let radius = film_diagonal / 2.0;
+                camera.bound_exit_pupil(
+                    radius * i as f64 / PUPIL_SEGMENTS as f64,
+                    radius * (i + 1) as f64 / PUPIL_SEGMENTS as f64,
+                )
+            })
+            .collect();
+        if camera.exit_pupils[0].area == 0.0 {
+            return Err("no light passes through the lens to the center of the film".to_string());
+        }
+        Ok(camera)
+    }
+
+    fn film_diagonal(&self) -> f64 {
+        (self.film_width * self.film_width + self.film_height * self.film_height).sqrt()
+    }
+
+    fn rear_z(&self) -> f64 {
+        -self.elements.last().unwrap().thickness
+    }
+
+    fn front_z(&self) -> f64 {
+        -self
+            .elements
+            .iter()
+            .map(|element| element.thickness)
+            .sum::<f64>()
+    }
+
+    // Follows a ray from the film out of the front of the lens
+    fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = ray.clone();
+        let mut z = 0.0;
+        for (i, element) in self.elements.iter().enumerate().rev() {
+            z -= element.thickness;
+            let normal = self.intersect(element, z, &mut ray)?;
+            if let Some(normal) = normal {
+                // the medium in front of the surface
+                let eta_t = i.checked_sub(1).map_or(1.0, |i| air(self.elements[i].eta));
+                ray.direction = refract(-ray.direction.unit(), normal, eta_t / air(element.eta))?;
+            }
+        }
+        Some(ray)
+    }
+
+    // Follows a ray from the scene out of the back of the lens
+    fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut ray = ray.clone();
+        let mut z = self.front_z();
+        for (i, element) in self.elements.iter().enumerate() {
+            let normal = self.intersect(element, z, &mut ray)?;
+            if let Some(normal) = normal {
+                // the medium in front of the surface
+                let eta_i = i.checked_sub(1).map_or(1.0, |i| air(self.elements[i].eta));
+                ray.direction = refract(-ray.direction.unit(), normal, air(element.eta) / eta_i)?;
+            }
+            z += element.thickness;
+        }
+        Some(ray)
+    }
+
+    // Moves the ray to where it meets the surface at `z`, returning the normal
+    // of a curved surface towards the ray, or `None` if the ray is blocked
+    fn intersect(&self, element: &LensElement, z: f64, ray: &mut Ray) -> Option<Option<Vec3>> {
+        let (t, normal) = if element.curvature_radius == 0.0 {
+            if ray.direction.z == 0.0 {
+                return None;
+            }
+            ((z - ray.origin.z) / ray.direction.z, None)
+        } else {
+            let (t, normal) = intersect_spherical(element.curvature_radius, z, ray)?;
+            (t, Some(normal))
+        };
+        if t < 0.0 {
+            return None;
+        }
+        let hit = ray.at(t);
+        if hit.x * hit.x + hit.y * hit.y > element.aperture_radius * element.aperture_radius {
+            return None;
+        }
+        ray.origin = hit;
+        Some(normal)
+    }
+
+    // Thick lens approximation: where rays parallel to the axis cross it and
+    // where they seem to bend, on each side of the lens
+    fn cardinal_points(&self) -> Result<(ThickLensSide, ThickLensSide), String> {
+        let x = 0.001 * self.film_diagonal();
+        let from_scene = Ray::new(
+            Point3::new(x, 0.0, self.front_z() - 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let from_film = Ray::new(Point3::new(x, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        match (
+            self.trace_from_scene(&from_scene),
+            self.trace_from_film(&from_film),
+        ) {
+            (Some(image), Some(object)) => Ok((
+                ThickLensSide::new(&from_scene, &image),
+                ThickLensSide::new(&from_film, &object),
+            )),
+            _ => Err("rays parallel to the axis do not pass through the lens".to_string()),
+        }
+    }
+
+    // Distance between the rear element and the film for objects at `focus_dist`
+    // from the film to be in focus, measured with the rear element on the film
+    fn focus_thick_lens(&self, focus_dist: f64) -> Result<f64, String> {
+        let (image, object) = self.cardinal_points()?;
+        let f = image.focal_z - image.principal_z;
+        // with the object at a distance s from its principal plane, the image is at
+        // f s / (s - f) from the other one, and both add up to `focus_dist`
+        let g = focus_dist - (image.principal_z - object.principal_z);
+        let discriminant = g * g - 4.0 * g * f;
+        if f <= 0.0 || g <= 0.0 || discriminant < 0.0 {
+            return Err(format!("the lens cannot focus at {}", focus_dist));
+        }
+        let s = (g + discriminant.sqrt()) / 2.0;
+        Ok(image.principal_z + f * s / (s - f))
+    }
+
+    // Traces a grid of rays from film points between `r0` and `r1` on the x axis
+    // to the plane of the rear element, and bounds those that get through.
+    fn bound_exit_pupil(&self, r0: f64, r1: f64) -> ExitPupil {
+        let extent = 1.5 * self.elements.last().unwrap().aperture_radius;
+        let cell = 2.0 * extent / PUPIL_GRID as f64;
+        let n = PUPIL_GRID * PUPIL_GRID;
+        let mut min = (f64::INFINITY, f64::INFINITY);
+        let mut max = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        let mut hits = 0;
+        for i in 0..n {
+            let film = Point3::new(r0 + (r1 - r0) * (i as f64 + 0.5) / n as f64, 0.0, 0.0);
+            let x = -extent + cell * ((i % PUPIL_GRID) as f64 + 0.5);
+            let y = -extent + cell * ((i / PUPIL_GRID) as f64 + 0.5);
+            let rear = Point3::new(x, y, self.rear_z());
+            if self.trace_from_film(&Ray::new(film, rear - film)).is_some() {
+                hits += 1;
+                min = (min.0.min(x), min.1.min(y));
+                max = (max.0.max(x), max.1.max(y));
+            }
+        }
+        if hits == 0 {
+            return ExitPupil {
+                min: (0.0, 0.0),
+                max: (0.0, 0.0),
+                area: 0.0,
+            };
+        }
+        // the grid only finds the pupil to within a cell
+        ExitPupil {
+            min: (min.0 - cell, min.1 - cell),
+            max: (max.0 + cell, max.1 + cell),
+            area: hits as f64 / n as f64 * (2.0 * extent) * (2.0 * extent),
+        }
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        // the lens turns the image upside down on the film
+        let film = Point3::new(
+            -(s - 0.5) * self.film_width,
+            -(t - 0.5) * self.film_height,
+            0.0,
+        );
+        let radius = (film.x * film.x + film.y * film.y).sqrt();
+        let segment = ((radius / (self.film_diagonal() / 2.0) * PUPIL_SEGMENTS as f64) as usize)
+            .min(PUPIL_SEGMENTS - 1);
+        let pupil = &self.exit_pupils[segment];
+        if pupil.area == 0.0 {
+            return None;
+        }
+        // the pupils are found for points on the x axis, rotate them to the film point
+        let (sin, cos) = if radius > 0.0 {
+            (film.y / radius, film.x / radius)
+        } else {
+            (0.0, 1.0)
+        };
+        // The retries are rotations of a single sample, so the camera always draws the
+        // same dimensions. When all of them miss the pupil, the sample is dropped,
+        // which the weight below makes up for.
+        let (u, accept) = (sampler.get_2d(), sampler.get_1d());
+        let ray = (0..PUPIL_TRIES).find_map(|i| {
+            let u = (
+                (u.0 + i as f64 * PUPIL_OFFSET.0).fract(),
+                (u.1 + i as f64 * PUPIL_OFFSET.1).fract(),
+            );
+            let x = pupil.min.0 + (pupil.max.0 - pupil.min.0) * u.0;
+            let y = pupil.min.1 + (pupil.max.1 - pupil.min.1) * u.1;
+            let rear = Point3::new(cos * x - sin * y, sin * x + cos * y, self.rear_z());
+            self.trace_from_film(&Ray::new(film, rear - film))
+        })?;
+        // Rays are found uniformly in the part of the pupil that lets light through,
+        // so the film gets less light than the center only by dropping some of them:
+        // in proportion to the area of the pupil and to cos⁴ of the angle of the ray,
+        // and to how likely the tries are to find a ray at all, relative to the center.
+        let cos_theta = ray.direction.unit().z.abs();
+        let center = &self.exit_pupils[0];
+        let weight = pupil.area / center.area * cos_theta.powi(4) * center.found() / pupil.found();
+        if accept >= weight {
+            return None;
+        }
+        let frame = &self.frame;
+        let to_scene = |v: Vec3| frame.u * v.x + frame.v * v.y + frame.w * v.z;
+        Some(Ray::new(
+            frame.origin + to_scene(ray.origin),
+            to_scene(ray.direction),
+        ))
+    }
+}
+
+// The axial positions found by tracing a ray parallel to the axis through the lens
+struct ThickLensSide {
+    focal_z: f64,
+    principal_z: f64,
+}
+
+impl ThickLensSide {
+    fn new(input: &Ray, output: &Ray) -> Self {
+        let focal_t = -output.origin.x / output.direction.x;
+        let principal_t = (input.origin.x - output.origin.x) / output.direction.x;
+        Self {
+            focal_z: output.at(focal_t).z,
+            principal_z: output.at(principal_t).z,
+        }
+    }
+}
+
+fn air(eta: f64) -> f64 {
+    if eta == 0.0 {
+        1.0
+    } else {
+        eta
+    }
+}
+
+// Where the ray meets the sphere of the given radius whose vertex is at `z`,
+// and the normal facing the ray
+fn intersect_spherical(radius: f64, z: f64, ray: &Ray) -> Option<(f64, Vec3)> {
+    let o = ray.origin - Vec3::new(0.0, 0.0, z + radius);
+    let d = ray.direction;
+    let a = d * d;
+    let b = 2.0 * (d * o);
+    let c = o * o - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = if b < 0.0 {
+        -0.5 * (b - discriminant.sqrt())
+    } else {
+        -0.5 * (b + discriminant.sqrt())
+    };
+    let (t0, t1) = (q / a, c / q);
+    let (t0, t1) = (t0.min(t1), t0.max(t1));
+    // the surface is the half of the sphere around the vertex
+    let t = if (d.z > 0.0) ^ (radius < 0.0) { t0 } else { t1 };
+    if t < 0.0 {
+        return None;
+    }
+    let normal = (o + d * t).unit();
+    Some((t, if normal * d > 0.0 { -normal } else { normal }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    fn dgauss(aperture_diameter: Option<f64>, focus_dist: f64) -> RealisticCamera {
+        let frame = Frame::new(
+            Point3::zero(),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let elements = load_lens("../data/lenses/dgauss.50mm.dat").unwrap();
+        RealisticCamera::new(frame, elements, aperture_diameter, focus_dist, 35.0, 1.0).unwrap()
+    }
+
+    #[test]
+    fn test_load_lens() {
+        let elements = load_lens("../data/lenses/dgauss.50mm.dat").unwrap();
+        assert_eq!(elements.len(), 11);
+        assert_eq!(
+            elements[5],
+            LensElement {
+                curvature_radius: 0.0,
+                thickness: 0.0045,
+                eta: 1.0,
+                aperture_radius: 0.00855,
+            }
+        );
+        assert!(load_lens("../data/scene_10.json").is_err());
+    }
+
+    #[test]
+    fn test_focal_length() {
+        let camera = dgauss(None, 10.0);
+        let (image, object) = camera.cardinal_points().unwrap();
+        let f = image.focal_z - image.principal_z;
+        assert!((f - 0.05).abs() < 0.005, "{}", f);
+        // and the same on the other side, both in air
+        assert!(((object.principal_z - object.focal_z) - f).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_focus() {
+        // rays from the center of the film meet again on the axis around the focus
+        // distance, the ones close to the axis right at it and the others a little
+        // closer to the lens because of spherical aberration
+        for &focus_dist in &[0.5, 2.0] {
+            let camera = dgauss(Some(5.0), focus_dist);
+            let mut sampler = IndependentSampler::new(1);
+            let distances: Vec<f64> = (0..200)
+                .filter_map(|_| camera.get_ray(0.5, 0.5, &mut sampler))
+                .map(|ray| {
+                    // the frame puts the film at the origin and the lens towards -z
+                    let t = -ray.origin.x / ray.direction.x;
+                    -ray.at(t).z
+                })
+                .collect();
+            assert!(distances.len() > 150);
+            assert!(distances
+                .iter()
+                .all(|&d| d <= focus_dist * 1.001 && d > focus_dist * 0.97));
+            let farthest = distances.iter().cloned().fold(0.0, f64::max);
+            assert!((farthest - focus_dist).abs() < 0.001 * focus_dist);
+        }
+        let lens = |aperture_diameter, focus_dist| {
+            RealisticCamera::new(
+                Frame::new(Point3::zero(), Point3::new(0.0, 0.0, -1.0), Vec3::ones()),
+                load_lens("../data/lenses/dgauss.50mm.dat").unwrap(),
+                aperture_diameter,
+                focus_dist,
+                35.0,
+                1.0,
+            )
+        };
+        // closer than four focal lengths, or wider than the stop
+        assert!(lens(None, 0.1).is_err());
+        assert!(lens(Some(30.0), 10.0).is_err());
+    }
+
+    #[test]
+    fn test_focus_breathing() {
+        // focusing closer moves the film away from the lens, which narrows the view
+        let angle = |camera: &RealisticCamera| {
+            let ray = camera
+                .get_ray(1.0, 0.5, &mut IndependentSampler::new(1))
+                .or_else(|| camera.get_ray(0.9, 0.5, &mut IndependentSampler::new(2)))
+                .unwrap();
+            ray.direction.unit().x.abs().asin()
+        };
+        let far = dgauss(Some(2.0), 100.0);
+        let near = dgauss(Some(2.0), 0.5);
+        assert!(near.rear_z() < far.rear_z());
+        assert!(angle(&near) < angle(&far));
+    }
+
+    #[test]
+    fn test_vignetting() {
+        // wide open, less light reaches the corners than the center
+        let camera = dgauss(None, 10.0);
+        let mut sampler = IndependentSampler::new(1);
+        let mut open = |s, t| {
+            (0..2000)
+                .filter(|_| camera.get_ray(s, t, &mut sampler).is_some())
+                .count()
+        };
+        let center = open(0.5, 0.5);
+        let corner = open(0.0, 1.0);
+        assert!(center > 1900);
+        assert!(corner < center / 2, "{} {}", corner, center);
+    }
+
+    // counts the dimensions drawn from an independent sampler
+    #[derive(Clone)]
+    struct CountingSampler(IndependentSampler, u32);
+
+    impl Sampler for CountingSampler {
+        fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+            self.0.start_pixel_sample(x, y, index);
+        }
+
+        fn get_1d(&mut self) -> f64 {
+            self.1 += 1;
+            self.0.get_1d()
+        }
+
+        fn get_2d(&mut self) -> (f64, f64) {
+            self.1 += 2;
+            self.0.get_2d()
+        }
+
+        fn clone_box(&self) -> Box<dyn Sampler> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn test_fixed_dimensions() {
+        // in the corner most points of the pupil bounds are blocked,
+        // yet every sample draws the same dimensions whether it is kept or not
+        let camera = dgauss(None, 10.0);
+        let mut sampler = CountingSampler(IndependentSampler::new(1), 0);
+        let (mut kept, mut dropped) = (0, 0);
+        for _ in 0..500 {
+            sampler.1 = 0;
+            match camera.get_ray(0.0, 1.0, &mut sampler) {
+                Some(_) => kept += 1,
+                None => dropped += 1,
+            }
+            assert_eq!(sampler.1, 3);
+        }
+        assert!(kept > 0 && dropped > 0);
+    }
+}
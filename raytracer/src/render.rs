@@ -0,0 +1,710 @@
+use crate::camera::Camera;
+use crate::checkpoint::Checkpoint;
+use crate::film::{Film, PixelStats};
+use crate::integrator::Integrator;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::scene::Scene;
+use crate::utils::clamp3;
+use crate::Color;
+use indicatif::ProgressBar;
+use std::ops::Range;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    pub integrator: Integrator,
+    pub sampler: SamplerKind,
+    pub seed: u64,
+}
+
+// A rectangle of the final image, rows counted from the top
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub index: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct TileResult {
+    pub tile: Tile,
+    pub pass: u32,
+    pub stats: Vec<PixelStats>,
+    pub duration: Duration,
+    pub worker: usize,
+}
+
+// Square tiles of `tile_size` covering the image, ordered in a spiral
+// starting from the center, where the interesting content usually is.
+pub fn spiral_tiles(width: u32, height: u32, tile_size: u32) -> Vec<Tile> {
+    let columns = ((width - 1) / tile_size + 1) as i64;
+    let rows = ((height - 1) / tile_size + 1) as i64;
+    let total = (columns * rows) as usize;
+
+    let mut tiles = Vec::with_capacity(total);
+    let (mut column, mut row) = ((columns - 1) / 2, (rows - 1) / 2);
+    // walk right, down, left, up with run lengths 1, 1, 2, 2, 3, 3, ...
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let (mut direction, mut run) = (0, 1);
+    while tiles.len() < total {
+        for _ in 0..2 {
+            for _ in 0..run {
+                if (0..columns).contains(&column) && (0..rows).contains(&row) {
+                    let (x, y) = (column as u32 * tile_size, row as u32 * tile_size);
+                    tiles.push(Tile {
+                        index: tiles.len(),
+                        x,
+                        y,
+                        width: tile_size.min(width - x),
+                        height: tile_size.min(height - y),
+                    });
+                }
+                column += directions[direction].0;
+                row += directions[direction].1;
+            }
+            direction = (direction + 1) % 4;
+        }
+        run += 1;
+    }
+    tiles
+}
+
+pub fn render_tile(
+    scene: &Scene,
+    camera: &dyn Camera,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    tile: &Tile,
+    // indices of the samples to take in every pixel
+    samples: Range<u32>,
+    active: Option<&[bool]>,
+) -> Vec<PixelStats> {
+    let (width, height) = (settings.width, settings.height);
+    let mut stats = vec![PixelStats::default(); (tile.width * tile.height) as usize];
+    for (i, pixel) in stats.iter_mut().enumerate() {
+        let x = tile.x + i as u32 % tile.width;
+        let row = tile.y + i as u32 / tile.width;
+        if let Some(active) = active {
+            if !active[(row * width + x) as usize] {
+                continue;
+            }
+        }
+        // Be consistent with the book: v grows upwards
+        let y = height - 1 - row;
+        for sample in samples.clone() {
+            sampler.start_pixel_sample(x, row, sample);
+            let (jitter_x, jitter_y) = sampler.get_2d();
+            let target_x = x as f64 + jitter_x;
+            let target_y = y as f64 + jitter_y;
+            let u = target_x / (width as f64 - 1.0);
+            let v = target_y / (height as f64 - 1.0);
+            // black where the camera sees nothing
+            let color = match camera.get_ray(u, v, sampler) {
+                Some(mut ray) => {
+                    if scene.motion_blur {
+                        ray.time = sampler.get_1d();
+                    }
+                    settings
+                        .integrator
+                        .ray_color(scene, &ray, settings.max_depth, sampler)
+                }
+                None => Color::zero(),
+            };
+            // the film only shows [0, 1), one clamp on the final color keeps single
+            // bright samples from turning into fireflies
+            pixel.add_sample(clamp3(color));
+        }
+    }
+    stats
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    MaxSamples,
+    TimeLimit,
+    NoiseThreshold,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StopConditions {
+    // render in passes of 1, 2, 4, ... samples per pixel instead of all at once
+    pub progressive: bool,
+    pub time_limit: Option<Duration>,
+    // maximum relative variance of any pixel
+    pub noise_threshold: Option<f64>,
+    // once every pixel has `min_spp` samples, only keep sampling
+    // the pixels that are still above the noise threshold
+    pub adaptive: bool,
+    pub min_spp: u32,
+}
+
+// Where a render is in its pass schedule, everything a checkpoint needs to continue
+pub struct RenderState {
+    pub film: Film,
+    pub pass: u32,
+    // samples per pixel before the current pass and once it is finished
+    pub spp: u32,
+    pub target: u32,
+    // tiles of the current pass that are already merged into `film`
+    pub done: Vec<bool>,
+    // pixels still sampled in the current pass, `None` for all of them
+    pub active: Option<Arc<Vec<bool>>>,
+}
+
+impl RenderState {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            film: Film::new(width, height),
+            pass: 0,
+            spp: 0,
+            target: 0,
+            done: vec![],
+            active: None,
+        }
+    }
+
+    // Whether a state read from a checkpoint fits an image of `width` by `height`
+    // pixels split into `n_tiles` tiles, so that resuming cannot index past the ends
+    pub fn validate(&self, width: u32, height: u32, n_tiles: usize) -> Result<(), String> {
+        if (self.film.width, self.film.height) != (width, height) {
+            return Err(format!(
+                "the checkpoint is of a {}x{} image, not {}x{}",
+                self.film.width, self.film.height, width, height
+            ));
+        }
+        // `done` is only empty before the first pass, it is set up when a pass starts
+        let started = !self.done.is_empty() || self.spp != self.target;
+        if started && self.done.len() != n_tiles {
+            return Err(format!(
+                "the checkpoint has {} tiles, not {}",
+                self.done.len(),
+                n_tiles
+            ));
+        }
+        match &self.active {
+            Some(active) if active.len() != self.film.pixels().len() => Err(format!(
+                "the checkpoint has {} active pixels, not {}",
+                active.len(),
+                self.film.pixels().len()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct CheckpointSettings {
+    pub path: String,
+    pub interval: Duration,
+    pub seed: u64,
+    pub fingerprint: u64,
+    // the frame of the animation that is being rendered
+    pub frame: Option<u32>,
+}
+
+pub struct Renderer {
+    pub pool: rayon::ThreadPool,
+    pub scene: Arc<Scene>,
+    pub camera: Arc<dyn Camera>,
+    pub settings: RenderSettings,
+    // cloned for every tile
+    pub sampler: Arc<dyn Sampler>,
+    pub tiles: Vec<Tile>,
+    pub checkpoint: Option<CheckpointSettings>,
+}
+
+impl Renderer {
+    fn save_checkpoint(&self, state: RenderState) -> RenderState {
+        match &self.checkpoint {
+            Some(settings) => {
+                let checkpoint = Checkpoint {
+                    seed: settings.seed,
+                    fingerprint: settings.fingerprint,
+                    frame: settings.frame,
+                    state,
+                };
+                if let Err(err) = checkpoint.save(&settings.path) {
+                    eprintln!("failed to write checkpoint {}: {}", settings.path, err);
+                }
+                checkpoint.state
+            }
+            None => state,
+        }
+    }
+
+    // Render the tiles of the current pass that are not done yet, adding
+    // `target - spp` samples to every pixel of the film, or only to the pixels
+    // set in `active`. Tiles that have not started when `deadline` passes are skipped.
+    fn render_pass(
+        &self,
+        mut state: RenderState,
+        deadline: Option<Instant>,
+        last_checkpoint: &mut Instant,
+    ) -> (RenderState, Vec<TileResult>) {
+        let width = self.settings.width;
+        let (pass, samples) = (state.pass, state.spp..state.target);
+        let tiles: Vec<Tile> = self
+            .tiles
+            .iter()
+            .filter(|tile| !state.done[tile.index])
+            .filter(|tile| match &state.active {
+                Some(active) => (tile.y..tile.y + tile.height).any(|y| {
+                    let row = (y * width) as usize;
+                    active[row + tile.x as usize..row + (tile.x + tile.width) as usize]
+                        .contains(&true)
+                }),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        // tiles without any active pixel have nothing to do in this pass
+        for done in state.done.iter_mut() {
+            *done = true;
+        }
+        for tile in &tiles {
+            state.done[tile.index] = false;
+        }
+
+        let n_tiles = tiles.len();
+        let bar = ProgressBar::new(n_tiles as u64);
+
+        // create a channel to send objects between threads
+        let (tx, rx) = channel();
+
+        // tiles are queued in spiral order, idle workers steal whatever is left
+        for tile in tiles {
+            let tx = tx.clone();
+            let scene = self.scene.clone();
+            let camera = self.camera.clone();
+            let settings = self.settings;
+            let mut sampler = self.sampler.clone_box();
+            let samples = samples.clone();
+            let active = state.active.clone();
+            self.pool.spawn_fifo(move || {
+                let start = Instant::now();
+                let stats = match deadline {
+                    Some(deadline) if start > deadline => vec![],
+                    _ => render_tile(
+                        &scene,
+                        camera.as_ref(),
+                        &settings,
+                        sampler.as_mut(),
+                        &tile,
+                        samples,
+                        active.as_ref().map(|active| active.as_slice()),
+                    ),
+                };
+                let result = TileResult {
+                    tile,
+                    pass,
+                    stats,
+                    duration: start.elapsed(),
+                    worker: rayon::current_thread_index().unwrap_or(0),
+                };
+                tx.send(result).expect("failed to send result");
+            });
+        }
+
+        let mut results = Vec::with_capacity(n_tiles);
+        for result in rx.iter().take(n_tiles) {
+            if !result.stats.is_empty() {
+                state.film.merge_tile(&result.tile, &result.stats);
+                state.done[result.tile.index] = true;
+                results.push(result);
+            }
+            bar.inc(1);
+
+            if let Some(settings) = &self.checkpoint {
+                if last_checkpoint.elapsed() >= settings.interval {
+                    state = self.save_checkpoint(state);
+                    *last_checkpoint = Instant::now();
+                }
+            }
+        }
+        bar.finish();
+        (state, results)
+    }
+
+    // Render until one of the stop conditions is met, calling `on_pass` after every pass.
+    // `state` is either a fresh `RenderState` or one restored from a checkpoint.
+    pub fn render<F>(
+        &self,
+        mut state: RenderState,
+        stop: &StopConditions,
+        mut on_pass: F,
+    ) -> (Film, Vec<TileResult>, StopReason)
+    where
+        F: FnMut(&Film),
+    {
+        let start = Instant::now();
+        let deadline = stop.time_limit.map(|limit| start + limit);
+        let max_spp = self.settings.samples_per_pixel;
+        let mut last_checkpoint = Instant::now();
+        let mut results = vec![];
+
+        let reason = loop {
+            if state.spp == state.target {
+                if state.spp >= max_spp {
+                    break StopReason::MaxSamples;
+                }
+                // passes end at 1, 2, 4, 8, ... samples per pixel
+                state.target = if stop.progressive {
+                    (state.spp * 2).max(1).min(max_spp)
+                } else {
+                    max_spp
+                };
+                state.done = vec![false; self.tiles.len()];
+            }
+
+            let (next_state, mut pass_results) =
+                self.render_pass(state, deadline, &mut last_checkpoint);
+            state = next_state;
+            results.append(&mut pass_results);
+            on_pass(&state.film);
+
+            // a pass cut short by the time limit is continued when resuming
+            if state.done.iter().all(|&done| done) {
+                state.spp = state.target;
+                state.pass += 1;
+                if stop.progressive {
+                    println!(
+                        "pass {}: {} spp ({} in the least sampled pixel) after {:.1} s",
+                        state.pass,
+                        state.spp,
+                        state.film.min_samples(),
+                        start.elapsed().as_secs_f64()
+                    );
+                }
+                if let Some(threshold) = stop.noise_threshold {
+                    let noisy = state.film.count_noisy(threshold);
+                    if stop.progressive {
+                        println!("  {} pixels above the noise threshold", noisy);
+                    }
+                    if noisy == 0 {
+                        break StopReason::NoiseThreshold;
+                    }
+                    if stop.adaptive && state.spp >= stop.min_spp {
+                        state.active = Some(Arc::new(state.film.noisy_mask(threshold)));
+                    }
+                }
+            }
+            if let Some(deadline) = deadline {
+                if Instant::now() >= deadline {
+                    break StopReason::TimeLimit;
+                }
+            }
+        };
+        let state = self.save_checkpoint(state);
+        (state.film, results, reason)
+    }
+}
+
+// Print how long tiles took and how busy every worker was,
+// so an unbalanced schedule shows up as a large max / mean ratio.
+pub fn report_tile_times(results: &[TileResult], n_workers: usize) {
+    if results.is_empty() {
+        return;
+    }
+    let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+    let total: f64 = results.iter().map(|r| millis(r.duration)).sum();
+    let mean = total / results.len() as f64;
+    let min = results
+        .iter()
+        .map(|r| millis(r.duration))
+        .fold(f64::INFINITY, f64::min);
+    let max = results
+        .iter()
+        .map(|r| millis(r.duration))
+        .fold(0.0, f64::max);
+    println!(
+        "{} tiles: min {:.1} ms, mean {:.1} ms, max {:.1} ms (max / mean = {:.2})",
+        results.len(),
+        min,
+        mean,
+        max,
+        max / mean
+    );
+
+    let mut slowest: Vec<&TileResult> = results.iter().collect();
+    slowest.sort_by_key(|r| std::cmp::Reverse(r.duration));
+    for r in slowest.iter().take(5) {
+        println!(
+            "  tile {:>4} at ({:>4}, {:>4}) in pass {}: {:.1} ms",
+            r.tile.index,
+            r.tile.x,
+            r.tile.y,
+            r.pass + 1,
+            millis(r.duration)
+        );
+    }
+
+    let mut busy = vec![0.0; n_workers];
+    for r in results {
+        busy[r.worker] += millis(r.duration);
+    }
+    let busiest = busy.iter().cloned().fold(0.0, f64::max);
+    for (worker, time) in busy.iter().enumerate() {
+        println!(
+            "  worker {:>2}: busy {:.0} ms ({:.0}% of the busiest)",
+            worker,
+            time,
+            100.0 * time / busiest
+        );
+    }
+}
+
+pub fn write_tile_times(path: &str, results: &[TileResult]) -> std::io::Result<()> {
+    let mut csv = String::from("pass,index,x,y,width,height,worker,ms\n");
+    for r in results {
+        csv += &format!(
+            "{},{},{},{},{},{},{},{:.3}\n",
+            r.pass + 1,
+            r.tile.index,
+            r.tile.x,
+            r.tile.y,
+            r.tile.width,
+            r.tile.height,
+            r.worker,
+            r.duration.as_secs_f64() * 1000.0
+        );
+    }
+    std::fs::write(path, csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constant_texture::ConstantTexture;
+    use crate::diffuse_light::DiffuseLight;
+    use crate::lambertian::Lambertian;
+    use crate::perspective_camera::PerspectiveCamera;
+    use crate::scene::Background;
+    use crate::{Hittable, HittableList, Material, Point3, Sphere, Vec3};
+
+    #[test]
+    fn test_spiral_covers_image() {
+        let (width, height) = (100, 70);
+        let tiles = spiral_tiles(width, height, 32);
+        assert_eq!(tiles.len(), 4 * 3);
+        let mut covered = vec![0; (width * height) as usize];
+        for tile in &tiles {
+            for y in tile.y..tile.y + tile.height {
+                for x in tile.x..tile.x + tile.width {
+                    covered[(y * width + x) as usize] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+    }
+
+    fn test_renderer(
+        sampler: SamplerKind,
+        tile_size: u32,
+        threads: usize,
+        samples_per_pixel: u32,
+        checkpoint: Option<CheckpointSettings>,
+    ) -> Renderer {
+        let mut scene = crate::scene::example_scene(3);
+        let settings = RenderSettings {
+            width: 24,
+            height: 16,
+            samples_per_pixel,
+            max_depth: 8,
+            integrator: Integrator::Path,
+            sampler,
+            seed: 7,
+        };
+        scene.world.hittables.truncate(40);
+        Renderer {
+            pool: rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap(),
+            camera: Arc::from(scene.camera.build(1.5).unwrap()),
+            scene: Arc::new(scene),
+            settings,
+            sampler: Arc::from(sampler.build(settings.seed, settings.samples_per_pixel)),
+            tiles: spiral_tiles(settings.width, settings.height, tile_size),
+            checkpoint,
+        }
+    }
+
+    fn render_with(
+        sampler: SamplerKind,
+        tile_size: u32,
+        threads: usize,
+        stop: &StopConditions,
+    ) -> Film {
+        let renderer = test_renderer(sampler, tile_size, threads, 4, None);
+        let state = RenderState::new(renderer.settings.width, renderer.settings.height);
+        renderer.render(state, stop, |_| {}).0
+    }
+
+    #[test]
+    fn test_independent_of_tiles_and_workers() {
+        let stop = StopConditions {
+            progressive: false,
+            time_limit: None,
+            noise_threshold: None,
+            adaptive: false,
+            min_spp: 1,
+        };
+        let progressive = StopConditions {
+            progressive: true,
+            ..stop
+        };
+        for &sampler in &[
+            SamplerKind::Independent,
+            SamplerKind::Sobol,
+            SamplerKind::Halton,
+        ] {
+            let reference = render_with(sampler, 24, 1, &stop);
+            assert_eq!(
+                render_with(sampler, 5, 3, &stop).pixels(),
+                reference.pixels()
+            );
+            assert_eq!(
+                render_with(sampler, 8, 4, &stop).pixels(),
+                reference.pixels()
+            );
+            // passes of 1, 2 and 4 spp draw the same samples as a single pass
+            let film = render_with(sampler, 7, 2, &progressive);
+            for (a, b) in film.pixels().iter().zip(reference.pixels()) {
+                assert_eq!(a.count, b.count);
+                assert!((a.mean - b.mean).length() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let stop = StopConditions {
+            progressive: true,
+            time_limit: None,
+            noise_threshold: None,
+            adaptive: false,
+            min_spp: 1,
+        };
+        let path = std::env::temp_dir().join(format!("resume-{}.rtck", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let reference = render_with(SamplerKind::Sobol, 7, 2, &stop);
+
+        // stop after the 2 spp pass, the final state is written to the checkpoint
+        let checkpoint = CheckpointSettings {
+            path: path.clone(),
+            interval: Duration::from_secs(3600),
+            seed: 7,
+            fingerprint: 0,
+            frame: None,
+        };
+        let renderer = test_renderer(SamplerKind::Sobol, 7, 2, 2, Some(checkpoint));
+        let state = RenderState::new(renderer.settings.width, renderer.settings.height);
+        let (film, _, reason) = renderer.render(state, &stop, |_| {});
+        assert_eq!(reason, StopReason::MaxSamples);
+        assert_eq!(film.min_samples(), 2);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let restored = Checkpoint::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            Checkpoint::from_bytes(&restored.to_bytes())
+                .unwrap()
+                .to_bytes(),
+            bytes
+        );
+        assert_eq!((restored.state.pass, restored.state.spp), (2, 2));
+
+        let renderer = test_renderer(SamplerKind::Sobol, 7, 2, 4, None);
+        let (width, height, n_tiles) = (
+            renderer.settings.width,
+            renderer.settings.height,
+            renderer.tiles.len(),
+        );
+        assert!(restored.state.validate(width, height, n_tiles).is_ok());
+        assert!(restored.state.validate(width + 1, height, n_tiles).is_err());
+        assert!(restored.state.validate(width, height, n_tiles + 1).is_err());
+        let (film, _, _) = renderer.render(restored.state, &stop, |_| {});
+        assert_eq!(film.pixels(), reference.pixels());
+    }
+
+    #[test]
+    fn test_bright_lights_clamped_alike() {
+        // a light brighter than white seen directly, and a gray sphere lit by a sky
+        // brighter than white, whose every path carries 0.2 * 1.5 of it
+        let light: Arc<dyn Material> = Arc::new(DiffuseLight::with_texture(Arc::new(
+            ConstantTexture::new(Color::ones() * 10.0),
+        )));
+        let gray: Arc<dyn Material> = Arc::new(Lambertian::new(Color::ones() * 0.2));
+        for &(material, expected) in &[(&light, 0.999), (&gray, 0.3)] {
+            let hittables: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere {
+                center: Point3::zero(),
+                radius: 1.0,
+                material: material.clone(),
+            })];
+            let world = HittableList { hittables };
+            let scene = Scene {
+                lights: world.lights(),
+                world,
+                background: Background::Solid(Color::ones() * 1.5),
+                ..crate::scene::example_scene(0)
+            };
+            let camera = PerspectiveCamera::new(
+                Point3::new(0.0, 0.0, 5.0),
+                Point3::zero(),
+                Vec3::new(0.0, 1.0, 0.0),
+                5.0,
+                1.0,
+                0.0,
+                5.0,
+            );
+            let tile = spiral_tiles(3, 3, 3)[0];
+            for &integrator in &[Integrator::Path, Integrator::Mis, Integrator::Spectral] {
+                let settings = RenderSettings {
+                    width: 3,
+                    height: 3,
+                    samples_per_pixel: 4000,
+                    max_depth: 8,
+                    integrator,
+                    sampler: SamplerKind::Independent,
+                    seed: 1,
+                };
+                let mut sampler = settings.sampler.build(settings.seed, 4000);
+                let stats = render_tile(
+                    &scene,
+                    &camera,
+                    &settings,
+                    sampler.as_mut(),
+                    &tile,
+                    0..4000,
+                    None,
+                );
+                let mean = stats
+                    .iter()
+                    .fold(Color::zero(), |sum, pixel| sum + pixel.mean)
+                    / 9.0;
+                assert!(
+                    (mean - Color::ones() * expected).length() < 0.02,
+                    "{:?} {:?}",
+                    integrator,
+                    mean
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_at_center() {
+        let tiles = spiral_tiles(160, 160, 32);
+        assert_eq!((tiles[0].x, tiles[0].y), (64, 64));
+        assert_eq!((tiles[1].x, tiles[1].y), (96, 64));
+        for (i, tile) in tiles.iter().enumerate() {
+            assert_eq!(tile.index, i);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+// Smooth spectra for RGB colors (Jakob and Hanika 2019): the sigmoid of a quadratic
+// in the wavelength, fitted so that it has the color under white light. The fits
+// are done over a grid of colors, as pbrt-v4 does, and interpolated in between.
+
+use crate::sampled_spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::spectrum::{cie_xyz, xyz_to_balanced_rgb, CIE_Y_INTEGRAL, LAMBDA_MAX, LAMBDA_MIN};
+use crate::{Color, Vec3};
+
+// the grid has this many fits along each axis, for each of the three channels that
+// can be the largest
+const RESOLUTION: usize = 24;
+// the fit integrates over the visible range in steps of 5 nm
+const FIT_STEP: f64 = 5.0;
+const FIT_STEPS: usize = ((LAMBDA_MAX - LAMBDA_MIN) / FIT_STEP) as usize;
+
+thread_local! {
+    // fitted once by every thread that needs it, which takes a fraction of a second
+    static TABLE: RgbToSpectrumTable = RgbToSpectrumTable::fit(RESOLUTION);
+}
+
+// The wavelength mapped to [-1, 1] over the visible range
+fn normalized(lambda: f64) -> f64 {
+    (2.0 * lambda - LAMBDA_MIN - LAMBDA_MAX) / (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+fn sigmoid(x: f64) -> f64 {
+    if x.is_infinite() {
+        return if x > 0.0 { 1.0 } else { 0.0 };
+    }
+    0.5 + x / (2.0 * (1.0 + x * x).sqrt())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SigmoidPolynomial {
+    pub c: [f64; 3],
+}
+
+impl SigmoidPolynomial {
+    // a flat spectrum of `value` in [0, 1]
+    fn flat(value: f64) -> Self {
+        let value = value.clamp(0.0, 1.0);
+        let c2 = if value == 0.0 {
+            f64::NEG_INFINITY
+        } else if value == 1.0 {
+            f64::INFINITY
+        } else {
+            (value - 0.5) / (value * (1.0 - value)).sqrt()
+        };
+        Self { c: [0.0, 0.0, c2] }
+    }
+
+    pub fn eval(&self, lambda: f64) -> f64 {
+        let x = normalized(lambda);
+        if self.c[2].is_infinite() {
+            return sigmoid(self.c[2]);
+        }
+        sigmoid((self.c[0] * x + self.c[1]) * x + self.c[2])
+    }
+}
+
+// The color matching functions at the wavelengths the fit integrates over
+struct Fitter {
+    lambda: [f64; FIT_STEPS],
+    xyz: [Vec3; FIT_STEPS],
+}
+
+impl Fitter {
+    fn new() -> Self {
+        let mut lambda = [0.0; FIT_STEPS];
+        let mut xyz = [Vec3::zero(); FIT_STEPS];
+        for i in 0..FIT_STEPS {
+            lambda[i] = LAMBDA_MIN + (i as f64 + 0.5) * FIT_STEP;
+            xyz[i] = cie_xyz(lambda[i]) * (FIT_STEP / CIE_Y_INTEGRAL);
+        }
+        Self { lambda, xyz }
+    }
+
+    // the color of the spectrum with coefficients `c` under white light
+    fn color(&self, c: [f64; 3]) -> Color {
+        let polynomial = SigmoidPolynomial { c };
+        let mut xyz = Vec3::zero();
+        for i in 0..FIT_STEPS {
+            xyz += self.xyz[i] * polynomial.eval(self.lambda[i]);
+        }
+        xyz_to_balanced_rgb(xyz)
+    }
+
+    // Gauss-Newton from `c`, halving steps that do not get closer to `target`
+    fn fit(&self, target: Color, mut c: [f64; 3]) -> [f64; 3] {
+        let mut residual = self.color(c) - target;
+        for _ in 0..50 {
+            if residual.length() < 1e-6 {
+                break;
+            }
+            let mut jacobian = [Vec3::zero(); 3];
+            for (j, column) in jacobian.iter_mut().enumerate() {
+                let mut shifted = c;
+                shifted[j] += 1e-5;
+                *column = (self.color(shifted) - target - residual) / 1e-5;
+            }
+            let step = match solve(jacobian, -residual) {
+                Some(step) => step,
+                None => break,
+            };
+            let mut t = 1.0;
+            loop {
+                let candidate = [c[0] + t * step.x, c[1] + t * step.y, c[2] + t * step.z];
+                let candidate_residual = self.color(candidate) - target;
+                if candidate_residual.length() < residual.length() {
+                    c = candidate;
+                    residual = candidate_residual;
+                    break;
+                }
+                t /= 2.0;
+                if t < 1e-4 {
+                    return c;
+                }
+            }
+        }
+        c
+    }
+}
+
+// `x` with `columns[0] x.x + columns[1] x.y + columns[2] x.z = b`, by Cramer's rule
+fn solve(columns: [Vec3; 3], b: Vec3) -> Option<Vec3> {
+    let det = |a: Vec3, b: Vec3, c: Vec3| a * Vec3::cross(b, c);
+    let d = det(columns[0], columns[1], columns[2]);
+    if d.abs() < 1e-300 {
+        return None;
+    }
+    Some(Vec3::new(
+        det(b, columns[1], columns[2]) / d,
+        det(columns[0], b, columns[2]) / d,
+        det(columns[0], columns[1], b) / d,
+    ))
+}
+
+// Coefficients for colors in the unit cube, arranged by the largest channel, its
+// value `z` and the other two relative to it
+pub struct RgbToSpectrumTable {
+    resolution: usize,
+    z_nodes: Vec<f64>,
+    coefficients: Vec<[f64; 3]>,
+}
+
+impl RgbToSpectrumTable {
+    pub fn fit(resolution: usize) -> Self {
+        let fitter = Fitter::new();
+        let smoothstep = |x: f64| x * x * (3.0 - 2.0 * x);
+        // denser towards dark and bright colors
+        let z_nodes: Vec<f64> = (0..resolution)
+            .map(|i| smoothstep(smoothstep(i as f64 / (resolution - 1) as f64)))
+            .collect();
+        let mut table = Self {
+            resolution,
+            z_nodes,
+            coefficients: vec![[0.0; 3]; 3 * resolution * resolution * resolution],
+        };
+        // fits start from a flat gray and move through the brightness, each starting
+        // from the last one
+        let start = resolution / 5;
+        for l in 0..3 {
+            for yi in 0..resolution {
+                for xi in 0..resolution {
+                    let (x, y) = (
+                        xi as f64 / (resolution - 1) as f64,
+                        yi as f64 / (resolution - 1) as f64,
+                    );
+                    let runs: [Vec<usize>; 2] =
+                        [(start..resolution).collect(), (0..start).rev().collect()];
+                    for run in runs.iter() {
+                        let mut c = [0.0; 3];
+                        for &zi in run {
+                            let z = table.z_nodes[zi];
+                            let mut rgb = [0.0; 3];
+                            rgb[l] = z;
+                            rgb[(l + 1) % 3] = x * z;
+                            rgb[(l + 2) % 3] = y * z;
+                            c = fitter.fit(Color::new(rgb[0], rgb[1], rgb[2]), c);
+                            let index = table.index(l, zi, yi, xi);
+                            table.coefficients[index] = c;
+                        }
+                    }
+                }
+            }
+        }
+        table
+    }
+
+    fn index(&self, l: usize, zi: usize, yi: usize, xi: usize) -> usize {
+        ((l * self.resolution + zi) * self.resolution + yi) * self.resolution + xi
+    }
+
+    // The spectrum of `rgb` with channels in [0, 1]
+    pub fn polynomial(&self, rgb: Color) -> SigmoidPolynomial {
+        let rgb = [
+            rgb.x.clamp(0.0, 1.0),
+            rgb.y.clamp(0.0, 1.0),
+            rgb.z.clamp(0.0, 1.0),
+        ];
+        if rgb[0] == rgb[1] && rgb[1] == rgb[2] {
+            return SigmoidPolynomial::flat(rgb[0]);
+        }
+        let l = if rgb[0] > rgb[1] && rgb[0] > rgb[2] {
+            0
+        } else if rgb[1] > rgb[2] {
+            1
+        } else {
+            2
+        };
+        let z = rgb[l];
+        let last = (self.resolution - 1) as f64;
+        let x = rgb[(l + 1) % 3] / z * last;
+        let y = rgb[(l + 2) % 3] / z * last;
+        let xi = (x as usize).min(self.resolution - 2);
+        let yi = (y as usize).min(self.resolution - 2);
+        let zi = match self.z_nodes.iter().rposition(|&node| node <= z) {
+            Some(zi) => zi.min(self.resolution - 2),
+            None => 0,
+        };
+        let (dx, dy) = (x - xi as f64, y - yi as f64);
+        let dz = (z - self.z_nodes[zi]) / (self.z_nodes[zi + 1] - self.z_nodes[zi]);
+        let mut c = [0.0; 3];
+        for (k, c) in c.iter_mut().enumerate() {
+            let at = |i: usize, j: usize, m: usize| {
+                self.coefficients[self.index(l, zi + i, yi + j, xi + m)][k]
+            };
+            let lerp = |t: f64, a: f64, b: f64| (1.0 - t) * a + t * b;
+            *c = lerp(
+                dz,
+                lerp(
+                    dy,
+                    lerp(dx, at(0, 0, 0), at(0, 0, 1)),
+                    lerp(dx, at(0, 1, 0), at(0, 1, 1)),
+                ),
+                lerp(
+                    dy,
+                    lerp(dx, at(1, 0, 0), at(1, 0, 1)),
+                    lerp(dx, at(1, 1, 0), at(1, 1, 1)),
+                ),
+            );
+        }
+        SigmoidPolynomial { c }
+    }
+}
+
+// A color as a spectrum. Colors brighter than one, as lights and the sky can be, are
+// a reflectance spectrum scaled up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RgbSpectrum {
+    scale: f64,
+    polynomial: SigmoidPolynomial,
+}
+
+impl RgbSpectrum {
+    pub fn new(rgb: Color) -> Self {
+        let rgb = Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0));
+        let scale = rgb.x.max(rgb.y).max(rgb.z).max(1.0);
+        Self {
+            scale,
+            polynomial: TABLE.with(|table| table.polynomial(rgb / scale)),
+        }
+    }
+
+    pub fn eval(&self, lambda: f64) -> f64 {
+        self.scale * self.polynomial.eval(lambda)
+    }
+
+    pub fn sample(&self, lambda: &SampledWavelengths) -> SampledSpectrum {
+        SampledSpectrum::from_fn(lambda, |lambda| self.eval(lambda))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let fitter = Fitter::new();
+        let colors = [
+            Color::new(0.8, 0.3, 0.1),
+            Color::new(0.05, 0.6, 0.2),
+            Color::new(0.2, 0.3, 0.9),
+            Color::new(0.5, 0.5, 0.5),
+            Color::new(0.95, 0.9, 0.2),
+            Color::new(0.0, 0.0, 0.0),
+        ];
+        for &rgb in &colors {
+            let polynomial = TABLE.with(|table| table.polynomial(rgb));
+            assert!((fitter.color(polynomial.c) - rgb).length() < 0.01);
+            // a reflectance never reflects more than comes in
+            for i in 0..=47 {
+                let value = polynomial.eval(LAMBDA_MIN + 10.0 * i as f64);
+                assert!((0.0..=1.0).contains(&value));
+            }
+        }
+        let light = RgbSpectrum::new(Color::new(4.0, 2.0, 1.0));
+        let color = fitter.color(light.polynomial.c) * light.scale;
+        assert!((color - Color::new(4.0, 2.0, 1.0)).length() < 0.04);
+    }
+}
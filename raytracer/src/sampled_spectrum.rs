@@ -0,0 +1,158 @@
+// A spectrum known at the few wavelengths a path carries, as in pbrt-v4. The first
+// one is the hero wavelength, the others are spread evenly over the visible range.
+
+use crate::spectrum::{
+    cie_xyz, sample_visible_wavelength, visible_wavelength_pdf, xyz_to_balanced_rgb, CIE_Y_INTEGRAL,
+};
+use crate::{Color, Vec3};
+use std::ops::{Add, AddAssign, Mul, MulAssign};
+
+pub const SPECTRUM_SAMPLES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; SPECTRUM_SAMPLES],
+    pub pdf: [f64; SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    // Stratified over the sensitivity of the eye, the strata shifted by `u`
+    pub fn sample_visible(u: f64) -> Self {
+        let mut lambda = [0.0; SPECTRUM_SAMPLES];
+        let mut pdf = [0.0; SPECTRUM_SAMPLES];
+        for i in 0..SPECTRUM_SAMPLES {
+            let up = (u + i as f64 / SPECTRUM_SAMPLES as f64).fract();
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+        Self { lambda, pdf }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|&pdf| pdf == 0.0)
+    }
+
+    // Drop all but the hero wavelength, once the path has gone where only it goes
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in &mut self.pdf[1..] {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= SPECTRUM_SAMPLES as f64;
+    }
+
+    // The color that `radiance` at these wavelengths stands for
+    pub fn color_of(&self, radiance: SampledSpectrum) -> Color {
+        let mut xyz = Vec3::zero();
+        for i in 0..SPECTRUM_SAMPLES {
+            if self.pdf[i] != 0.0 {
+                xyz += cie_xyz(self.lambda[i]) * (radiance.0[i] / self.pdf[i]);
+            }
+        }
+        xyz_to_balanced_rgb(xyz / (SPECTRUM_SAMPLES as f64 * CIE_Y_INTEGRAL))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampledSpectrum(pub [f64; SPECTRUM_SAMPLES]);
+
+impl SampledSpectrum {
+    pub fn splat(value: f64) -> Self {
+        Self([value; SPECTRUM_SAMPLES])
+    }
+
+    pub fn from_fn(lambda: &SampledWavelengths, f: impl Fn(f64) -> f64) -> Self {
+        let mut values = [0.0; SPECTRUM_SAMPLES];
+        for (value, &lambda) in values.iter_mut().zip(lambda.lambda.iter()) {
+            *value = f(lambda);
+        }
+        Self(values)
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.0.iter().all(|&value| value == 0.0)
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut values = self.0;
+        for (value, other) in values.iter_mut().zip(other.0.iter()) {
+            *value += other;
+        }
+        Self(values)
+    }
+}
+
+impl AddAssign for SampledSpectrum {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut values = self.0;
+        for (value, other) in values.iter_mut().zip(other.0.iter()) {
+            *value *= other;
+        }
+        Self(values)
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: f64) -> Self {
+        self * SampledSpectrum::splat(other)
+    }
+}
+
+impl MulAssign for SampledSpectrum {
+    fn mul_assign(&mut self, other: Self) {
+        *self = *self * other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_spectrum_is_white() {
+        let n = 20_000;
+        let mut sum = Color::zero();
+        for i in 0..n {
+            let lambda = SampledWavelengths::sample_visible((i as f64 + 0.5) / n as f64);
+            sum += lambda.color_of(SampledSpectrum::splat(1.0));
+        }
+        assert!((sum / n as f64 - Color::ones()).length() < 1e-3);
+    }
+
+    #[test]
+    fn test_terminate_secondary() {
+        let mut lambda = SampledWavelengths::sample_visible(0.3);
+        let spectrum = SampledSpectrum::from_fn(&lambda, |l| l / 500.0);
+        let hero = lambda.hero();
+        let pdf = lambda.pdf[0];
+        lambda.terminate_secondary();
+        lambda.terminate_secondary();
+        assert!(lambda.secondary_terminated());
+        assert_eq!(lambda.hero(), hero);
+        assert!((lambda.pdf[0] - pdf / 4.0).abs() < 1e-15);
+        // the hero alone now stands for all of them
+        let rgb = lambda.color_of(spectrum);
+        let expected = xyz_to_balanced_rgb(cie_xyz(hero) * (hero / 500.0 / pdf / CIE_Y_INTEGRAL));
+        assert!((rgb - expected).length() < 1e-12);
+    }
+}
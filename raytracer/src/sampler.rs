@@ -0,0 +1,682 @@
+// Sources of the random numbers of a render.
+//
+// A sampler is positioned at one sample of one pixel, then hands out the
+// dimensions of that sample in order: the pixel jitter first, then the lens,
+// then whatever every bounce asks for. Low-discrepancy samplers spread the
+// samples of a pixel evenly over each pair of dimensions, which lowers the
+// noise at the same sample count as long as every sample consumes its
+// dimensions in the same order.
+
+use crate::utils::hash_u64;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::str::FromStr;
+use std::sync::Arc;
+
+pub trait Sampler: Send + Sync {
+    // restart at the first dimension of sample `index` of pixel (x, y)
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32);
+
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64);
+
+    // every tile renders with its own copy of the sampler
+    fn clone_box(&self) -> Box<dyn Sampler>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub fn build(&self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "independent" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "bluenoise" => Ok(SamplerKind::BlueNoise),
+            _ => Err(format!(
+                "unknown sampler `{}`, expected one of: independent, stratified, halton, sobol, bluenoise",
+                s
+            )),
+        }
+    }
+}
+
+fn hash3(a: u64, b: u64, c: u64) -> u64 {
+    hash_u64(hash_u64(hash_u64(a) ^ b) ^ c)
+}
+
+fn pixel_key(x: u32, y: u32) -> u64 {
+    ((y as u64) << 32) | x as u64
+}
+
+// 32 random bits to a float in [0, 1)
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 * (1.0 / 4_294_967_296.0)
+}
+
+// Element `i` of a random permutation of 0..n chosen by `seed` (Kensler, "Correlated
+// Multi-Jittered Sampling"), without storing the permutation.
+fn permutation_element(mut i: u32, n: u32, seed: u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+// Random stream of one sample of one pixel, seeded from the global seed,
+// the pixel and the sample index. The image then does not depend on the
+// tile size, the number of workers or the order the tiles finish in.
+pub fn sample_rng(seed: u64, x: u32, y: u32, sample: u32) -> SmallRng {
+    SmallRng::seed_from_u64(hash3(seed, pixel_key(x, y), sample as u64))
+}
+
+// Plain pseudo-random numbers, a fresh stream for every sample
+#[derive(Clone)]
+pub struct IndependentSampler {
+    seed: u64,
+    rng: SmallRng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: sample_rng(seed, 0, 0, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.rng = sample_rng(self.seed, x, y, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.gen_range(0.0..1.0)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+// Jittered strata, one per sample of the pixel. Every dimension visits
+// the strata in its own random order, so dimensions are not correlated.
+#[derive(Clone)]
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: u32,
+    // strata of the 2D grid, close to square and at least `samples_per_pixel`
+    x_strata: u32,
+    y_strata: u32,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: u32) -> Self {
+        let x_strata = (samples_per_pixel as f64).sqrt().ceil() as u32;
+        Self {
+            seed,
+            samples_per_pixel,
+            x_strata,
+            y_strata: (samples_per_pixel - 1) / x_strata + 1,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_hash(&mut self) -> u64 {
+        self.dimension += 1;
+        hash3(self.seed, self.pixel, self.dimension)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = pixel_key(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.next_hash();
+        let n = self.samples_per_pixel;
+        let stratum = permutation_element(self.index % n, n, hash as u32);
+        let jitter = to_unit(hash_u64(hash ^ self.index as u64) as u32);
+        (stratum as f64 + jitter) / n as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = self.next_hash();
+        let n = self.x_strata * self.y_strata;
+        let stratum = permutation_element(self.index % n, n, hash as u32);
+        let jitter = hash_u64(hash ^ self.index as u64);
+        (
+            ((stratum % self.x_strata) as f64 + to_unit(jitter as u32)) / self.x_strata as f64,
+            ((stratum / self.x_strata) as f64 + to_unit((jitter >> 32) as u32))
+                / self.y_strata as f64,
+        )
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Radical inverse of `index` in `base` with every digit permuted depending on
+// the digits before it, which is Owen scrambling. The digits past the last
+// nonzero one of `index` are scrambled too, until the float precision is used up.
+fn owen_scrambled_radical_inverse(mut index: u64, base: u32, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    // `prefix` only identifies the digits so far, it may wrap around
+    let (mut value, mut scale, mut prefix) = (0.0, inv_base, 0_u64);
+    while 1.0 - (base - 1) as f64 * scale < 1.0 {
+        let digit = (index % base as u64) as u32;
+        let digit = permutation_element(digit, base, hash_u64(hash ^ prefix) as u32);
+        value += digit as f64 * scale;
+        prefix = prefix
+            .wrapping_mul(base as u64)
+            .wrapping_add(digit as u64 + 1);
+        scale *= inv_base;
+        index /= base as u64;
+    }
+    value.min(1.0 - f64::EPSILON / 2.0)
+}
+
+// The Halton sequence, dimension `d` being the radical inverse in the
+// d-th prime, scrambled differently in every pixel.
+#[derive(Clone)]
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = pixel_key(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        // past the last prime the bases repeat, with a different scramble
+        let base = PRIMES[self.dimension % PRIMES.len()];
+        let hash = hash3(self.seed, self.pixel, self.dimension as u64);
+        self.dimension += 1;
+        owen_scrambled_radical_inverse(self.index as u64, base, hash)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+// The first two dimensions of the Sobol sequence as 32-bit fractions
+fn sobol(index: u32, dimension: usize) -> u32 {
+    let (mut result, mut v) = (0, 1_u32 << 31);
+    for bit in 0..32 {
+        if index >> bit & 1 == 1 {
+            result ^= v;
+        }
+        // van der Corput for the first dimension, the Pascal matrix for the second
+        v = match dimension {
+            0 => v >> 1,
+            _ => v ^ (v >> 1),
+        };
+    }
+    result
+}
+
+// Laine and Karras' hash, which only lets bits affect more significant ones
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+// Owen scrambling of a 32-bit fraction in a handful of multiplications (Burley 2020)
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Owen-scrambled Sobol points of one 2D pair of dimensions. Every pair reuses
+// the first two Sobol dimensions with its own shuffle of the sample order,
+// so that pairs are not correlated with each other (Burley, "Practical
+// Hash-based Owen Scrambling").
+fn shuffled_sobol_2d(index: u32, hash: u64) -> (u32, u32) {
+    let index = nested_uniform_scramble(index, hash as u32);
+    let hash = hash_u64(hash);
+    (
+        nested_uniform_scramble(sobol(index, 0), hash as u32),
+        nested_uniform_scramble(sobol(index, 1), (hash >> 32) as u32),
+    )
+}
+
+fn shuffled_sobol_1d(index: u32, hash: u64) -> u32 {
+    let index = nested_uniform_scramble(index, hash as u32);
+    nested_uniform_scramble(sobol(index, 0), (hash >> 32) as u32)
+}
+
+#[derive(Clone)]
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn next_hash(&mut self) -> u64 {
+        self.dimension += 1;
+        hash3(self.seed, self.pixel, self.dimension)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.pixel = pixel_key(x, y);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = self.next_hash();
+        to_unit(shuffled_sobol_1d(self.index, hash))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = self.next_hash();
+        let (x, y) = shuffled_sobol_2d(self.index, hash);
+        (to_unit(x), to_unit(y))
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+// Tileable blue-noise threshold map made with the void-and-cluster method
+// (Ulichney 1993): every value in 0..size² appears once, and the pixels
+// below any threshold are spread as evenly as possible.
+pub struct BlueNoiseMask {
+    pub size: usize,
+    pub values: Vec<f64>,
+}
+
+impl BlueNoiseMask {
+    pub fn void_and_cluster(size: usize, seed: u64) -> Self {
+        const SIGMA: f64 = 1.5;
+        const RADIUS: i64 = 6;
+        let n = size * size;
+        let kernel: Vec<f64> = (-RADIUS..=RADIUS)
+            .flat_map(|dy| (-RADIUS..=RADIUS).map(move |dx| (dx, dy)))
+            .map(|(dx, dy)| (-((dx * dx + dy * dy) as f64) / (2.0 * SIGMA * SIGMA)).exp())
+            .collect();
+        // Gaussian-filtered density of the set pixels, wrapping around the edges
+        let update = |energy: &mut Vec<f64>, i: usize, sign: f64| {
+            let (x, y) = ((i % size) as i64, (i / size) as i64);
+            let mut k = 0;
+            for dy in -RADIUS..=RADIUS {
+                for dx in -RADIUS..=RADIUS {
+                    let nx = (x + dx).rem_euclid(size as i64) as usize;
+                    let ny = (y + dy).rem_euclid(size as i64) as usize;
+                    energy[ny * size + nx] += sign * kernel[k];
+                    k += 1;
+                }
+            }
+        };
+        // the set pixel with the most set neighbours, or the free one with the fewest
+        let tightest_cluster = |pattern: &[bool], energy: &[f64]| {
+            (0..n)
+                .filter(|&i| pattern[i])
+                .fold((0, f64::NEG_INFINITY), |best, i| {
+                    if energy[i] > best.1 {
+                        (i, energy[i])
+                    } else {
+                        best
+                    }
+                })
+                .0
+        };
+        let largest_void = |pattern: &[bool], energy: &[f64]| {
+            (0..n)
+                .filter(|&i| !pattern[i])
+                .fold((0, f64::INFINITY), |best, i| {
+                    if energy[i] < best.1 {
+                        (i, energy[i])
+                    } else {
+                        best
+                    }
+                })
+                .0
+        };
+
+        // random initial pattern, relaxed by moving points from clusters into voids
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let mut pattern = vec![false; n];
+        let mut energy = vec![0.0; n];
+        let initial = (n / 10).max(1);
+        let mut ones = 0;
+        while ones < initial {
+            let i = rng.gen_range(0..n);
+            if !pattern[i] {
+                pattern[i] = true;
+                update(&mut energy, i, 1.0);
+                ones += 1;
+            }
+        }
+        loop {
+            let cluster = tightest_cluster(&pattern, &energy);
+            pattern[cluster] = false;
+            update(&mut energy, cluster, -1.0);
+            let void = largest_void(&pattern, &energy);
+            pattern[void] = true;
+            update(&mut energy, void, 1.0);
+            if void == cluster {
+                break;
+            }
+        }
+
+        let mut rank = vec![0; n];
+        // rank the initial points by removing the tightest clusters first
+        let (mut reduced, mut reduced_energy) = (pattern.clone(), energy.clone());
+        for r in (0..initial).rev() {
+            let cluster = tightest_cluster(&reduced, &reduced_energy);
+            reduced[cluster] = false;
+            update(&mut reduced_energy, cluster, -1.0);
+            rank[cluster] = r;
+        }
+        // then fill the largest voids until every pixel is set
+        for r in initial..n {
+            let void = largest_void(&pattern, &energy);
+            pattern[void] = true;
+            update(&mut energy, void, 1.0);
+            rank[void] = r;
+        }
+
+        Self {
+            size,
+            values: rank.iter().map(|&r| (r as f64 + 0.5) / n as f64).collect(),
+        }
+    }
+
+    pub fn value(&self, x: u32, y: u32) -> f64 {
+        let size = self.size as u32;
+        self.values[((y % size) * size + x % size) as usize]
+    }
+}
+
+// Sobol points shared by all pixels, each pixel shifted by the blue-noise
+// mask (a Cranley-Patterson rotation). The error then varies little between
+// neighbouring pixels, which looks much calmer at low sample counts.
+#[derive(Clone)]
+pub struct BlueNoiseSampler {
+    seed: u64,
+    mask: Arc<BlueNoiseMask>,
+    x: u32,
+    y: u32,
+    index: u32,
+    dimension: u64,
+}
+
+impl BlueNoiseSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            mask: Arc::new(BlueNoiseMask::void_and_cluster(64, seed)),
+            x: 0,
+            y: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    // every dimension reads the mask at its own offset, so they are not correlated
+    fn next_offset(&mut self) -> (u64, f64) {
+        self.dimension += 1;
+        let hash = hash3(self.seed, 0, self.dimension);
+        let offset = self.mask.value(
+            self.x.wrapping_add(hash as u32),
+            self.y.wrapping_add((hash >> 32) as u32),
+        );
+        (hash, offset)
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, index: u32) {
+        self.x = x;
+        self.y = y;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (hash, offset) = self.next_offset();
+        (to_unit(shuffled_sobol_1d(self.index, hash)) + offset).fract()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (hash, offset_x) = self.next_offset();
+        let (_, offset_y) = self.next_offset();
+        let (x, y) = shuffled_sobol_2d(self.index, hash);
+        (
+            (to_unit(x) + offset_x).fract(),
+            (to_unit(y) + offset_y).fract(),
+        )
+    }
+
+    fn clone_box(&self) -> Box<dyn Sampler> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    #[test]
+    fn test_sample_rng_streams_differ() {
+        let first = |x, y, sample| sample_rng(1, x, y, sample).gen::<u64>();
+        assert_eq!(first(3, 4, 5), first(3, 4, 5));
+        assert_ne!(first(3, 4, 5), first(4, 3, 5));
+        assert_ne!(first(3, 4, 5), first(3, 4, 6));
+        assert_ne!(first(3, 4, 5), sample_rng(2, 3, 4, 5).gen::<u64>());
+    }
+
+    #[test]
+    fn test_sobol() {
+        let first: Vec<f64> = (0..4).map(|i| to_unit(sobol(i, 1))).collect();
+        assert_eq!(first, [0.0, 0.5, 0.75, 0.25]);
+        let first: Vec<f64> = (0..4).map(|i| to_unit(sobol(i, 0))).collect();
+        assert_eq!(first, [0.0, 0.5, 0.25, 0.75]);
+    }
+
+    #[test]
+    fn test_permutation_element() {
+        for &n in &[1, 5, 16, 100] {
+            let mut seen: Vec<u32> = (0..n).map(|i| permutation_element(i, n, 12345)).collect();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..n).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_samples_in_unit_square() {
+        for kind in &KINDS {
+            let mut sampler = kind.build(1, 16);
+            for index in 0..16 {
+                sampler.start_pixel_sample(3, 5, index);
+                for _ in 0..8 {
+                    let (u, v) = sampler.get_2d();
+                    let w = sampler.get_1d();
+                    assert!([u, v, w].iter().all(|s| (0.0..1.0).contains(s)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_blue_noise_mask() {
+        let mask = BlueNoiseMask::void_and_cluster(16, 1);
+        let mut ranks: Vec<usize> = mask
+            .values
+            .iter()
+            .map(|value| (value * 256.0) as usize)
+            .collect();
+        ranks.sort_unstable();
+        assert_eq!(ranks, (0..256).collect::<Vec<_>>());
+        // neighbours differ more than in white noise, where the mean difference is 1/3
+        let mean_difference = (0..16)
+            .flat_map(|y| (0..16).map(move |x| (x, y)))
+            .map(|(x, y)| (mask.value(x, y) - mask.value(x + 1, y)).abs())
+            .sum::<f64>()
+            / 256.0;
+        assert!(mean_difference > 0.4, "{}", mean_difference);
+    }
+
+    // Mean squared error of estimating the integral of `f` over the unit square
+    // with `n` samples, averaged over many pixels.
+    fn mean_squared_error(kind: SamplerKind, n: u32, f: fn(f64, f64) -> f64, exact: f64) -> f64 {
+        let mut sampler = kind.build(7, n);
+        let pixels = 64;
+        let mut total = 0.0;
+        for pixel in 0..pixels {
+            let mut estimate = 0.0;
+            for index in 0..n {
+                sampler.start_pixel_sample(pixel, 0, index);
+                // skip the pixel jitter, like a camera ray would
+                sampler.get_2d();
+                let (u, v) = sampler.get_2d();
+                estimate += f(u, v) / n as f64;
+            }
+            total += (estimate - exact) * (estimate - exact);
+        }
+        total / pixels as f64
+    }
+
+    #[test]
+    fn test_convergence() {
+        // a smooth integrand, and the discontinuous one of a disk
+        let smooth = |u: f64, v: f64| (u * 3.0).sin() * v * v;
+        let smooth_exact = (1.0 - 3.0_f64.cos()) / 9.0;
+        let disk = |u: f64, v: f64| (u * u + v * v < 1.0) as u32 as f64;
+        let disk_exact = std::f64::consts::PI / 4.0;
+        for &(f, exact) in &[
+            (smooth as fn(f64, f64) -> f64, smooth_exact),
+            (disk, disk_exact),
+        ] {
+            let independent = mean_squared_error(SamplerKind::Independent, 64, f, exact);
+            for &kind in &KINDS[1..] {
+                let error = mean_squared_error(kind, 64, f, exact);
+                assert!(
+                    error < independent * 0.25,
+                    "{:?}: {} vs {}",
+                    kind,
+                    error,
+                    independent
+                );
+            }
+            // and the error keeps falling faster than 1/n
+            let sobol = |n| mean_squared_error(SamplerKind::Sobol, n, f, exact);
+            assert!(sobol(256) < sobol(16) / 32.0);
+        }
+    }
+}
@@ -1,12 +1,15 @@
 use crate::{Ray, Vec3};
 
-pub enum ScatterRecord{
+pub enum ScatterRecord {
     Specular {
         specular_ray: Ray,
         attenuation: Vec3,
     },
+    // anything but a mirror or a smooth interface, sampled with density `pdf`
+    // and `attenuation` already divided by it
     Diffuse {
         scattered: Ray,
         attenuation: Vec3,
-    }
-}
\ No newline at end of file
+        pdf: f64,
+    },
+}
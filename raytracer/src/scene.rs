@@ -1,63 +1,161 @@
-#![allow(dead_code)]
-// You SHOULD remove above line in your code.
-
-use crate::{Vec3, Point3, Color};
+use crate::aperture::ApertureShape;
+use crate::bvh::Bvh;
+use crate::camera::{Camera, CameraSettings, Projection};
+use crate::conductor::Conductor;
+use crate::displacement::DisplacedMesh;
+use crate::moving_triangle::MovingTriangle;
+use crate::sampler::Sampler;
+use crate::Sphere;
+use crate::{dielectric::Dielectric, lambertian::Lambertian, Material};
+use crate::{Color, HitRecord, Point3, Ray, Vec3};
 use crate::{Hittable, HittableList};
-use crate::{Sphere, utils};
-use crate::{Material, lambertian::Lambertian, metal::Metal, dielectric::Dielectric};
-use std::sync::Arc;
 use rand::{rngs::SmallRng, Rng, SeedableRng};
+use std::sync::Arc;
 // use raytracer_codegen::make_spheres_impl;
 
 // Call the procedural macro, which will become `make_spheres` function.
 // make_spheres_impl! {}
 
-// These three structs are just written here to make it compile.
-// You should `use` your own structs in this file.
-// e.g. replace next two lines with
-// `use crate::materials::{DiffuseLight, ConstantTexture}`
-pub struct ConstantTexture(Vec3);
-pub struct DiffuseLight(ConstantTexture);
+// What a ray sees when it hits nothing
+#[derive(Clone, Copy, Debug)]
+pub enum Background {
+    Sky,
+    Solid(Color),
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Sky => {
+                let t = 0.5 * (ray.direction.unit().y + 1.0);
+                Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+            }
+            Background::Solid(color) => *color,
+        }
+    }
+}
+
+pub struct Scene {
+    pub world: HittableList,
+    // over `world`, once built
+    pub bvh: Option<Bvh>,
+    // indices into `world` of the hittables sampled as lights
+    pub lights: Vec<usize>,
+    // meshes that go into `world` once they are tessellated for the camera
+    pub displaced: Vec<DisplacedMesh>,
+    // whether objects move while the shutter is open, which gives every camera ray a time
+    pub motion_blur: bool,
+    pub camera: CameraSettings,
+    pub background: Background,
+    // aspect ratio the scene was composed for, used when only one of width/height is given
+    pub aspect_ratio: f64,
+}
+
+impl Scene {
+    // Adds the triangles of the displaced meshes as fine as the image `height` pixels
+    // high through `camera` needs them, before the BVH is built. Each goes into the BVH
+    // bounded by its displaced corners, so the boxes are no larger than the surface.
+    pub fn tessellate(&mut self, camera: &dyn Camera, height: u32) {
+        for displaced in self.displaced.drain(..) {
+            let mesh = displaced
+                .displacement
+                .tessellate(&displaced.mesh, |p| camera.pixel_footprint(p, height));
+            self.world.hittables.extend(MovingTriangle::mesh_triangles(
+                &Arc::new(mesh),
+                &displaced.material,
+                displaced.motion,
+            ));
+        }
+        self.lights = self.world.lights();
+    }
+
+    // Builds the BVH over the objects as they are now, to be rebuilt when they change
+    pub fn build_bvh(&mut self) {
+        self.bvh = Some(Bvh::new(&self.world.hittables));
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        match &self.bvh {
+            Some(bvh) => bvh.hit(&self.world.hittables, ray, t_min, t_max),
+            None => self.world.hit(ray, t_min, t_max),
+        }
+    }
 
-pub fn example_scene() -> HittableList {
-    let mut rng = SmallRng::from_entropy();
+    // A direction from `origin` towards one of the lights, chosen uniformly
+    pub fn sample_light(&self, origin: Point3, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let n = self.lights.len();
+        let i = ((sampler.get_1d() * n as f64) as usize).min(n - 1);
+        self.world.hittables[self.lights[i]].sample_direction(origin, sampler.get_2d())
+    }
+
+    // The density of `sample_light` choosing `direction`
+    pub fn light_pdf(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .lights
+            .iter()
+            .map(|&i| self.world.hittables[i].pdf_value(origin, direction))
+            .sum();
+        sum / self.lights.len() as f64
+    }
+}
+
+pub fn example_scene(seed: u64) -> Scene {
+    let mut rng = SmallRng::seed_from_u64(seed);
 
     // Add ground
     let material_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    let mut spheres: Vec<Box<dyn Hittable>> = vec![
-        Box::new(Sphere {
-            center: Vec3::new(0.0, -1000.0, 0.0),
-            radius: 1000.0,
-            material: material_ground.clone(),
-        }),
-    ];
+    let mut spheres: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere {
+        center: Vec3::new(0.0, -1000.0, 0.0),
+        radius: 1000.0,
+        material: material_ground,
+    })];
 
     // Add random small spheres
     for a in -11..11 {
         for b in -11..11 {
             let choose_mat = rng.gen_range(0.0..1.0);
-            let center = Point3::new(a as f64 + 0.9 * rng.gen_range(0.0..1.0), 0.2, b as f64 + 0.9 * rng.gen_range(0.0..1.0));
+            let center = Point3::new(
+                a as f64 + 0.9 * rng.gen_range(0.0..1.0),
+                0.2,
+                b as f64 + 0.9 * rng.gen_range(0.0..1.0),
+            );
 
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 let sphere_material: Arc<dyn Material> = match choose_mat {
                     x if x < 0.8 => {
-                        let albedo = utils::random_in_unit_sphere(&mut rng);
+                        let albedo = std::iter::repeat_with(|| {
+                            Color::new(
+                                rng.gen_range(-1.0..1.0),
+                                rng.gen_range(-1.0..1.0),
+                                rng.gen_range(-1.0..1.0),
+                            )
+                        })
+                        .find(|albedo| albedo.length() < 1.0)
+                        .unwrap();
                         let albedo = Vec3::elemul(albedo, albedo); // No negative color
                         Arc::new(Lambertian::new(albedo))
                     }
                     x if x < 0.95 => {
-                        let albedo = Color::new(rng.gen_range(0.5..1.0), rng.gen_range(0.5..1.0), rng.gen_range(0.5..1.0));
+                        let albedo = Color::new(
+                            rng.gen_range(0.5..1.0),
+                            rng.gen_range(0.5..1.0),
+                            rng.gen_range(0.5..1.0),
+                        );
                         let fuzz = rng.gen_range(0.0..0.5);
-                        Arc::new(Metal::new(albedo, fuzz))
-                    }
-                    _ => {
-                        Arc::new(Dielectric::new(1.5))
+                        Arc::new(Conductor::from_reflectance(albedo, fuzz))
                     }
+                    _ => Arc::new(Dielectric::new(1.5)),
                 };
                 spheres.push(Box::new(Sphere {
                     center,
                     radius: 0.2,
-                    material: sphere_material.clone(),
+                    material: sphere_material,
                 }));
             }
         }
@@ -66,33 +164,50 @@ pub fn example_scene() -> HittableList {
     // Add big balls
     let big_material1 = Arc::new(Dielectric::new(1.5));
     let big_material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    let big_material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    let big_material3 = Arc::new(Conductor::from_reflectance(Color::new(0.7, 0.6, 0.5), 0.0));
     spheres.append(&mut vec![
         Box::new(Sphere {
             center: Vec3::new(0.0, 1.0, 0.0),
             radius: 1.0,
-            material: big_material1.clone(),
+            material: big_material1,
         }),
         Box::new(Sphere {
             center: Vec3::new(-4.0, 1.0, 0.0),
             radius: 1.0,
-            material: big_material2.clone(),
+            material: big_material2,
         }),
         Box::new(Sphere {
             center: Vec3::new(4.0, 1.0, 0.0),
             radius: 1.0,
-            material: big_material3.clone(),
+            material: big_material3,
         }),
     ]);
 
-    // let mut hittables = vec![]; // This is wrong
     let mut hittables: Vec<Box<dyn Hittable>> = vec![];
     // You can now add spheres to your own world
     hittables.append(&mut spheres);
 
-    return HittableList{
-        hittables,
-    };
-    // hittable_list.clear();
-    // World { height: 512 }
+    let world = HittableList { hittables };
+    Scene {
+        lights: world.lights(),
+        world,
+        displaced: vec![],
+        bvh: None,
+        motion_blur: false,
+        camera: CameraSettings {
+            lookfrom: Vec3::new(13.0, 2.0, 3.0),
+            lookat: Vec3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aperture: 0.1,
+            focus_dist: 10.0,
+            projection: Projection::Perspective,
+            aperture_shape: ApertureShape::Circle,
+            cat_eye: 0.0,
+            stereo: None,
+            animation: None,
+        },
+        background: Background::Sky,
+        aspect_ratio: 1200.0 / 800.0,
+    }
 }
@@ -0,0 +1,1552 @@
+// Loading scenes from the JSON / YAML files in `data/`.
+// The file layout follows the tutorial's format: an `objects` tree plus a `camera`.
+
+use crate::aperture::ApertureShape;
+use crate::bump::{Bump, Bumped};
+use crate::camera::{CameraSettings, Projection};
+use crate::camera_animation::CameraAnimation;
+use crate::coated::Coated;
+use crate::conductor::{Conductor, ConductorPreset};
+use crate::dispersion::Dispersion;
+use crate::displacement::{DisplacedMesh, Displacement};
+use crate::image_texture::ImageTexture;
+use crate::keyframe::Animated;
+use crate::light_spectrum::LightSpectrum;
+use crate::medium::Medium;
+use crate::microfacet::TrowbridgeReitz;
+use crate::mix::Mix;
+use crate::moving_sphere::MovingSphere;
+use crate::moving_triangle::MovingTriangle;
+use crate::mtl::MtlMaterial;
+use crate::noise_texture::NoiseTexture;
+use crate::obj::load_obj;
+use crate::principled::Principled;
+use crate::scene::{Background, Scene};
+use crate::stereo_camera::Stereo;
+use crate::subsurface::Subsurface;
+use crate::texture::Texture;
+use crate::thin_film::ThinFilm;
+use crate::triangle_mesh::TriangleMesh;
+use crate::{checker_texture::CheckerTexture, constant_texture::ConstantTexture};
+use crate::{dielectric::Dielectric, diffuse_light::DiffuseLight, lambertian::Lambertian};
+use crate::{Color, Hittable, HittableList, Material, Point3, Sphere, Vec3};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+struct SceneDescription {
+    objects: ObjectDescription,
+    camera: Option<CameraDescription>,
+    background: Option<Color>,
+    // the part of a frame the shutter is open for, 0.5 if not given
+    shutter: Option<f64>,
+}
+
+#[derive(Clone, Deserialize)]
+struct CameraDescription {
+    look_from: Point3,
+    look_at: Point3,
+    vup: Vec3,
+    vfov: f64,
+    aspect: f64,
+    aperture: f64,
+    focus_dist: f64,
+    // a perspective camera if not given
+    projection: Option<Projection>,
+    // a circular aperture without vignetting if not given
+    aperture_shape: Option<ApertureShape>,
+    cat_eye: Option<f64>,
+    stereo: Option<Stereo>,
+    animation: Option<CameraAnimation>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum ObjectDescription {
+    #[serde(rename = "HitableList")]
+    HittableList { items: Vec<ObjectDescription> },
+    // BVH nodes are stored with their precomputed boxes, we only keep the children
+    #[serde(rename = "BVHNode")]
+    BvhNode {
+        left: Box<ObjectDescription>,
+        right: Box<ObjectDescription>,
+    },
+    Sphere {
+        center: Animated<Point3>,
+        radius: Animated<f64>,
+        material: Box<MaterialDescription>,
+    },
+    // the meshes of an OBJ file with its MTL materials, or all with `material`,
+    // scaled by `scale` and then moved by `translate`; paths of files are relative
+    // to the scene file, like those of image textures and normal maps
+    Mesh {
+        file: String,
+        material: Option<Box<MaterialDescription>>,
+        scale: Option<Animated<f64>>,
+        translate: Option<Animated<Vec3>>,
+        displacement: Option<DisplacementDescription>,
+        // read with the scene file and not yet placed, the material is `None`
+        // for meshes without one
+        #[serde(skip)]
+        meshes: Vec<LoadedMesh>,
+    },
+}
+
+// Heights along the normals, the mean of the channels of the texture `map` times
+// `scale`, on triangles with edges about `edge_length` pixels long, 1 if not given
+#[derive(Deserialize)]
+struct DisplacementDescription {
+    map: TextureDescription,
+    scale: Animated<f64>,
+    edge_length: Option<f64>,
+}
+
+// a mesh of an OBJ file and the material of its MTL file
+type LoadedMesh = (Arc<TriangleMesh>, Option<Arc<dyn Material>>);
+
+// Any of these values can be keyframed, see `Animated`
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum MaterialDescription {
+    Lambertian {
+        albedo: TextureDescription,
+    },
+    // a conductor reflecting `albedo` head-on, `fuzz` is its roughness
+    Metal {
+        albedo: Animated<Color>,
+        fuzz: Animated<f64>,
+    },
+    // `roughness_v` along the bitangent makes it anisotropic, `thin_film: {thickness:
+    // 400, ior: 1.33}` in nm makes it iridescent
+    Conductor {
+        metal: MetalDescription,
+        roughness: Animated<f64>,
+        roughness_v: Option<Animated<f64>>,
+        thin_film: Option<ThinFilm>,
+    },
+    // smooth without `roughness`, tinted with a thick body by `absorption` per unit
+    // length, `dispersion` replaces `ref_idx` by an index that depends on the wavelength,
+    // a `thin_film` needs a smooth surface
+    Dielectric {
+        ref_idx: Animated<f64>,
+        dispersion: Option<Dispersion>,
+        roughness: Option<Animated<f64>>,
+        roughness_v: Option<Animated<f64>>,
+        absorption: Option<Animated<Color>>,
+        thin_film: Option<ThinFilm>,
+    },
+    // the Disney principled BSDF, see `Principled` for the parameters and their defaults
+    Principled {
+        base_color: Box<TextureDescription>,
+        metallic: Option<Animated<f64>>,
+        roughness: Option<Animated<f64>>,
+        specular: Option<Animated<f64>>,
+        specular_tint: Option<Animated<f64>>,
+        sheen: Option<Animated<f64>>,
+        sheen_tint: Option<Animated<f64>>,
+        clearcoat: Option<Animated<f64>>,
+        clearcoat_gloss: Option<Animated<f64>>,
+        transmission: Option<Animated<f64>>,
+        anisotropic: Option<Animated<f64>>,
+        ior: Option<Animated<f64>>,
+    },
+    // a dielectric coat of index `ref_idx`, 1.5 if not given, over `base`, smooth without
+    // `roughness` and tinted by `absorption` over a `thickness` of 1 if not given
+    Coated {
+        base: Box<MaterialDescription>,
+        ref_idx: Option<Animated<f64>>,
+        roughness: Option<Animated<f64>>,
+        roughness_v: Option<Animated<f64>>,
+        absorption: Option<Animated<Color>>,
+        thickness: Option<Animated<f64>>,
+    },
+    // `a` where `amount` is 0 and `b` where it is 1
+    Mix {
+        a: Box<MaterialDescription>,
+        b: Box<MaterialDescription>,
+        amount: MixAmount,
+    },
+    // a translucent body inside a dielectric boundary of index `ref_idx`, 1.4 if not
+    // given, smooth without `roughness`. It has to be closed, `g` above 0 scatters forwards.
+    Subsurface {
+        medium: MediumDescription,
+        g: Option<Animated<f64>>,
+        ref_idx: Option<Animated<f64>>,
+        roughness: Option<Animated<f64>>,
+        roughness_v: Option<Animated<f64>>,
+    },
+    // `base` with the shading normals of either a tangent-space `normal_map` picture or
+    // a `bump_map` texture of heights, scaled by `bump_scale`, 1 if not given
+    Bumped {
+        base: Box<MaterialDescription>,
+        normal_map: Option<String>,
+        bump_map: Option<Box<TextureDescription>>,
+        bump_scale: Option<Animated<f64>>,
+        // read with the scene file
+        #[serde(skip)]
+        normal_image: Option<Arc<ImageTexture>>,
+    },
+    DiffuseLight {
+        emit: TextureDescription,
+    },
+    // a blackbody or measured emitter, of its color outside the spectral integrator
+    SpectralLight {
+        spectrum: LightSpectrum,
+    },
+}
+
+// `metal: Gold` or `metal: {eta: [0.2, 0.92, 1.1], k: [3.9, 2.45, 2.14]}`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MetalDescription {
+    Preset(ConductorPreset),
+    Measured {
+        eta: Animated<Color>,
+        k: Animated<Color>,
+    },
+}
+
+// The color it looks and how far light goes in it between scatterings, like `{albedo:
+// [0.9, 0.6, 0.4], mean_free_path: [1, 0.5, 0.25]}`, or what it scatters and absorbs
+// per unit length, like `{sigma_a: [0.01, 0.05, 0.1], sigma_s: [2, 2, 2]}`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MediumDescription {
+    Albedo {
+        albedo: Animated<Color>,
+        mean_free_path: Animated<Color>,
+    },
+    Coefficients {
+        sigma_a: Animated<Color>,
+        sigma_s: Animated<Color>,
+    },
+}
+
+// `amount: 0.25` or a texture like `amount: {type: NoiseTexture, scale: 4}`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MixAmount {
+    Constant(Animated<f64>),
+    Texture(TextureDescription),
+}
+
+// the names of the scene file, ending in `Texture` like the types
+#[allow(clippy::enum_variant_names)]
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum TextureDescription {
+    ConstantTexture {
+        color: Animated<Color>,
+    },
+    CheckerTexture {
+        t0: Box<TextureDescription>,
+        t1: Box<TextureDescription>,
+    },
+    // one octave of noise if not given
+    NoiseTexture {
+        scale: Animated<f64>,
+        octaves: Option<u32>,
+    },
+    // a picture over the texture coordinates of meshes and spheres
+    ImageTexture {
+        file: String,
+        // read with the scene file
+        #[serde(skip)]
+        image: Option<Arc<ImageTexture>>,
+    },
+}
+
+// The frames during which the shutter is open. Objects are blurred between where
+// they are at `open` and at `close`, materials are taken at `open`.
+#[derive(Clone, Copy)]
+struct Shutter {
+    open: f64,
+    close: f64,
+}
+
+impl ObjectDescription {
+    // Displaced meshes wait in `displaced` for the camera
+    fn build_into(
+        &self,
+        shutter: Shutter,
+        hittables: &mut Vec<Box<dyn Hittable>>,
+        displaced: &mut Vec<DisplacedMesh>,
+    ) {
+        match self {
+            ObjectDescription::HittableList { items } => {
+                for item in items {
+                    item.build_into(shutter, hittables, displaced);
+                }
+            }
+            ObjectDescription::BvhNode { left, right } => {
+                left.build_into(shutter, hittables, displaced);
+                right.build_into(shutter, hittables, displaced);
+            }
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                let material = material.build(shutter.open);
+                let (center0, center1) = (center.at(shutter.open), center.at(shutter.close));
+                let (radius0, radius1) = (radius.at(shutter.open), radius.at(shutter.close));
+                if center0 == center1 && radius0 == radius1 {
+                    hittables.push(Box::new(Sphere {
+                        center: center0,
+                        radius: radius0,
+                        material,
+                    }))
+                } else {
+                    hittables.push(Box::new(MovingSphere {
+                        center0,
+                        center1,
+                        radius0,
+                        radius1,
+                        material,
+                    }))
+                }
+            }
+            ObjectDescription::Mesh {
+                material,
+                scale,
+                translate,
+                displacement,
+                meshes,
+                ..
+            } => {
+                let replacement = material.as_ref().map(|m| m.build(shutter.open));
+                let placement = |time: f64| {
+                    (
+                        scale.as_ref().map_or(1.0, |s| s.at(time)),
+                        translate.as_ref().map_or_else(Vec3::zero, |t| t.at(time)),
+                    )
+                };
+                let (scale, translate) = placement(shutter.open);
+                // the meshes are placed where they are when the shutter opens, and
+                // blurred from there to where they are when it closes
+                let (scale1, translate1) = placement(shutter.close);
+                let motion = if (scale1, translate1) == (scale, translate) || scale == 0.0 {
+                    None
+                } else {
+                    let relative = scale1 / scale;
+                    Some((relative, translate1 - translate * relative))
+                };
+                for (mesh, own_material) in meshes {
+                    // the meshes are kept as read, and placed for every frame
+                    let mesh = if scale == 1.0 && translate == Vec3::zero() {
+                        mesh.clone()
+                    } else {
+                        let mut mesh = TriangleMesh::clone(mesh);
+                        mesh.transform(scale, translate);
+                        Arc::new(mesh)
+                    };
+                    let material = match (&replacement, own_material) {
+                        (Some(material), _) | (None, Some(material)) => material.clone(),
+                        (None, None) => MtlMaterial::default().build(),
+                    };
+                    match displacement {
+                        Some(displacement) => displaced.push(DisplacedMesh {
+                            mesh,
+                            material,
+                            displacement: Displacement {
+                                map: displacement.map.build(shutter.open),
+                                scale: displacement.scale.at(shutter.open),
+                                edge_length: displacement.edge_length.unwrap_or(1.0),
+                            },
+                            motion,
+                        }),
+                        None => hittables
+                            .extend(MovingTriangle::mesh_triangles(&mesh, &material, motion)),
+                    }
+                }
+            }
+        }
+    }
+
+    // Reads the files of the meshes and of the pictures of their materials,
+    // relative paths from `directory`, the one of the scene file
+    fn load_files(&mut self, directory: &Path) -> Result<(), String> {
+        match self {
+            ObjectDescription::HittableList { items } => items
+                .iter_mut()
+                .try_for_each(|item| item.load_files(directory)),
+            ObjectDescription::BvhNode { left, right } => {
+                left.load_files(directory)?;
+                right.load_files(directory)
+            }
+            ObjectDescription::Sphere { material, .. } => material.load_images(directory),
+            ObjectDescription::Mesh {
+                file,
+                material,
+                displacement,
+                meshes,
+                ..
+            } => {
+                if let Some(material) = material {
+                    material.load_images(directory)?;
+                }
+                if let Some(displacement) = displacement {
+                    displacement.map.load_images(directory)?;
+                }
+                let path = resolve(directory, file);
+                let model = load_obj(&path)?;
+                let materials: HashMap<_, _> = model
+                    .materials
+                    .iter()
+                    .map(|(name, material)| (name.clone(), material.build()))
+                    .collect();
+                for obj_mesh in model.meshes {
+                    let material = match obj_mesh.material {
+                        Some(name) => Some(
+                            materials
+                                .get(&name)
+                                .ok_or_else(|| format!("{}: unknown material {}", path, name))?
+                                .clone(),
+                        ),
+                        None => None,
+                    };
+                    meshes.push((Arc::new(obj_mesh.mesh), material));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            ObjectDescription::HittableList { items } => {
+                items.iter().try_for_each(|item| item.validate())
+            }
+            ObjectDescription::BvhNode { left, right } => {
+                left.validate()?;
+                right.validate()
+            }
+            ObjectDescription::Sphere { material, .. } => material.validate(),
+            ObjectDescription::Mesh {
+                material,
+                displacement,
+                ..
+            } => {
+                if let Some(DisplacementDescription {
+                    edge_length: Some(edge_length),
+                    ..
+                }) = displacement
+                {
+                    if *edge_length <= 0.0 {
+                        return Err(format!(
+                            "the edge length of a displacement must be positive, not {}",
+                            edge_length
+                        ));
+                    }
+                }
+                match material {
+                    Some(material) => material.validate(),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn is_animated(&self) -> bool {
+        match self {
+            ObjectDescription::HittableList { items } => {
+                items.iter().any(|item| item.is_animated())
+            }
+            ObjectDescription::BvhNode { left, right } => left.is_animated() || right.is_animated(),
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => center.is_animated() || radius.is_animated() || material.is_animated(),
+            ObjectDescription::Mesh {
+                material,
+                scale,
+                translate,
+                displacement,
+                ..
+            } => {
+                matches!(material, Some(m) if m.is_animated())
+                    || matches!(scale, Some(s) if s.is_animated())
+                    || matches!(translate, Some(t) if t.is_animated())
+                    || matches!(displacement, Some(d) if d.map.is_animated() || d.scale.is_animated())
+            }
+        }
+    }
+}
+
+impl MaterialDescription {
+    fn build(&self, frame: f64) -> Arc<dyn Material> {
+        match self {
+            MaterialDescription::Lambertian { albedo } => {
+                Arc::new(Lambertian::with_texture(albedo.build(frame)))
+            }
+            MaterialDescription::Metal { albedo, fuzz } => Arc::new(Conductor::from_reflectance(
+                albedo.at(frame),
+                fuzz.at(frame),
+            )),
+            MaterialDescription::Conductor {
+                metal,
+                roughness,
+                roughness_v,
+                thin_film,
+            } => {
+                let distribution = distribution(roughness.at(frame), roughness_v, frame);
+                let mut conductor = match metal {
+                    MetalDescription::Preset(preset) => Conductor::preset(*preset, distribution),
+                    MetalDescription::Measured { eta, k } => {
+                        Conductor::new(eta.at(frame), k.at(frame), distribution)
+                    }
+                };
+                if let Some(thin_film) = thin_film {
+                    conductor = conductor.with_thin_film(*thin_film);
+                }
+                Arc::new(conductor)
+            }
+            MaterialDescription::Dielectric {
+                ref_idx,
+                dispersion,
+                roughness,
+                roughness_v,
+                absorption,
+                thin_film,
+            } => {
+                let roughness = roughness.as_ref().map_or(0.0, |r| r.at(frame));
+                let mut dielectric = Dielectric::new(ref_idx.at(frame))
+                    .with_roughness(distribution(roughness, roughness_v, frame))
+                    .with_absorption(absorption.as_ref().map_or(Color::zero(), |a| a.at(frame)));
+                if let Some(dispersion) = dispersion {
+                    dielectric = dielectric.with_dispersion(*dispersion);
+                }
+                if let Some(thin_film) = thin_film {
+                    dielectric = dielectric.with_thin_film(*thin_film);
+                }
+                Arc::new(dielectric)
+            }
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                specular_tint,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_gloss,
+                transmission,
+                anisotropic,
+                ior,
+            } => {
+                let at = |value: &Option<Animated<f64>>, default: f64| {
+                    value.as_ref().map_or(default, |v| v.at(frame))
+                };
+                let defaults = Principled::with_texture(base_color.build(frame));
+                Arc::new(Principled {
+                    metallic: at(metallic, defaults.metallic),
+                    roughness: at(roughness, defaults.roughness),
+                    specular: at(specular, defaults.specular),
+                    specular_tint: at(specular_tint, defaults.specular_tint),
+                    sheen: at(sheen, defaults.sheen),
+                    sheen_tint: at(sheen_tint, defaults.sheen_tint),
+                    clearcoat: at(clearcoat, defaults.clearcoat),
+                    clearcoat_gloss: at(clearcoat_gloss, defaults.clearcoat_gloss),
+                    transmission: at(transmission, defaults.transmission),
+                    anisotropic: at(anisotropic, defaults.anisotropic),
+                    ior: at(ior, defaults.ior),
+                    ..defaults
+                })
+            }
+            MaterialDescription::Coated {
+                base,
+                ref_idx,
+                roughness,
+                roughness_v,
+                absorption,
+                thickness,
+            } => {
+                let roughness = roughness.as_ref().map_or(0.0, |r| r.at(frame));
+                let coat = Dielectric::new(ref_idx.as_ref().map_or(1.5, |r| r.at(frame)))
+                    .with_roughness(distribution(roughness, roughness_v, frame))
+                    .with_absorption(absorption.as_ref().map_or(Color::zero(), |a| a.at(frame)));
+                let coated = Coated::new(base.build(frame), coat);
+                Arc::new(match thickness {
+                    Some(thickness) => coated.with_thickness(thickness.at(frame)),
+                    None => coated,
+                })
+            }
+            MaterialDescription::Subsurface {
+                medium,
+                g,
+                ref_idx,
+                roughness,
+                roughness_v,
+            } => {
+                let g = g.as_ref().map_or(0.0, |g| g.at(frame));
+                let medium = match medium {
+                    MediumDescription::Albedo {
+                        albedo,
+                        mean_free_path,
+                    } => Medium::from_albedo(albedo.at(frame), mean_free_path.at(frame), g),
+                    MediumDescription::Coefficients { sigma_a, sigma_s } => {
+                        Medium::new(sigma_a.at(frame), sigma_s.at(frame), g)
+                    }
+                };
+                let roughness = roughness.as_ref().map_or(0.0, |r| r.at(frame));
+                let boundary = Dielectric::new(ref_idx.as_ref().map_or(1.4, |r| r.at(frame)))
+                    .with_roughness(distribution(roughness, roughness_v, frame));
+                Arc::new(Subsurface::new(boundary, medium))
+            }
+            MaterialDescription::Mix { a, b, amount } => {
+                let amount: Arc<dyn Texture> = match amount {
+                    MixAmount::Constant(amount) => {
+                        Arc::new(ConstantTexture::new(Color::ones() * amount.at(frame)))
+                    }
+                    MixAmount::Texture(texture) => texture.build(frame),
+                };
+                Arc::new(Mix::new(a.build(frame), b.build(frame), amount))
+            }
+            MaterialDescription::Bumped {
+                base,
+                bump_map,
+                bump_scale,
+                normal_image,
+                ..
+            } => {
+                let bump = match (normal_image, bump_map) {
+                    (Some(image), _) => Bump::NormalMap(image.clone()),
+                    (None, Some(height)) => Bump::Height {
+                        height: height.build(frame),
+                        scale: bump_scale.as_ref().map_or(1.0, |s| s.at(frame)),
+                    },
+                    (None, None) => panic!("the normal map is read with the scene file"),
+                };
+                Arc::new(Bumped::new(base.build(frame), bump))
+            }
+            MaterialDescription::DiffuseLight { emit } => {
+                Arc::new(DiffuseLight::with_texture(emit.build(frame)))
+            }
+            MaterialDescription::SpectralLight { spectrum } => {
+                Arc::new(DiffuseLight::with_spectrum(spectrum.clone()))
+            }
+        }
+    }
+
+    fn is_animated(&self) -> bool {
+        match self {
+            MaterialDescription::Lambertian { albedo } => albedo.is_animated(),
+            MaterialDescription::Metal { albedo, fuzz } => {
+                albedo.is_animated() || fuzz.is_animated()
+            }
+            MaterialDescription::Conductor {
+                metal,
+                roughness,
+                roughness_v,
+                ..
+            } => {
+                let metal = match metal {
+                    MetalDescription::Preset(_) => false,
+                    MetalDescription::Measured { eta, k } => eta.is_animated() || k.is_animated(),
+                };
+                metal
+                    || roughness.is_animated()
+                    || matches!(roughness_v, Some(r) if r.is_animated())
+            }
+            MaterialDescription::Dielectric {
+                ref_idx,
+                roughness,
+                roughness_v,
+                absorption,
+                ..
+            } => {
+                ref_idx.is_animated()
+                    || matches!(roughness, Some(r) if r.is_animated())
+                    || matches!(roughness_v, Some(r) if r.is_animated())
+                    || matches!(absorption, Some(a) if a.is_animated())
+            }
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                specular_tint,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_gloss,
+                transmission,
+                anisotropic,
+                ior,
+            } => {
+                base_color.is_animated()
+                    || [
+                        metallic,
+                        roughness,
+                        specular,
+                        specular_tint,
+                        sheen,
+                        sheen_tint,
+                        clearcoat,
+                        clearcoat_gloss,
+                        transmission,
+                        anisotropic,
+                        ior,
+                    ]
+                    .iter()
+                    .any(|value| matches!(value, Some(v) if v.is_animated()))
+            }
+            MaterialDescription::Coated {
+                base,
+                ref_idx,
+                roughness,
+                roughness_v,
+                thickness,
+                absorption,
+            } => {
+                base.is_animated()
+                    || [ref_idx, roughness, roughness_v, thickness]
+                        .iter()
+                        .any(|value| matches!(value, Some(v) if v.is_animated()))
+                    || matches!(absorption, Some(a) if a.is_animated())
+            }
+            MaterialDescription::Subsurface {
+                medium,
+                g,
+                ref_idx,
+                roughness,
+                roughness_v,
+            } => {
+                let medium = match medium {
+                    MediumDescription::Albedo {
+                        albedo,
+                        mean_free_path,
+                    } => albedo.is_animated() || mean_free_path.is_animated(),
+                    MediumDescription::Coefficients { sigma_a, sigma_s } => {
+                        sigma_a.is_animated() || sigma_s.is_animated()
+                    }
+                };
+                medium
+                    || [g, ref_idx, roughness, roughness_v]
+                        .iter()
+                        .any(|value| matches!(value, Some(v) if v.is_animated()))
+            }
+            MaterialDescription::Mix { a, b, amount } => {
+                let amount = match amount {
+                    MixAmount::Constant(amount) => amount.is_animated(),
+                    MixAmount::Texture(texture) => texture.is_animated(),
+                };
+                amount || a.is_animated() || b.is_animated()
+            }
+            MaterialDescription::Bumped {
+                base,
+                bump_map,
+                bump_scale,
+                ..
+            } => {
+                base.is_animated()
+                    || matches!(bump_map, Some(b) if b.is_animated())
+                    || matches!(bump_scale, Some(s) if s.is_animated())
+            }
+            MaterialDescription::DiffuseLight { emit } => emit.is_animated(),
+            MaterialDescription::SpectralLight { .. } => false,
+        }
+    }
+
+    // Reads the pictures of its textures and normal maps
+    fn load_images(&mut self, directory: &Path) -> Result<(), String> {
+        match self {
+            MaterialDescription::Lambertian { albedo } => albedo.load_images(directory),
+            MaterialDescription::Principled { base_color, .. } => base_color.load_images(directory),
+            MaterialDescription::Coated { base, .. } => base.load_images(directory),
+            MaterialDescription::Mix { a, b, amount } => {
+                a.load_images(directory)?;
+                b.load_images(directory)?;
+                match amount {
+                    MixAmount::Constant(_) => Ok(()),
+                    MixAmount::Texture(texture) => texture.load_images(directory),
+                }
+            }
+            MaterialDescription::Bumped {
+                base,
+                normal_map,
+                bump_map,
+                normal_image,
+                ..
+            } => {
+                base.load_images(directory)?;
+                if let Some(file) = normal_map {
+                    *normal_image = Some(Arc::new(ImageTexture::open(
+                        &resolve(directory, file),
+                        true,
+                    )?));
+                }
+                match bump_map {
+                    Some(bump_map) => bump_map.load_images(directory),
+                    None => Ok(()),
+                }
+            }
+            MaterialDescription::DiffuseLight { emit } => emit.load_images(directory),
+            _ => Ok(()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            MaterialDescription::SpectralLight { spectrum } => spectrum.validate(),
+            MaterialDescription::Conductor {
+                thin_film: Some(thin_film),
+                ..
+            } => thin_film.validate(),
+            MaterialDescription::Dielectric {
+                roughness,
+                thin_film: Some(thin_film),
+                ..
+            } => {
+                if roughness.is_some() {
+                    return Err("a dielectric with a thin film must be smooth".to_string());
+                }
+                thin_film.validate()
+            }
+            MaterialDescription::Coated { base, .. } => base.validate(),
+            MaterialDescription::Mix { a, b, .. } => {
+                a.validate()?;
+                b.validate()
+            }
+            MaterialDescription::Bumped {
+                base,
+                normal_map,
+                bump_map,
+                bump_scale,
+                ..
+            } => {
+                if normal_map.is_some() == bump_map.is_some() {
+                    return Err(
+                        "a bumped material takes either a normal_map or a bump_map".to_string()
+                    );
+                }
+                if bump_scale.is_some() && bump_map.is_none() {
+                    return Err("bump_scale needs a bump_map".to_string());
+                }
+                base.validate()
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Isotropic `roughness`, unless `roughness_v` gives another one along the bitangent
+fn distribution(
+    roughness: f64,
+    roughness_v: &Option<Animated<f64>>,
+    frame: f64,
+) -> TrowbridgeReitz {
+    let roughness_v = roughness_v.as_ref().map_or(roughness, |r| r.at(frame));
+    TrowbridgeReitz::new(roughness, roughness_v)
+}
+
+impl TextureDescription {
+    fn build(&self, frame: f64) -> Arc<dyn Texture> {
+        match self {
+            TextureDescription::ConstantTexture { color } => {
+                Arc::new(ConstantTexture::new(color.at(frame)))
+            }
+            TextureDescription::CheckerTexture { t0, t1 } => {
+                Arc::new(CheckerTexture::new(t0.build(frame), t1.build(frame)))
+            }
+            TextureDescription::NoiseTexture { scale, octaves } => {
+                Arc::new(NoiseTexture::new(scale.at(frame), octaves.unwrap_or(1)))
+            }
+            TextureDescription::ImageTexture { image, .. } => image
+                .clone()
+                .expect("the picture is read with the scene file"),
+        }
+    }
+
+    fn load_images(&mut self, directory: &Path) -> Result<(), String> {
+        match self {
+            TextureDescription::CheckerTexture { t0, t1 } => {
+                t0.load_images(directory)?;
+                t1.load_images(directory)
+            }
+            TextureDescription::ImageTexture { file, image } => {
+                *image = Some(Arc::new(ImageTexture::open(
+                    &resolve(directory, file),
+                    false,
+                )?));
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn is_animated(&self) -> bool {
+        match self {
+            TextureDescription::ConstantTexture { color } => color.is_animated(),
+            TextureDescription::CheckerTexture { t0, t1 } => t0.is_animated() || t1.is_animated(),
+            TextureDescription::NoiseTexture { scale, .. } => scale.is_animated(),
+            TextureDescription::ImageTexture { .. } => false,
+        }
+    }
+}
+
+// A scene file kept in memory, to build the scene of every frame of an animation from
+pub struct SceneFile {
+    description: SceneDescription,
+}
+
+impl SceneFile {
+    // whether any object or material is keyframed
+    pub fn is_animated(&self) -> bool {
+        self.description.objects.is_animated()
+    }
+
+    // The scene at `frame`, or a still showing everything as at its first keyframe
+    pub fn scene_at(&self, frame: Option<u32>) -> Scene {
+        let description = &self.description;
+        let shutter = match frame {
+            Some(frame) => Shutter {
+                open: frame as f64,
+                close: frame as f64 + description.shutter.unwrap_or(0.5),
+            },
+            None => Shutter {
+                open: f64::NEG_INFINITY,
+                close: f64::NEG_INFINITY,
+            },
+        };
+        let (mut hittables, mut displaced) = (vec![], vec![]);
+        description
+            .objects
+            .build_into(shutter, &mut hittables, &mut displaced);
+
+        // the same view as `example_scene` if the file does not come with a camera
+        let camera = description.camera.clone().unwrap_or(CameraDescription {
+            look_from: Point3::new(13.0, 2.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 20.0,
+            aspect: 1.5,
+            aperture: 0.1,
+            focus_dist: 10.0,
+            projection: None,
+            aperture_shape: None,
+            cat_eye: None,
+            stereo: None,
+            animation: None,
+        });
+
+        let world = HittableList { hittables };
+        Scene {
+            lights: world.lights(),
+            world,
+            displaced,
+            bvh: None,
+            motion_blur: shutter.close > shutter.open && self.is_animated(),
+            camera: CameraSettings {
+                lookfrom: camera.look_from,
+                lookat: camera.look_at,
+                vup: camera.vup,
+                vfov: camera.vfov,
+                aperture: camera.aperture,
+                focus_dist: camera.focus_dist,
+                projection: camera.projection.unwrap_or(Projection::Perspective),
+                aperture_shape: camera.aperture_shape.unwrap_or(ApertureShape::Circle),
+                cat_eye: camera.cat_eye.unwrap_or(0.0),
+                stereo: camera.stereo,
+                animation: camera.animation,
+            },
+            background: description
+                .background
+                .map_or(Background::Sky, Background::Solid),
+            aspect_ratio: camera.aspect,
+        }
+    }
+}
+
+// `file` as written in the scene file, relative to its `directory` unless absolute
+fn resolve(directory: &Path, file: &str) -> String {
+    directory.join(file).to_string_lossy().into_owned()
+}
+
+pub fn load_scene_file(path: &str) -> Result<SceneFile, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut description: SceneDescription = match extension.as_str() {
+        "json" => serde_json::from_str(&content).map_err(|e| format!("{}: {}", path, e))?,
+        "yaml" | "yml" => serde_yaml::from_str(&content).map_err(|e| format!("{}: {}", path, e))?,
+        _ => {
+            return Err(format!(
+                "{}: unknown scene format, expected .json or .yaml",
+                path
+            ))
+        }
+    };
+    description
+        .objects
+        .validate()
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    description
+        .objects
+        .load_files(directory)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    if let Some(camera) = &mut description.camera {
+        if let Some(Projection::Realistic { lens, .. }) = &mut camera.projection {
+            *lens = resolve(directory, lens);
+        }
+        if let Some(ApertureShape::Image { path }) = &mut camera.aperture_shape {
+            *path = resolve(directory, path);
+        }
+    }
+    if let Some(camera) = &description.camera {
+        if let Some(projection) = &camera.projection {
+            projection
+                .validate()
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if camera.cat_eye.unwrap_or(0.0) < 0.0 {
+            return Err(format!("{}: cat_eye must not be negative", path));
+        }
+        if let Some(stereo) = &camera.stereo {
+            stereo.validate().map_err(|e| format!("{}: {}", path, e))?;
+        }
+        if let Some(animation) = &camera.animation {
+            animation
+                .validate()
+                .map_err(|e| format!("{}: {}", path, e))?;
+        }
+    }
+    if let Some(shutter) = description.shutter {
+        if !(0.0..=1.0).contains(&shutter) {
+            return Err(format!(
+                "{}: shutter must be in [0, 1], not {}",
+                path, shutter
+            ));
+        }
+    }
+    Ok(SceneFile { description })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::FisheyeMapping;
+    use crate::scene::example_scene;
+    use crate::stereo_camera::StereoLayout;
+    use crate::Ray;
+
+    fn load_scene(path: &str) -> Result<Scene, String> {
+        Ok(load_scene_file(path)?.scene_at(None))
+    }
+
+    #[test]
+    fn test_load_json_and_yaml() {
+        for path in &["../data/scene_10.json", "../data/scene_10.yaml"] {
+            let scene = load_scene(path).unwrap();
+            assert_eq!(scene.world.hittables.len(), 25);
+            assert_eq!(scene.camera.lookfrom, Point3::new(-6.0, 2.0, -6.0));
+            assert_eq!(scene.aspect_ratio, 1.0);
+            assert_eq!(scene.camera.projection, Projection::Perspective);
+        }
+    }
+
+    #[test]
+    fn test_projection() {
+        let camera = |projection: &str| {
+            serde_yaml::from_str::<CameraDescription>(&format!(
+                "{{look_from: [0, 0, 0], look_at: [0, 0, -1], vup: [0, 1, 0], vfov: 90,
+                  aspect: 2, aperture: 0, focus_dist: 1, projection: {}}}",
+                projection
+            ))
+            .map(|camera| camera.projection.unwrap())
+        };
+        assert_eq!(
+            camera("{type: Fisheye, fov: 180, mapping: Equisolid}").unwrap(),
+            Projection::Fisheye {
+                fov: 180.0,
+                mapping: FisheyeMapping::Equisolid
+            }
+        );
+        assert_eq!(
+            camera("{type: Orthographic, height: 4}").unwrap(),
+            Projection::Orthographic { height: 4.0 }
+        );
+        assert_eq!(
+            camera("{type: Equirectangular}").unwrap(),
+            Projection::Equirectangular
+        );
+        assert_eq!(
+            camera("{type: Realistic, lens: lenses/dgauss.50mm.dat}").unwrap(),
+            Projection::Realistic {
+                lens: "lenses/dgauss.50mm.dat".to_string(),
+                aperture_diameter: None,
+                film_diagonal: 43.27
+            }
+        );
+        assert!(camera("{type: Fisheye}").is_err());
+        let stereo = serde_yaml::from_str::<Stereo>("{layout: TopBottom, interocular: 0.06}");
+        assert_eq!(
+            stereo.unwrap(),
+            Stereo {
+                interocular: 0.06,
+                ..Stereo::new(StereoLayout::TopBottom)
+            }
+        );
+        let aperture = serde_yaml::from_str::<ApertureShape>("{type: Polygon, blades: 5}");
+        assert_eq!(
+            aperture.unwrap(),
+            ApertureShape::Polygon {
+                blades: 5,
+                rotation: 0.0
+            }
+        );
+        assert!(Projection::Fisheye {
+            fov: 400.0,
+            mapping: FisheyeMapping::Equidistant
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_camera_files_next_to_scene() {
+        let directory = std::env::temp_dir().join(format!("camera-files-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("scene.yaml");
+        std::fs::write(
+            &path,
+            "objects: {type: HitableList, items: []}
+camera: {look_from: [0, 0, 0], look_at: [0, 0, -1], vup: [0, 1, 0], vfov: 90, aspect: 1,
+  aperture: 0, focus_dist: 1, projection: {type: Realistic, lens: lenses/dgauss.50mm.dat},
+  aperture_shape: {type: Image, path: masks/star.png}}",
+        )
+        .unwrap();
+        let scene = load_scene(path.to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        match scene.camera.projection {
+            Projection::Realistic { lens, .. } => assert_eq!(
+                lens,
+                directory.join("lenses/dgauss.50mm.dat").to_string_lossy()
+            ),
+            projection => panic!("unexpected projection {:?}", projection),
+        }
+        assert_eq!(
+            scene.camera.aperture_shape,
+            ApertureShape::Image {
+                path: directory
+                    .join("masks/star.png")
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn test_flatten_bvh() {
+        assert_eq!(
+            load_scene("../data/scene_200_no_bvh.json")
+                .unwrap()
+                .world
+                .hittables
+                .len(),
+            405
+        );
+        assert_eq!(
+            load_scene("../data/scene_500.json")
+                .unwrap()
+                .world
+                .hittables
+                .len(),
+            1005
+        );
+    }
+
+    #[test]
+    fn test_animated_objects() {
+        let file = SceneFile {
+            description: serde_yaml::from_str(
+                "{shutter: 0.25, objects: {type: HitableList, items: [
+                   {type: Sphere, radius: 1, material: {type: Dielectric, ref_idx: 1.5},
+                    center: {interpolation: Linear, keyframes: [
+                      {frame: 1, value: [0, 0, 0]}, {frame: 3, value: [4, 0, 0]}]}},
+                   {type: Sphere, center: [0, -100, 0], radius: 99, material: {
+                      type: Metal, albedo: [0.5, 0.5, 0.5],
+                      fuzz: {keyframes: [{frame: 1, value: 0}, {frame: 3, value: 1}]}}}]}}",
+            )
+            .unwrap(),
+        };
+        assert!(file.is_animated());
+        let moving = Ray::new(Point3::new(3.2, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        // the still shows the first keyframes
+        let still = file.scene_at(None);
+        assert!(!still.motion_blur);
+        assert!(still.hit(&moving, 1e-3, 9.5).is_none());
+        // at frame 2 the sphere moves from x = 2 to 2.5 while the shutter is open
+        let scene = file.scene_at(Some(2));
+        assert!(scene.motion_blur);
+        let at = |time| Ray {
+            time,
+            ..moving.clone()
+        };
+        assert!(scene.hit(&at(0.0), 1e-3, 9.5).is_none());
+        assert!(scene.hit(&at(1.0), 1e-3, 9.5).is_some());
+
+        let mut description: SceneDescription =
+            serde_yaml::from_str("{objects: {type: HitableList, items: []}}").unwrap();
+        description.shutter = Some(0.5);
+        assert!(!SceneFile { description }.is_animated());
+    }
+
+    #[test]
+    fn test_microfacet_materials() {
+        let material = |yaml: &str| serde_yaml::from_str::<MaterialDescription>(yaml);
+        let gold = material("{type: Conductor, metal: Gold, roughness: 0.2, roughness_v: 0.1}");
+        assert!(matches!(
+            gold.unwrap(),
+            MaterialDescription::Conductor {
+                metal: MetalDescription::Preset(ConductorPreset::Gold),
+                roughness_v: Some(_),
+                ..
+            }
+        ));
+        let measured = material(
+            "{type: Conductor, metal: {eta: [0.2, 0.9, 1.1], k: [3.9, 2.5, 2.1]}, roughness: 0}",
+        )
+        .unwrap();
+        assert!(matches!(
+            measured,
+            MaterialDescription::Conductor {
+                metal: MetalDescription::Measured { .. },
+                roughness_v: None,
+                ..
+            }
+        ));
+        assert!(!measured.is_animated());
+        assert!(material("{type: Conductor, metal: Tin, roughness: 0.2}").is_err());
+        let glass = material("{type: Dielectric, ref_idx: 1.5, absorption: [0, 0.2, 0.4]}");
+        assert!(matches!(
+            glass.unwrap(),
+            MaterialDescription::Dielectric {
+                roughness: None,
+                absorption: Some(_),
+                dispersion: None,
+                ..
+            }
+        ));
+        let prism = material("{type: Dielectric, ref_idx: 1.5, dispersion: {type: BK7}}");
+        assert!(matches!(
+            prism.unwrap(),
+            MaterialDescription::Dielectric {
+                dispersion: Some(Dispersion::Bk7),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_coated() {
+        let material = |yaml: &str| serde_yaml::from_str::<MaterialDescription>(yaml).unwrap();
+        let paint = material(
+            "{type: Coated, roughness: 0.05, absorption: [0.1, 0.2, 0.3], \
+             thickness: {keyframes: [{frame: 1, value: 0}, {frame: 3, value: 1}]}, \
+             base: {type: Conductor, metal: Aluminium, roughness: 0.3}}",
+        );
+        assert!(paint.is_animated());
+        assert!(matches!(
+            &paint,
+            MaterialDescription::Coated {
+                ref_idx: None,
+                roughness_v: None,
+                ..
+            }
+        ));
+        assert!(paint.validate().is_ok());
+        let lamp = material(
+            "{type: Coated, base: {type: SpectralLight, \
+             spectrum: {type: Measured, samples: []}}}",
+        );
+        assert!(!lamp.is_animated());
+        assert!(lamp.validate().is_err());
+    }
+
+    #[test]
+    fn test_thin_films() {
+        let material = |yaml: &str| serde_yaml::from_str::<MaterialDescription>(yaml).unwrap();
+        let bubble =
+            material("{type: Dielectric, ref_idx: 1, thin_film: {thickness: 400, ior: 1.33}}");
+        assert!(matches!(
+            &bubble,
+            MaterialDescription::Dielectric {
+                thin_film: Some(ThinFilm { ior, .. }),
+                ..
+            } if *ior == 1.33
+        ));
+        assert!(bubble.validate().is_ok());
+        assert!(bubble.build(0.0).is_dispersive());
+        let frosted = material(
+            "{type: Dielectric, ref_idx: 1.5, roughness: 0.2, \
+             thin_film: {thickness: 400, ior: 1.33}}",
+        );
+        assert!(frosted.validate().is_err());
+        let oily = material(
+            "{type: Conductor, metal: Aluminium, roughness: 0.1, \
+             thin_film: {thickness: 300, ior: 1.5}}",
+        );
+        assert!(oily.validate().is_ok());
+        assert!(oily.build(0.0).is_dispersive());
+        let negative = material(
+            "{type: Conductor, metal: Gold, roughness: 0, thin_film: {thickness: -1, ior: 1.5}}",
+        );
+        assert!(negative.validate().is_err());
+    }
+
+    #[test]
+    fn test_subsurface() {
+        let material = |yaml: &str| serde_yaml::from_str::<MaterialDescription>(yaml).unwrap();
+        let marble = material(
+            "{type: Subsurface, medium: {albedo: [0.9, 0.9, 0.85], mean_free_path: [0.2, 0.2, 0.2]}}",
+        );
+        assert!(matches!(
+            &marble,
+            MaterialDescription::Subsurface {
+                medium: MediumDescription::Albedo { .. },
+                ref_idx: None,
+                ..
+            }
+        ));
+        assert!(!marble.is_animated());
+        let medium = marble.build(0.0).medium().unwrap();
+        assert!((medium.sigma_a + medium.sigma_s - Color::ones() * 5.0).length() < 1e-9);
+        let wax = material(
+            "{type: Subsurface, g: 0.3, roughness: 0.2, \
+             medium: {sigma_a: [0.01, 0.05, 0.2], sigma_s: {keyframes: \
+             [{frame: 0, value: [1, 1, 1]}, {frame: 2, value: [3, 3, 3]}]}}}",
+        );
+        assert!(wax.is_animated());
+        let medium = wax.build(1.0).medium().unwrap();
+        assert_eq!(medium.sigma_s, Color::ones() * 2.0);
+        assert_eq!(medium.g, 0.3);
+    }
+
+    #[test]
+    fn test_spectral_lights() {
+        let object = |yaml: &str| serde_yaml::from_str::<ObjectDescription>(yaml).unwrap();
+        let bulb = object(
+            "{type: Sphere, center: [0, 0, 0], radius: 1, \
+             material: {type: SpectralLight, spectrum: {type: Blackbody, temperature: 2700}}}",
+        );
+        assert!(bulb.validate().is_ok());
+        if let ObjectDescription::Sphere { material, .. } = &bulb {
+            assert!(matches!(
+                material.as_ref(),
+                MaterialDescription::SpectralLight {
+                    spectrum: LightSpectrum::Blackbody { scale, .. }
+                } if *scale == 1.0
+            ));
+        }
+        let broken = object(
+            "{type: Sphere, center: [0, 0, 0], radius: 1, \
+             material: {type: SpectralLight, spectrum: {type: Measured, samples: []}}}",
+        );
+        assert!(broken.validate().is_err());
+    }
+
+    #[test]
+    fn test_meshes() {
+        let mut mesh = serde_yaml::from_str::<ObjectDescription>(
+            "{type: Mesh, file: ../objects/cube.obj, scale: 2, translate: [0, 1, 0], \
+             material: {type: Principled, base_color: {type: ConstantTexture, color: [1, 0, 0]}, \
+             metallic: 1, roughness: 0.2}}",
+        )
+        .unwrap();
+        assert!(mesh.validate().is_ok());
+        mesh.load_files(Path::new("")).unwrap();
+        let (mut hittables, mut displaced) = (vec![], vec![]);
+        let still = Shutter {
+            open: f64::NEG_INFINITY,
+            close: f64::NEG_INFINITY,
+        };
+        mesh.build_into(still, &mut hittables, &mut displaced);
+        assert_eq!(hittables.len(), 12);
+        assert!(displaced.is_empty());
+        // scaled about the origin, then moved up
+        let bounds = hittables
+            .iter()
+            .map(|hittable| hittable.bounding_box())
+            .fold(hittables[0].bounding_box(), |a, b| a.union(&b));
+        assert_eq!(bounds.min, Point3::new(-2.0, -1.0, -2.0));
+        assert_eq!(bounds.max, Point3::new(2.0, 3.0, 2.0));
+
+        // relative to the directory of the scene file, and placed anew for every frame
+        let mut rising = serde_yaml::from_str::<ObjectDescription>(
+            "{type: Mesh, file: cube.obj, \
+             translate: {keyframes: [{frame: 0, value: [0, 0, 0]}, {frame: 2, value: [0, 4, 0]}]}}",
+        )
+        .unwrap();
+        assert!(rising.is_animated());
+        rising.load_files(Path::new("../objects")).unwrap();
+        let mut hittables = vec![];
+        let frame = Shutter {
+            open: 1.0,
+            close: 1.0,
+        };
+        rising.build_into(frame, &mut hittables, &mut displaced);
+        let bounds = hittables
+            .iter()
+            .map(|hittable| hittable.bounding_box())
+            .fold(hittables[0].bounding_box(), |a, b| a.union(&b));
+        assert_eq!(bounds.min, Point3::new(-1.0, 1.0, -1.0));
+        // blurred from 1 to 2 up while the shutter is open
+        let mut hittables = vec![];
+        let blurred = Shutter {
+            open: 1.0,
+            close: 1.5,
+        };
+        rising.build_into(blurred, &mut hittables, &mut displaced);
+        let bottom = |time| {
+            let ray = Ray {
+                time,
+                ..Ray::new(Point3::new(0.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+            };
+            let hits = hittables
+                .iter()
+                .filter_map(|h| h.hit(&ray, 0.0, f64::INFINITY));
+            hits.map(|rec| rec.p.y).fold(f64::INFINITY, f64::min)
+        };
+        assert!((bottom(0.0) - 1.0).abs() < 1e-12);
+        assert!((bottom(0.5) - 1.5).abs() < 1e-12);
+        assert!((bottom(1.0) - 2.0).abs() < 1e-12);
+
+        let mut missing =
+            serde_yaml::from_str::<ObjectDescription>("{type: Mesh, file: ../objects/missing.obj}")
+                .unwrap();
+        assert!(missing.load_files(Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_displacement() {
+        let object = |yaml: &str| serde_yaml::from_str::<ObjectDescription>(yaml).unwrap();
+        let mut rock = object(
+            "{type: Mesh, file: ../objects/cube.obj, \
+             displacement: {map: {type: NoiseTexture, scale: 4}, scale: 0.1, edge_length: 4}}",
+        );
+        assert!(rock.validate().is_ok());
+        assert!(!rock.is_animated());
+        rock.load_files(Path::new("")).unwrap();
+        let (mut hittables, mut displaced) = (vec![], vec![]);
+        let still = Shutter {
+            open: f64::NEG_INFINITY,
+            close: f64::NEG_INFINITY,
+        };
+        rock.build_into(still, &mut hittables, &mut displaced);
+        assert!(hittables.is_empty());
+        assert_eq!(displaced.len(), 1);
+        assert_eq!(displaced[0].displacement.edge_length, 4.0);
+        // tessellated once the camera is there
+        let mut scene = Scene {
+            world: HittableList { hittables },
+            displaced,
+            ..example_scene(0)
+        };
+        let camera = scene.camera.build(1.5).unwrap();
+        scene.tessellate(camera.as_ref(), 100);
+        assert!(scene.displaced.is_empty());
+        assert!(scene.world.hittables.len() > 12);
+
+        let swelling = object(
+            "{type: Mesh, file: ../objects/cube.obj, displacement: {map: {type: NoiseTexture, \
+             scale: 1}, scale: {keyframes: [{frame: 0, value: 0}, {frame: 1, value: 1}]}}}",
+        );
+        assert!(swelling.is_animated());
+        let broken = object(
+            "{type: Mesh, file: ../objects/cube.obj, \
+             displacement: {map: {type: NoiseTexture, scale: 1}, scale: 1, edge_length: 0}}",
+        );
+        assert!(broken.validate().is_err());
+    }
+
+    #[test]
+    fn test_mix() {
+        let material = |yaml: &str| serde_yaml::from_str::<MaterialDescription>(yaml).unwrap();
+        let rusty = material(
+            "{type: Mix, amount: {type: NoiseTexture, scale: 4, octaves: 3}, \
+             a: {type: Conductor, metal: Aluminium, roughness: 0.1}, \
+             b: {type: Lambertian, albedo: {type: ConstantTexture, color: [0.4, 0.2, 0.1]}}}",
+        );
+        assert!(matches!(
+            &rusty,
+            MaterialDescription::Mix {
+                amount: MixAmount::Texture(TextureDescription::NoiseTexture {
+                    octaves: Some(3),
+                    ..
+                }),
+                ..
+            }
+        ));
+        assert!(!rusty.is_animated());
+        let fading = material(
+            "{type: Mix, amount: {keyframes: [{frame: 1, value: 0}, {frame: 9, value: 1}]}, \
+             a: {type: Dielectric, ref_idx: 1.5}, \
+             b: {type: SpectralLight, spectrum: {type: Blackbody, temperature: 3000}}}",
+        );
+        assert!(matches!(
+            &fading,
+            MaterialDescription::Mix {
+                amount: MixAmount::Constant(_),
+                ..
+            }
+        ));
+        assert!(fading.is_animated());
+        assert!(fading.validate().is_ok());
+        assert!(fading.build(9.0).is_emissive());
+    }
+
+    #[test]
+    fn test_bumped() {
+        let material = |yaml: &str| serde_yaml::from_str::<MaterialDescription>(yaml).unwrap();
+        let mut spot = material(
+            "{type: Bumped, normal_map: ../objects/spot_texture.png, base: {type: Lambertian, \
+             albedo: {type: ImageTexture, file: ../objects/spot_texture.png}}}",
+        );
+        assert!(!spot.is_animated());
+        assert!(spot.validate().is_ok());
+        spot.load_images(Path::new("")).unwrap();
+        assert!(matches!(
+            &spot,
+            MaterialDescription::Bumped {
+                normal_image: Some(_),
+                ..
+            }
+        ));
+        spot.build(0.0);
+        let stucco = material(
+            "{type: Bumped, bump_map: {type: NoiseTexture, scale: 8}, \
+             bump_scale: {keyframes: [{frame: 0, value: 0}, {frame: 4, value: 0.02}]}, \
+             base: {type: Conductor, metal: Gold, roughness: 0.2}}",
+        );
+        assert!(stucco.is_animated());
+        assert!(stucco.validate().is_ok());
+        assert!(!stucco.build(2.0).is_emissive());
+        let both = material(
+            "{type: Bumped, normal_map: normal.png, bump_map: {type: NoiseTexture, scale: 1}, \
+             base: {type: Dielectric, ref_idx: 1.5}}",
+        );
+        assert!(both.validate().is_err());
+        let neither = material("{type: Bumped, base: {type: Dielectric, ref_idx: 1.5}}");
+        assert!(neither.validate().is_err());
+        let mut missing = material(
+            "{type: Bumped, normal_map: ../objects/missing.png, \
+             base: {type: Dielectric, ref_idx: 1.5}}",
+        );
+        assert!(missing.load_images(Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_unknown_extension() {
+        assert!(load_scene("../data/scene_10.txt").is_err());
+    }
+}
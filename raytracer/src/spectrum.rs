@@ -0,0 +1,126 @@
+// Single wavelengths of visible light and their color. Wavelengths are in nm.
+
+use crate::{Color, Vec3};
+
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// the integrals of the red, green and blue matching functions over the visible range
+const RGB_INTEGRALS: [f64; 3] = [176.177322, 115.391275, 109.370546];
+
+// the integral of the luminance matching function over the visible range
+pub const CIE_Y_INTEGRAL: f64 = 106.922075;
+
+// the sRGB color of a flat spectrum of luminance one
+const EQUAL_ENERGY_WHITE: [f64; 3] = [1.200268, 0.949699, 0.908296];
+
+// The CIE 1931 colour matching functions, fitted by a sum of piecewise Gaussians
+// (Wyman, Sloan and Shirley 2013)
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        let t = (lambda - mu) / sigma;
+        (-0.5 * t * t).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// CIE XYZ to linear sRGB, with the D65 white point
+pub fn xyz_to_rgb(xyz: Vec3) -> Color {
+    Color::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+// The sRGB color of `xyz`, white balanced so that a flat spectrum is white as in
+// the rest of the renderer, rather than the bluish white of D65
+pub fn xyz_to_balanced_rgb(xyz: Vec3) -> Color {
+    let rgb = xyz_to_rgb(xyz);
+    Color::new(
+        rgb.x / EQUAL_ENERGY_WHITE[0],
+        rgb.y / EQUAL_ENERGY_WHITE[1],
+        rgb.z / EQUAL_ENERGY_WHITE[2],
+    )
+}
+
+// A wavelength with density roughly following the eye's sensitivity (pbrt-v4)
+pub fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+pub fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    0.0039398042 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+// The color a path carrying only `lambda` contributes to, divided by the density of
+// `sample_visible_wavelength`. Averaged over the wavelengths it is white, the gamut
+// is clipped so that no channel goes negative.
+pub fn wavelength_weight(lambda: f64) -> Color {
+    let rgb = xyz_to_rgb(cie_xyz(lambda));
+    let pdf = visible_wavelength_pdf(lambda);
+    if pdf == 0.0 {
+        return Color::zero();
+    }
+    Color::new(
+        rgb.x.max(0.0) / RGB_INTEGRALS[0],
+        rgb.y.max(0.0) / RGB_INTEGRALS[1],
+        rgb.z.max(0.0) / RGB_INTEGRALS[2],
+    ) / pdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgb_integrals() {
+        let steps = 47_000;
+        let h = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let mut sum = Color::zero();
+        let mut xyz_sum = Vec3::zero();
+        let mut pdf_sum = 0.0;
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + (i as f64 + 0.5) * h;
+            let rgb = xyz_to_rgb(cie_xyz(lambda));
+            sum += Color::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0)) * h;
+            xyz_sum += cie_xyz(lambda) * h;
+            pdf_sum += visible_wavelength_pdf(lambda) * h;
+        }
+        for (c, &integral) in [sum.x, sum.y, sum.z].iter().zip(RGB_INTEGRALS.iter()) {
+            assert!((c - integral).abs() < 1e-4);
+        }
+        assert!((xyz_sum.y - CIE_Y_INTEGRAL).abs() < 1e-4);
+        let white = xyz_to_balanced_rgb(xyz_sum / CIE_Y_INTEGRAL);
+        assert!((white - Color::ones()).length() < 1e-5);
+        assert!((pdf_sum - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_white_on_average() {
+        let n = 100_000;
+        let mut sum = Color::zero();
+        for i in 0..n {
+            let lambda = sample_visible_wavelength((i as f64 + 0.5) / n as f64);
+            assert!((LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda));
+            sum += wavelength_weight(lambda);
+        }
+        assert!((sum / n as f64 - Color::ones()).length() < 1e-3);
+        // the ends of the spectrum are red and blue
+        let red = wavelength_weight(650.0);
+        let blue = wavelength_weight(450.0);
+        assert!(red.x > red.z && blue.z > blue.x);
+    }
+}
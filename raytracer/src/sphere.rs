@@ -1,4 +1,8 @@
-use crate::{HitRecord, Hittable, Material, Ray, Vec3};
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+use crate::warp::{sample_uniform_cone, uniform_cone_pdf};
+use crate::{HitRecord, Hittable, Material, Point3, Ray, Vec3};
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 pub struct Sphere {
@@ -7,48 +11,158 @@ pub struct Sphere {
     pub material: Arc<dyn Material>,
 }
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = ray.origin - self.center;
+impl Sphere {
+    // p is a point on the unit sphere, u and v are both in [0, 1]
+    fn get_uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // How the point at outward normal `n` moves with the u and v of `get_uv`, about
+    // the poles along any frame around the normal
+    fn derivatives(n: Vec3, radius: f64) -> (Vec3, Vec3) {
+        let sin_theta = (n.x * n.x + n.z * n.z).sqrt();
+        if sin_theta < 1e-9 {
+            let onb = Onb::from_w(n);
+            return (onb.u * (2.0 * PI * radius), onb.v * (PI * radius));
+        }
+        let cos_theta = -n.y;
+        let dpdu = Vec3::new(n.z, 0.0, -n.x) * (2.0 * PI * radius);
+        let dpdv = Vec3::new(
+            cos_theta * n.x / sin_theta,
+            sin_theta,
+            cos_theta * n.z / sin_theta,
+        ) * (PI * radius);
+        (dpdu, dpdv)
+    }
+
+    // the cone the sphere covers as seen at `-to_center`, `None` from inside
+    fn cos_theta_max(&self, to_center: Vec3) -> Option<f64> {
+        let sin2_theta_max = self.radius * self.radius / to_center.squared_length();
+        if sin2_theta_max >= 1.0 {
+            None
+        } else {
+            Some((1.0 - sin2_theta_max).sqrt())
+        }
+    }
+
+    // Where the ray hits the sphere at `center`, shared with spheres that move
+    pub fn hit_at(
+        center: Point3,
+        radius: f64,
+        material: &Arc<dyn Material>,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord> {
+        let oc = ray.origin - center;
         let a = ray.direction.squared_length();
         let half_b = oc * ray.direction;
-        let c = oc.squared_length() - self.radius * self.radius;
+        let c = oc.squared_length() - radius * radius;
         let discriminant = half_b * half_b - a * c;
         if discriminant > 0.0 {
             // Find the nearest root that lies in the acceptable range.
-            let t = (-half_b - discriminant.sqrt()) / a; // smaller t
-            if t_min < t && t < t_max {
-                let p = ray.at(t);
-                let mut normal = (p - self.center) / self.radius;
-                let front = (normal * ray.direction) < 0.0;
-                if front == false {
-                    normal = -normal;
+            for &t in &[
+                (-half_b - discriminant.sqrt()) / a, // smaller t
+                (-half_b + discriminant.sqrt()) / a, // larger t
+            ] {
+                if t_min < t && t < t_max {
+                    let p = ray.at(t);
+                    let outward_normal = (p - center) / radius;
+                    let (u, v) = Sphere::get_uv(outward_normal);
+                    let (dpdu, dpdv) = Sphere::derivatives(outward_normal, radius);
+                    let front = (outward_normal * ray.direction) < 0.0;
+                    let normal = if front {
+                        outward_normal
+                    } else {
+                        -outward_normal
+                    };
+                    return Some(HitRecord {
+                        p,
+                        normal,
+                        t,
+                        u,
+                        v,
+                        dpdu,
+                        dpdv,
+                        front,
+                        wavelength: ray.wavelength,
+                        material: material.clone(),
+                    });
                 }
-                return Some(HitRecord {
-                    p,
-                    normal,
-                    t,
-                    front,
-                    material: self.material.clone(),
-                });
             }
-            let t = (-half_b + discriminant.sqrt()) / a; // larger t
-            if t_min < t && t < t_max {
-                let p = ray.at(t);
-                let mut normal = (p - self.center) / self.radius;
-                let front = (normal * ray.direction) < 0.0;
-                if front == false {
-                    normal = -normal;
-                }
-                return Some(HitRecord {
-                    p,
-                    normal,
-                    t,
-                    front,
-                    material: self.material.clone(),
-                });
+        }
+        None
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        Sphere::hit_at(self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - r, self.center + r)
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    // uniform in the cone of directions the sphere covers, not seen from inside
+    fn sample_direction(&self, origin: Point3, u: (f64, f64)) -> Option<Vec3> {
+        let to_center = self.center - origin;
+        let cos_theta_max = self.cos_theta_max(to_center)?;
+        Some(Onb::from_w(to_center).local(sample_uniform_cone(u, cos_theta_max)))
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let to_center = self.center - origin;
+        match self.cos_theta_max(to_center) {
+            Some(cos_theta_max) if direction.unit() * to_center.unit() >= cos_theta_max => {
+                uniform_cone_pdf(cos_theta_max)
             }
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::Color;
+
+    #[test]
+    fn test_derivatives() {
+        let sphere = Sphere {
+            center: Point3::new(1.0, 2.0, 3.0),
+            radius: 2.0,
+            material: Arc::new(Lambertian::new(Color::ones())),
+        };
+        let h = 1e-6;
+        for &direction in &[
+            Vec3::new(1.0, 0.2, -0.5),
+            Vec3::new(-0.3, -0.8, 0.1),
+            Vec3::new(0.0, 0.0, 1.0),
+        ] {
+            let ray = Ray::new(sphere.center + direction.unit() * 5.0, -direction);
+            let rec = sphere.hit(&ray, 0.0, f64::INFINITY).unwrap();
+            let n = rec.normal;
+            assert!(rec.dpdu * n < 1e-12 && rec.dpdv * n < 1e-12);
+            // the frame is right-handed about the outward normal
+            assert!(Vec3::cross(rec.dpdu, rec.dpdv) * n > 0.0);
+            // the point of the sphere at (u + h, v) against the one at (u, v)
+            let at = |u: f64, v: f64| {
+                let (phi, theta) = (2.0 * PI * u - PI, PI * v);
+                let (y, r) = (-theta.cos(), theta.sin());
+                sphere.center + Vec3::new(r * phi.cos(), y, -r * phi.sin()) * sphere.radius
+            };
+            assert!((at(rec.u, rec.v) - rec.p).length() < 1e-9);
+            assert!(((at(rec.u + h, rec.v) - rec.p) / h - rec.dpdu).length() < 1e-4);
+            assert!(((at(rec.u, rec.v + h) - rec.p) / h - rec.dpdv).length() < 1e-4);
         }
-        return None;
     }
 }
@@ -0,0 +1,313 @@
+use crate::camera::{Camera, CameraSettings, Frame, Projection};
+use crate::equirectangular_camera::EquirectangularCamera;
+use crate::sampler::Sampler;
+use crate::{Point3, Ray};
+use serde::Deserialize;
+use std::str::FromStr;
+
+// Where the two views go in the image
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum StereoLayout {
+    // left eye on the left half
+    SideBySide,
+    // left eye on the top half
+    TopBottom,
+}
+
+// How the eyes converge on the plane at the convergence distance
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum StereoMode {
+    // both eyes turn towards the convergence point, which is simple but
+    // gives vertical parallax towards the image corners
+    ToeIn,
+    // the eyes look straight ahead and their image windows shift to meet,
+    // only for the perspective projection
+    OffAxis,
+}
+
+impl FromStr for StereoLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "side-by-side" => Ok(StereoLayout::SideBySide),
+            "top-bottom" => Ok(StereoLayout::TopBottom),
+            _ => Err(format!(
+                "unknown stereo layout `{}`, expected side-by-side or top-bottom",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for StereoMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "toe-in" => Ok(StereoMode::ToeIn),
+            "off-axis" => Ok(StereoMode::OffAxis),
+            _ => Err(format!(
+                "unknown stereo mode `{}`, expected toe-in or off-axis",
+                s
+            )),
+        }
+    }
+}
+
+fn side_by_side() -> StereoLayout {
+    StereoLayout::SideBySide
+}
+
+fn off_axis() -> StereoMode {
+    StereoMode::OffAxis
+}
+
+fn human_interocular() -> f64 {
+    0.065
+}
+
+// A pair of views from `lookfrom` moved half the interocular distance to either side.
+// With the equirectangular projection the pair is an omnidirectional stereo (ODS)
+// panorama instead, and `mode` and `convergence` are not used.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct Stereo {
+    #[serde(default = "side_by_side")]
+    pub layout: StereoLayout,
+    #[serde(default = "off_axis")]
+    pub mode: StereoMode,
+    // in scene units, 65 mm if they are meters
+    #[serde(default = "human_interocular")]
+    pub interocular: f64,
+    // distance to the plane that appears at the depth of the screen, `focus_dist` if not given
+    pub convergence: Option<f64>,
+}
+
+impl Stereo {
+    pub fn new(layout: StereoLayout) -> Self {
+        Self {
+            layout,
+            mode: off_axis(),
+            interocular: human_interocular(),
+            convergence: None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interocular < 0.0 {
+            return Err(format!(
+                "the interocular distance must not be negative, not {}",
+                self.interocular
+            ));
+        }
+        match self.convergence {
+            Some(convergence) if convergence <= 0.0 => Err(format!(
+                "the convergence distance must be positive, not {}",
+                convergence
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // The aspect ratio of the whole image with both views of `eye_aspect_ratio`
+    pub fn image_aspect_ratio(&self, eye_aspect_ratio: f64) -> f64 {
+        match self.layout {
+            StereoLayout::SideBySide => eye_aspect_ratio * 2.0,
+            StereoLayout::TopBottom => eye_aspect_ratio / 2.0,
+        }
+    }
+}
+
+pub struct StereoCamera {
+    pub left: Box<dyn Camera>,
+    pub right: Box<dyn Camera>,
+    pub layout: StereoLayout,
+}
+
+impl StereoCamera {
+    pub fn new(
+        settings: &CameraSettings,
+        stereo: &Stereo,
+        aspect_ratio: f64,
+    ) -> Result<Self, String> {
+        let eye_aspect_ratio = match stereo.layout {
+            StereoLayout::SideBySide => aspect_ratio / 2.0,
+            StereoLayout::TopBottom => aspect_ratio * 2.0,
+        };
+        let frame = Frame::new(settings.lookfrom, settings.lookat, settings.vup);
+        let convergence = stereo.convergence.unwrap_or(settings.focus_dist);
+        let eye = |side: f64| -> Result<Box<dyn Camera>, String> {
+            let offset = frame.u * (side * stereo.interocular / 2.0);
+            let mono = CameraSettings {
+                lookfrom: settings.lookfrom + offset,
+                stereo: None,
+                ..settings.clone()
+            };
+            Ok(match (&settings.projection, stereo.mode) {
+                (Projection::Equirectangular, _) => Box::new(
+                    EquirectangularCamera::new(Frame::new(
+                        settings.lookfrom,
+                        settings.lookat,
+                        settings.vup,
+                    ))
+                    .with_eye_offset(side * stereo.interocular / 2.0),
+                ),
+                (_, StereoMode::ToeIn) => CameraSettings {
+                    lookat: frame.origin - frame.w * convergence,
+                    ..mono
+                }
+                .build(eye_aspect_ratio)?,
+                (Projection::Perspective, StereoMode::OffAxis) => {
+                    // shift the window so that it meets the other eye's at the convergence distance
+                    let shift = -offset * (settings.focus_dist / convergence);
+                    Box::new(
+                        CameraSettings {
+                            lookat: settings.lookat + offset,
+                            ..mono
+                        }
+                        .perspective(eye_aspect_ratio)?
+                        .with_shift(shift),
+                    )
+                }
+                (_, StereoMode::OffAxis) => {
+                    return Err(String::from(
+                        "off-axis stereo needs the perspective projection, use toe-in instead",
+                    ))
+                }
+            })
+        };
+        Ok(Self {
+            left: eye(-1.0)?,
+            right: eye(1.0)?,
+            layout: stereo.layout,
+        })
+    }
+}
+
+impl Camera for StereoCamera {
+    fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => self.left.get_ray(s * 2.0, t, sampler),
+            StereoLayout::SideBySide => self.right.get_ray(s * 2.0 - 1.0, t, sampler),
+            StereoLayout::TopBottom if t >= 0.5 => self.left.get_ray(s, t * 2.0 - 1.0, sampler),
+            StereoLayout::TopBottom => self.right.get_ray(s, t * 2.0, sampler),
+        }
+    }
+
+    // as for one of the views, each half as high as the image when they are stacked
+    fn pixel_footprint(&self, p: Point3, height: u32) -> Option<f64> {
+        let height = match self.layout {
+            StereoLayout::SideBySide => height,
+            StereoLayout::TopBottom => height / 2,
+        };
+        self.left.pixel_footprint(p, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aperture::ApertureShape;
+    use crate::sampler::IndependentSampler;
+    use crate::{Point3, Vec3};
+
+    fn settings(projection: Projection, stereo: Stereo) -> CameraSettings {
+        CameraSettings {
+            lookfrom: Point3::zero(),
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            vfov: 90.0,
+            aperture: 0.0,
+            focus_dist: 1.0,
+            projection,
+            aperture_shape: ApertureShape::Circle,
+            cat_eye: 0.0,
+            stereo: Some(stereo),
+            animation: None,
+        }
+    }
+
+    // where the ray through (s, t) crosses the plane z = -depth
+    fn hit(camera: &dyn Camera, s: f64, t: f64, depth: f64) -> Point3 {
+        let ray = camera
+            .get_ray(s, t, &mut IndependentSampler::new(1))
+            .unwrap();
+        ray.at((-depth - ray.origin.z) / ray.direction.z)
+    }
+
+    #[test]
+    fn test_convergence() {
+        for &mode in &[StereoMode::ToeIn, StereoMode::OffAxis] {
+            let stereo = Stereo {
+                layout: StereoLayout::SideBySide,
+                mode,
+                interocular: 0.2,
+                convergence: Some(4.0),
+            };
+            let camera = settings(Projection::Perspective, stereo)
+                .build(2.0)
+                .unwrap();
+            // the eyes sit apart and their centers meet at the convergence distance
+            let left = camera
+                .get_ray(0.25, 0.5, &mut IndependentSampler::new(1))
+                .unwrap();
+            assert!((left.origin - Point3::new(-0.1, 0.0, 0.0)).length() < 1e-12);
+            let center = Point3::new(0.0, 0.0, -4.0);
+            assert!((hit(camera.as_ref(), 0.25, 0.5, 4.0) - center).length() < 1e-9);
+            assert!((hit(camera.as_ref(), 0.75, 0.5, 4.0) - center).length() < 1e-9);
+            // off-axis views keep matching away from the center, toe-in ones drift apart
+            let left = hit(camera.as_ref(), 0.0, 1.0, 4.0);
+            let right = hit(camera.as_ref(), 0.5, 1.0, 4.0);
+            assert_eq!(
+                (left - right).length() < 1e-9,
+                mode == StereoMode::OffAxis,
+                "{:?}",
+                mode
+            );
+        }
+        let stereo = Stereo::new(StereoLayout::SideBySide);
+        let fisheye = Projection::Fisheye {
+            fov: 180.0,
+            mapping: crate::camera::FisheyeMapping::Equidistant,
+        };
+        assert!(settings(fisheye, stereo).build(2.0).is_err());
+    }
+
+    #[test]
+    fn test_layouts() {
+        let stereo = Stereo::new(StereoLayout::TopBottom);
+        assert_eq!(stereo.image_aspect_ratio(2.0), 1.0);
+        let camera = settings(Projection::Perspective, stereo)
+            .build(1.0)
+            .unwrap();
+        let mut sampler = IndependentSampler::new(1);
+        // the top half is the left eye, and each half is a full view
+        let top = camera.get_ray(0.5, 0.75, &mut sampler).unwrap();
+        let bottom = camera.get_ray(0.5, 0.25, &mut sampler).unwrap();
+        assert!(top.origin.x < 0.0 && bottom.origin.x > 0.0);
+        assert!(top.direction.y.abs() < 1e-12);
+        let corner = camera.get_ray(1.0, 1.0, &mut sampler).unwrap().direction;
+        // a 90° vertical field of view over a view twice as wide as high
+        assert!((corner.y / -corner.z - 1.0).abs() < 1e-9);
+        assert!((corner.x / -corner.z - 2.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_ods() {
+        let stereo = Stereo::new(StereoLayout::TopBottom);
+        let camera = settings(Projection::Equirectangular, stereo)
+            .build(1.0)
+            .unwrap();
+        let mut sampler = IndependentSampler::new(1);
+        // every ray leaves a circle of the interocular diameter, tangent to it,
+        // on the left of the view direction for the left eye
+        for &(s, t) in &[(0.1, 0.6), (0.5, 0.9), (0.8, 0.3), (0.3, 0.1)] {
+            let ray = camera.get_ray(s, t, &mut sampler).unwrap();
+            let horizontal = Vec3::new(ray.direction.x, 0.0, ray.direction.z);
+            assert!((ray.origin.length() - 0.0325).abs() < 1e-12);
+            assert!((ray.origin * horizontal).abs() < 1e-12);
+            let side = Vec3::cross(horizontal, ray.origin).y;
+            assert_eq!(side > 0.0, t >= 0.5);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::dielectric::Dielectric;
+use crate::medium::Medium;
+use crate::sampler::Sampler;
+use crate::{Color, HitRecord, Material, Ray, ScatterRecord, Vec3};
+
+// Marble, skin, wax and other translucent bodies: light refracts in through the
+// dielectric `boundary`, smooth or rough, and scatters around in `medium` until it
+// gets out somewhere else. The walk inside is up to the integrator, which needs the
+// surface to be closed.
+pub struct Subsurface {
+    pub boundary: Dielectric,
+    pub medium: Medium,
+}
+
+impl Subsurface {
+    pub fn new(boundary: Dielectric, medium: Medium) -> Self {
+        Self { boundary, medium }
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_record: HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        self.boundary.scatter(ray_in, hit_record, sampler)
+    }
+
+    fn is_dispersive(&self) -> bool {
+        self.boundary.is_dispersive()
+    }
+
+    fn medium(&self) -> Option<Medium> {
+        Some(self.medium)
+    }
+
+    fn eval(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        Material::eval(&self.boundary, hit_record, wo, wi)
+    }
+
+    fn pdf(&self, hit_record: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        Material::pdf(&self.boundary, hit_record, wo, wi)
+    }
+}
@@ -0,0 +1,5 @@
+use crate::{Color, Point3};
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+}
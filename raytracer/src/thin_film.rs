@@ -0,0 +1,268 @@
+use crate::spectrum::xyz_to_rgb;
+use crate::{Color, Vec3};
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// A transparent layer a few hundred nm thick over a surface, like oil on water or the
+// skin of a soap bubble. Light reflected off its top and its bottom interferes, into
+// colors that shift with the angle (Belcour and Barla 2017).
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct ThinFilm {
+    // in nm
+    pub thickness: f64,
+    pub ior: f64,
+}
+
+// The reflectance of one polarization as the Fourier series of the phase difference
+// between consecutive reflections, c0 + 2 Σ cm x^m cos(m (φ + phase))
+struct Airy {
+    c0: f64,
+    cm: f64,
+    x: f64,
+    phase: f64,
+}
+
+impl Airy {
+    // the series summed, for a phase difference φ from the path length alone
+    fn at(&self, phi: f64) -> f64 {
+        let cos = (phi + self.phase).cos();
+        let x = self.x;
+        self.c0 + 2.0 * self.cm * (x * cos - x * x) / (1.0 - 2.0 * x * cos + x * x)
+    }
+}
+
+impl ThinFilm {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.thickness < 0.0 {
+            return Err("thin film thickness must not be negative".to_string());
+        }
+        if self.ior <= 0.0 {
+            return Err(format!("thin film ior must be positive, not {}", self.ior));
+        }
+        Ok(())
+    }
+
+    // The reflectance for light at `cos_theta_i` in a medium of index `n1` off the film,
+    // over a substrate of index `eta + i k` per channel. The color a white light takes
+    // without `wavelength`, else the reflectance at that wavelength alone.
+    pub fn reflectance(
+        &self,
+        cos_theta_i: f64,
+        n1: f64,
+        eta: Color,
+        k: Color,
+        wavelength: Option<f64>,
+    ) -> Color {
+        if eta.x == eta.y && eta.x == eta.z && k.x == k.y && k.x == k.z {
+            return self.channel(cos_theta_i, n1, eta.x, k.x, wavelength);
+        }
+        Color::new(
+            self.channel(cos_theta_i, n1, eta.x, k.x, wavelength).x,
+            self.channel(cos_theta_i, n1, eta.y, k.y, wavelength).y,
+            self.channel(cos_theta_i, n1, eta.z, k.z, wavelength).z,
+        )
+    }
+
+    fn channel(
+        &self,
+        cos_theta_i: f64,
+        n1: f64,
+        eta3: f64,
+        k3: f64,
+        wavelength: Option<f64>,
+    ) -> Color {
+        let (airy, opd) = match self.interference(cos_theta_i, n1, eta3, k3) {
+            Some(interference) => interference,
+            None => return Color::ones(),
+        };
+        match wavelength {
+            Some(lambda) => {
+                let phi = 2.0 * PI * opd / lambda;
+                let r = (airy[0].at(phi) + airy[1].at(phi)) / 2.0;
+                Color::ones() * r.clamp(0.0, 1.0)
+            }
+            // the terms beyond the third are too faint to matter
+            None => {
+                let mut xyz = Vec3::zero();
+                for polarization in &airy {
+                    xyz += sensitivity(0.0, 0.0) * polarization.c0;
+                    let mut cm = polarization.cm;
+                    for m in 1..=3 {
+                        let m_f = m as f64;
+                        cm *= polarization.x;
+                        xyz += sensitivity(m_f * opd, m_f * polarization.phase) * (2.0 * cm);
+                    }
+                }
+                // balanced so that without interference the reflectance stays gray
+                let (rgb, white) = (xyz_to_rgb(xyz / 2.0), xyz_to_rgb(sensitivity(0.0, 0.0)));
+                Color::new(
+                    (rgb.x / white.x).clamp(0.0, 1.0),
+                    (rgb.y / white.y).clamp(0.0, 1.0),
+                    (rgb.z / white.z).clamp(0.0, 1.0),
+                )
+            }
+        }
+    }
+
+    // The series for s and p polarized light, with the optical path difference in nm
+    // between consecutive reflections. `None` when the film reflects everything.
+    fn interference(
+        &self,
+        cos_theta_i: f64,
+        n1: f64,
+        eta3: f64,
+        k3: f64,
+    ) -> Option<([Airy; 2], f64)> {
+        let cos_theta_i = cos_theta_i.abs().min(1.0);
+        if cos_theta_i == 0.0 {
+            return None;
+        }
+        // the film fades into the incident medium as it vanishes, leaving the substrate
+        let t = (self.thickness / 30.0).clamp(0.0, 1.0);
+        let n2 = n1 + (self.ior - n1) * t * t * (3.0 - 2.0 * t);
+        let (r12, phi12) = fresnel_dielectric_polarized(cos_theta_i, n1, n2);
+        if r12[0] >= 1.0 && r12[1] >= 1.0 {
+            return None;
+        }
+        let sin2_theta_2 = (n1 / n2).powi(2) * (1.0 - cos_theta_i * cos_theta_i);
+        let cos_theta_2 = (1.0 - sin2_theta_2).max(0.0).sqrt();
+        let (r23, phi23) = if k3 == 0.0 {
+            fresnel_dielectric_polarized(cos_theta_2, n2, eta3)
+        } else {
+            fresnel_conductor_polarized(cos_theta_2, n2, eta3, k3)
+        };
+        let airy = |i: usize| {
+            let t121 = 1.0 - r12[i];
+            let rs = t121 * t121 * r23[i] / (1.0 - r12[i] * r23[i]);
+            Airy {
+                c0: r12[i] + rs,
+                cm: rs - t121,
+                x: (r12[i] * r23[i]).sqrt(),
+                phase: PI - phi12[i] + phi23[i],
+            }
+        };
+        let opd = 2.0 * n2 * self.thickness * cos_theta_2;
+        Some(([airy(0), airy(1)], opd))
+    }
+}
+
+// The reflectance and the phase change of s and p polarized light going from index
+// `n1` to `n2`
+fn fresnel_dielectric_polarized(cos_theta_i: f64, n1: f64, n2: f64) -> ([f64; 2], [f64; 2]) {
+    let sin2_theta_i = 1.0 - cos_theta_i * cos_theta_i;
+    let nr = n1 / n2;
+    if nr * nr * sin2_theta_i > 1.0 {
+        // total internal reflection, only shifting the phase
+        let s = (sin2_theta_i - 1.0 / (nr * nr)).sqrt();
+        let phi = [
+            2.0 * (-s / cos_theta_i).atan(),
+            2.0 * (-nr * nr * s / cos_theta_i).atan(),
+        ];
+        return ([1.0, 1.0], phi);
+    }
+    let cos_theta_t = (1.0 - nr * nr * sin2_theta_i).sqrt();
+    let r = [
+        (n1 * cos_theta_i - n2 * cos_theta_t) / (n1 * cos_theta_i + n2 * cos_theta_t),
+        (n2 * cos_theta_i - n1 * cos_theta_t) / (n2 * cos_theta_i + n1 * cos_theta_t),
+    ];
+    let phase = |r: f64| if r < 0.0 { PI } else { 0.0 };
+    ([r[0] * r[0], r[1] * r[1]], [phase(r[0]), phase(r[1])])
+}
+
+// The same into a conductor of index `eta + i k`
+fn fresnel_conductor_polarized(
+    cos_theta_i: f64,
+    n1: f64,
+    eta: f64,
+    k: f64,
+) -> ([f64; 2], [f64; 2]) {
+    // as eta (1 + i kappa)
+    let kappa = k / eta;
+    let (n1c, eta2) = (n1 * cos_theta_i, eta * eta);
+    let a = eta2 * (1.0 - kappa * kappa) - n1 * n1 * (1.0 - cos_theta_i * cos_theta_i);
+    let b = (a * a + (2.0 * eta2 * kappa).powi(2)).sqrt();
+    let u = ((a + b) / 2.0).sqrt();
+    let v = ((b - a) / 2.0).max(0.0).sqrt();
+    let r_s = ((n1c - u).powi(2) + v * v) / ((n1c + u).powi(2) + v * v);
+    let phi_s = (2.0 * n1 * v * cos_theta_i).atan2(u * u + v * v - n1c * n1c) + PI;
+    let (p_re, p_im) = (
+        eta2 * (1.0 - kappa * kappa) * cos_theta_i,
+        2.0 * eta2 * kappa * cos_theta_i,
+    );
+    let r_p = ((p_re - n1 * u).powi(2) + (p_im - n1 * v).powi(2))
+        / ((p_re + n1 * u).powi(2) + (p_im + n1 * v).powi(2));
+    let phi_p = (2.0 * n1 * eta2 * cos_theta_i * (2.0 * kappa * u - (1.0 - kappa * kappa) * v))
+        .atan2((eta2 * (1.0 + kappa * kappa) * cos_theta_i).powi(2) - n1 * n1 * (u * u + v * v));
+    ([r_s, r_p], [phi_s, phi_p])
+}
+
+// The CIE XYZ response to a spectrum cos(2π opd / λ + shift) with `opd` in nm, from
+// Gaussian fits of the matching functions, scaled so that a flat spectrum is (1, 1, 1)
+fn sensitivity(opd: f64, shift: f64) -> Vec3 {
+    let phase = 2.0 * PI * opd * 1e-9;
+    let term = |val: f64, pos: f64, var: f64| {
+        val * (2.0 * PI * var).sqrt() * (pos * phase + shift).cos() * (-var * phase * phase).exp()
+    };
+    Vec3::new(
+        term(5.4856e-13, 1.6810e6, 4.3278e9) + term(9.7470e-14, 2.2399e6, 4.5282e9),
+        term(4.4201e-13, 1.7953e6, 9.3046e9),
+        term(5.2481e-13, 2.2084e6, 6.6121e9),
+    ) / 1.0685e-7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fresnel::{fresnel_complex, fresnel_dielectric};
+    use crate::spectrum::{cie_xyz, xyz_to_balanced_rgb, CIE_Y_INTEGRAL, LAMBDA_MAX, LAMBDA_MIN};
+
+    #[test]
+    fn test_vanishing_film() {
+        let film = ThinFilm {
+            thickness: 0.0,
+            ior: 1.33,
+        };
+        for i in 0..=10 {
+            let cos = i as f64 / 10.0;
+            let glass = fresnel_dielectric(cos, 1.5);
+            for &wavelength in &[None, Some(550.0)] {
+                let r = film.reflectance(cos, 1.0, Color::ones() * 1.5, Color::zero(), wavelength);
+                assert!(
+                    (r - Color::ones() * glass).length() < 1e-3,
+                    "{} {:?}",
+                    cos,
+                    r
+                );
+            }
+            let r = film.reflectance(cos, 1.0, Color::ones() * 0.2, Color::ones() * 3.9, None);
+            assert!((r - Color::ones() * fresnel_complex(cos, 0.2, 3.9)).length() < 1e-3);
+        }
+        // from inside the glass beyond the critical angle
+        let r = film.reflectance(0.5, 1.5, Color::ones(), Color::zero(), Some(550.0));
+        assert_eq!(r, Color::ones());
+    }
+
+    #[test]
+    fn test_rgb_matches_spectrum() {
+        for &(thickness, cos) in &[(250.0, 1.0), (400.0, 0.7), (700.0, 0.3)] {
+            let film = ThinFilm {
+                thickness,
+                ior: 1.33,
+            };
+            let (eta, k) = (Color::ones() * 1.5, Color::zero());
+            let steps = 4700;
+            let dl = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+            let mut xyz = Vec3::zero();
+            for i in 0..steps {
+                let lambda = LAMBDA_MIN + (i as f64 + 0.5) * dl;
+                let r = film.reflectance(cos, 1.0, eta, k, Some(lambda)).x;
+                xyz += cie_xyz(lambda) * (r * dl / CIE_Y_INTEGRAL);
+            }
+            let spectral = xyz_to_balanced_rgb(xyz);
+            let rgb = film.reflectance(cos, 1.0, eta, k, None);
+            assert!((spectral - rgb).length() < 0.02, "{:?} {:?}", spectral, rgb);
+            // it is colored, not gray
+            assert!((rgb.x - rgb.y).abs() + (rgb.y - rgb.z).abs() > 0.01);
+        }
+    }
+}
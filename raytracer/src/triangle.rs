@@ -0,0 +1,225 @@
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+use crate::triangle_mesh::TriangleMesh;
+use crate::warp::{sample_spherical_triangle, spherical_triangle_area};
+use crate::{HitRecord, Hittable, Material, Point3, Ray, Vec3};
+use std::sync::Arc;
+
+// One face of a mesh
+pub struct Triangle {
+    pub mesh: Arc<TriangleMesh>,
+    pub index: usize,
+    pub material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    fn vertices(&self) -> [Point3; 3] {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let positions = &self.mesh.positions;
+        [positions[i0], positions[i1], positions[i2]]
+    }
+
+    // How the point moves with u and v over the face, by the texture coordinates of its
+    // corners or else by the barycentrics standing in for them
+    fn derivatives(&self) -> (Vec3, Vec3) {
+        let [p0, p1, p2] = self.vertices();
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        if self.mesh.uvs.is_empty() {
+            return (e1, e2);
+        }
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let uvs = &self.mesh.uvs;
+        let (du1, dv1) = (uvs[i1].0 - uvs[i0].0, uvs[i1].1 - uvs[i0].1);
+        let (du2, dv2) = (uvs[i2].0 - uvs[i0].0, uvs[i2].1 - uvs[i0].1);
+        let det = du1 * dv2 - dv1 * du2;
+        if det == 0.0 {
+            let onb = Onb::from_w(Vec3::cross(e1, e2));
+            return (onb.u, onb.v);
+        }
+        ((e1 * dv2 - e2 * dv1) / det, (e2 * du1 - e1 * du2) / det)
+    }
+
+    // Möller-Trumbore: the distance along the ray and the barycentrics of the
+    // second and third vertex
+    fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let [p0, p1, p2] = self.vertices();
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let pvec = Vec3::cross(ray.direction, e2);
+        let det = e1 * pvec;
+        if det == 0.0 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let tvec = ray.origin - p0;
+        let b1 = (tvec * pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+        let qvec = Vec3::cross(tvec, e1);
+        let b2 = (ray.direction * qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+        let t = (e2 * qvec) * inv_det;
+        if t_min < t && t < t_max {
+            Some((t, b1, b2))
+        } else {
+            None
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t, b1, b2) = self.intersect(ray, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let [p0, p1, p2] = self.vertices();
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let geometric = Vec3::cross(p1 - p0, p2 - p0).unit();
+        let front = geometric * ray.direction < 0.0;
+        // interpolated normals on the side of the face they belong to
+        let mut shading = geometric;
+        if !self.mesh.normals.is_empty() {
+            let normals = &self.mesh.normals;
+            let n = normals[i0] * b0 + normals[i1] * b1 + normals[i2] * b2;
+            if n.squared_length() > 0.0 {
+                shading = if n * geometric < 0.0 {
+                    -n.unit()
+                } else {
+                    n.unit()
+                };
+            }
+        }
+        // the generated tangents where there are any, keeping the lengths of the face's
+        let (mut dpdu, mut dpdv) = self.derivatives();
+        if !self.mesh.tangents.is_empty() {
+            let tangents = &self.mesh.tangents;
+            let t = tangents[i0].0 * b0 + tangents[i1].0 * b1 + tangents[i2].0 * b2;
+            let t = t - shading * (t * shading);
+            if t.squared_length() > 0.0 {
+                let sign = tangents[i0].1 * b0 + tangents[i1].1 * b1 + tangents[i2].1 * b2;
+                let bitangent = Vec3::cross(shading, t.unit()) * sign.signum();
+                dpdu = t.unit() * dpdu.length();
+                dpdv = bitangent * dpdv.length();
+            }
+        }
+        let (u, v) = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let uvs = &self.mesh.uvs;
+            (
+                uvs[i0].0 * b0 + uvs[i1].0 * b1 + uvs[i2].0 * b2,
+                uvs[i0].1 * b0 + uvs[i1].1 * b1 + uvs[i2].1 * b2,
+            )
+        };
+        Some(HitRecord {
+            p: p0 * b0 + p1 * b1 + p2 * b2,
+            normal: if front { shading } else { -shading },
+            t,
+            u,
+            v,
+            dpdu,
+            dpdv,
+            front,
+            wavelength: ray.wavelength,
+            material: self.material.clone(),
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [p0, p1, p2] = self.vertices();
+        Aabb::new(p0, p0)
+            .union(&Aabb::new(p1, p1))
+            .union(&Aabb::new(p2, p2))
+    }
+
+    fn is_light(&self) -> bool {
+        self.material.is_emissive()
+    }
+
+    // uniform in the solid angle of the triangle
+    fn sample_direction(&self, origin: Point3, u: (f64, f64)) -> Option<Vec3> {
+        let (direction, _) = sample_spherical_triangle(self.vertices(), origin, u)?;
+        Some(direction)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self
+            .intersect(&Ray::new(origin, direction), 0.0, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+        let area = spherical_triangle_area(self.vertices(), origin);
+        if area > 0.0 {
+            1.0 / area
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lambertian::Lambertian;
+    use crate::Color;
+
+    fn quad() -> Arc<TriangleMesh> {
+        Arc::new(TriangleMesh {
+            positions: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            normals: vec![],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            tangents: vec![],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+        })
+    }
+
+    #[test]
+    fn test_hit() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::ones()));
+        let triangles = TriangleMesh::triangles(&quad(), &material);
+        let down = Ray::new(Point3::new(0.75, 0.25, 2.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = triangles[0].hit(&down, 0.0, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        assert!(rec.front);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(rec.dpdu, Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(rec.dpdv, Vec3::new(0.0, 1.0, 0.0));
+        assert!(triangles[1].hit(&down, 0.0, f64::INFINITY).is_none());
+        assert!(triangles[0].hit(&down, 0.0, 1.0).is_none());
+        // from below the normal still faces the ray
+        let up = Ray::new(Point3::new(0.75, 0.25, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = triangles[0].hit(&up, 0.0, f64::INFINITY).unwrap();
+        assert!(!rec.front);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+        let bounds = triangles[1].bounding_box();
+        assert_eq!(bounds.min, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(bounds.max, Point3::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_light_sampling() {
+        let triangle = Triangle {
+            mesh: quad(),
+            index: 1,
+            material: Arc::new(Lambertian::new(Color::ones())),
+        };
+        let origin = Point3::new(0.2, 0.3, 1.0);
+        let direction = triangle.sample_direction(origin, (0.3, 0.6)).unwrap();
+        assert!(triangle
+            .intersect(&Ray::new(origin, direction), 0.0, 2.0)
+            .is_some());
+        let area = spherical_triangle_area(triangle.vertices(), origin);
+        assert!((triangle.pdf_value(origin, direction) * area - 1.0).abs() < 1e-12);
+        // a direction passing beside it
+        let other = Point3::new(0.9, 0.1, 0.0) - origin;
+        assert_eq!(triangle.pdf_value(origin, other), 0.0);
+    }
+}
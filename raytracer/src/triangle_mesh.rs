@@ -0,0 +1,187 @@
+use crate::onb::Onb;
+use crate::triangle::Triangle;
+use crate::{Hittable, Material, Point3, Vec3};
+use std::sync::Arc;
+
+// Triangles sharing their vertices. Normals and texture coordinates are per vertex
+// and may be missing, then the faces are flat and textured by barycentrics.
+#[derive(Clone, Debug)]
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    // per vertex with the sign of the bitangent, `sign * normal × tangent`, empty
+    // unless there are both normals and texture coordinates
+    pub tangents: Vec<(Vec3, f64)>,
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl TriangleMesh {
+    // moved by `offset` after scaling by `scale` about the origin
+    pub fn transform(&mut self, scale: f64, offset: Vec3) {
+        for p in &mut self.positions {
+            *p = *p * scale + offset;
+        }
+        if scale < 0.0 {
+            for n in &mut self.normals {
+                *n = -*n;
+            }
+            // u and v grow the other way, and so the bitangent turns around
+            for (t, sign) in &mut self.tangents {
+                *t = -*t;
+                *sign = -*sign;
+            }
+        }
+    }
+
+    // Per vertex, the sum of the faces around it weighted by their area
+    pub fn smooth_normals(&self) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::zero(); self.positions.len()];
+        for &[i0, i1, i2] in &self.indices {
+            let (p0, p1, p2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+            // twice the area long
+            let n = Vec3::cross(p1 - p0, p2 - p0);
+            for &i in &[i0, i1, i2] {
+                normals[i] += n;
+            }
+        }
+        normals
+            .into_iter()
+            .map(|n| {
+                if n.squared_length() > 0.0 {
+                    n.unit()
+                } else {
+                    n
+                }
+            })
+            .collect()
+    }
+
+    // The tangents MikkTSpace (Mikkelsen 2008) gives a mesh whose vertices each have
+    // one normal and one texture coordinate: the direction u grows in over each face,
+    // projected into the tangent plane of the vertex and averaged over its corners
+    // weighted by their angles, with the sign that makes `normal × tangent` follow v.
+    pub fn generate_tangents(&mut self) {
+        self.tangents.clear();
+        if self.normals.is_empty() || self.uvs.is_empty() {
+            return;
+        }
+        let count = self.positions.len();
+        let (mut tangents, mut bitangents) = (vec![Vec3::zero(); count], vec![Vec3::zero(); count]);
+        let direction = |v: Vec3| {
+            if v.squared_length() > 0.0 {
+                v.unit()
+            } else {
+                Vec3::zero()
+            }
+        };
+        for corners in &self.indices {
+            let [i0, i1, i2] = *corners;
+            let (p0, p1, p2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+            let (uv0, uv1, uv2) = (self.uvs[i0], self.uvs[i1], self.uvs[i2]);
+            let (e1, e2) = (p1 - p0, p2 - p0);
+            let (du1, dv1, du2, dv2) = (uv1.0 - uv0.0, uv1.1 - uv0.1, uv2.0 - uv0.0, uv2.1 - uv0.1);
+            let det = du1 * dv2 - dv1 * du2;
+            if det == 0.0 {
+                continue;
+            }
+            let dpdu = (e1 * dv2 - e2 * dv1) / det;
+            let dpdv = (e2 * du1 - e1 * du2) / det;
+            for k in 0..3 {
+                let i = corners[k];
+                let a = direction(self.positions[corners[(k + 1) % 3]] - self.positions[i]);
+                let b = direction(self.positions[corners[(k + 2) % 3]] - self.positions[i]);
+                let angle = (a * b).clamp(-1.0, 1.0).acos();
+                let normal = direction(self.normals[i]);
+                let project = |v: Vec3| direction(v - normal * (v * normal));
+                tangents[i] += project(dpdu) * angle;
+                bitangents[i] += project(dpdv) * angle;
+            }
+        }
+        self.tangents = (0..count)
+            .map(|i| {
+                let normal = direction(self.normals[i]);
+                if normal == Vec3::zero() {
+                    return (Vec3::zero(), 1.0);
+                }
+                let t = tangents[i] - normal * (tangents[i] * normal);
+                // a vertex whose faces have no texture to follow
+                let t = if t.squared_length() > 1e-24 {
+                    t.unit()
+                } else {
+                    Onb::from_w(normal).u
+                };
+                let sign = if Vec3::cross(normal, t) * bitangents[i] < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                (t, sign)
+            })
+            .collect();
+    }
+
+    pub fn triangles(
+        mesh: &Arc<TriangleMesh>,
+        material: &Arc<dyn Material>,
+    ) -> Vec<Box<dyn Hittable>> {
+        (0..mesh.indices.len())
+            .map(|index| {
+                Box::new(Triangle {
+                    mesh: mesh.clone(),
+                    index,
+                    material: material.clone(),
+                }) as Box<dyn Hittable>
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a unit square in the xy plane facing +z, textured by `uv` of its corners
+    fn square(uv: impl Fn(Point3) -> (f64, f64)) -> TriangleMesh {
+        let positions = vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ];
+        TriangleMesh {
+            uvs: positions.iter().map(|&p| uv(p)).collect(),
+            normals: vec![Vec3::new(0.0, 0.0, 1.0); 4],
+            positions,
+            tangents: vec![],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+        }
+    }
+
+    #[test]
+    fn test_generate_tangents() {
+        let mut mesh = square(|p| (p.x, p.y));
+        mesh.generate_tangents();
+        for &(t, sign) in &mesh.tangents {
+            assert!((t - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-12);
+            assert_eq!(sign, 1.0);
+        }
+        // mirrored horizontally the bitangent still follows v up the square
+        let mut mirrored = square(|p| (1.0 - p.x, p.y));
+        mirrored.generate_tangents();
+        for &(t, sign) in &mirrored.tangents {
+            assert!((t - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-12);
+            assert_eq!(
+                Vec3::cross(Vec3::new(0.0, 0.0, 1.0), t) * sign,
+                Vec3::new(0.0, 1.0, 0.0)
+            );
+        }
+        // rotated texture coordinates turn the tangents with them
+        let mut rotated = square(|p| (p.y, -p.x));
+        rotated.generate_tangents();
+        assert!((rotated.tangents[2].0 - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        mesh.normals.clear();
+        mesh.generate_tangents();
+        assert!(mesh.tangents.is_empty());
+    }
+}
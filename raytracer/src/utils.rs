@@ -1,13 +1,12 @@
-use crate::{Color, Point3, Vec3};
-use rand::{rngs::SmallRng, Rng};
+use crate::{Color, Vec3};
 
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     if x < min {
-        return min;
+        min
     } else if x > max {
-        return max;
+        max
     } else {
-        return x;
+        x
     }
 }
 
@@ -19,54 +18,14 @@ pub fn clamp3(color: Color) -> Color {
     }
 }
 
-pub fn random_unit_vector(rng: &mut SmallRng) -> Point3 {
-    random_in_unit_sphere(rng).unit() // TODO
-}
-
-pub fn random_in_hemisphere(normal: Vec3, rng: &mut SmallRng) -> Point3 {
-    let in_unit_sphere = random_in_unit_sphere(rng);
-    if in_unit_sphere * normal > 0.0 {
-        return in_unit_sphere;
-    } else {
-        return -in_unit_sphere;
-    }
-}
-
-pub fn random_in_unit_sphere(rng: &mut SmallRng) -> Point3 {
-    loop {
-        let tmp = Point3::new(
-            rng.gen_range(-1.0..1.0),
-            rng.gen_range(-1.0..1.0),
-            rng.gen_range(-1.0..1.0),
-        );
-        if tmp.length() < 1.0 {
-            return tmp;
-        }
-    }
-}
-
-pub fn random_in_unitdisk(rng: &mut SmallRng) -> Vec3 {
-    loop {
-        let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-        if p.squared_length() <1_f64 {
-            return p;
-        }
-    }
+// SplitMix64 finalizer, every input bit affects every output bit
+pub fn hash_u64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
 }
 
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - n * (v * n) * 2.0
 }
-
-pub fn refract(uv: Vec3, normal: Vec3, etai_over_etat: f64) -> Vec3 {
-    let cos_theta = (-uv * normal).min(1.0_f64);
-    let r_out_perp = (uv + normal * cos_theta) * etai_over_etat;
-    let r_out_parallel = -normal * (1.0 - r_out_perp.squared_length()).abs().sqrt();
-    r_out_perp + r_out_parallel
-}
-
-pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
-    let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
-    let r0 = r0 * r0;
-    r0 + (1.0 - r0) * ((1.0 - cosine).powi(5))
-}
@@ -1,6 +1,7 @@
+use serde::Deserialize;
 use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
 
-#[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Deserialize)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -327,7 +328,7 @@ mod tests {
     fn test_length() {
         assert_eq!(
             Vec3::new(3.0, 4.0, 5.0).length(),
-            ((3.0 * 3.0 + 4.0 * 4.0 + 5.0 * 5.0) as f64).sqrt()
+            (3.0_f64 * 3.0 + 4.0 * 4.0 + 5.0 * 5.0).sqrt()
         );
     }
 
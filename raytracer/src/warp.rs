@@ -0,0 +1,454 @@
+// Warps from uniform samples in [0, 1)^n to other distributions, each with the
+// density of the result. Directions are sampled around +z, `Onb` turns them into
+// world space. Solid angle densities are per steradian, the others per unit area.
+// Only the warps the renderer samples with are part of normal builds. The uniform
+// sphere, ball and hemisphere warps and the densities nothing in the renderer asks
+// for are `#[cfg(test)]`: the tests use them as reference samplers and densities to
+// check the materials and the other warps against.
+
+use crate::{Point3, Vec3};
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+// Shirley and Chiu's concentric mapping, which keeps strata compact
+// unlike the polar one and maps the square onto the disk continuously
+pub fn sample_concentric_disk(u: (f64, f64)) -> Vec3 {
+    let (x, y) = (u.0 * 2.0 - 1.0, u.1 * 2.0 - 1.0);
+    if x == 0.0 && y == 0.0 {
+        return Vec3::zero();
+    }
+    let (r, theta) = if x.abs() > y.abs() {
+        (x, FRAC_PI_4 * (y / x))
+    } else {
+        (y, FRAC_PI_2 - FRAC_PI_4 * (x / y))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+#[cfg(test)]
+pub fn concentric_disk_pdf() -> f64 {
+    1.0 / PI
+}
+
+#[cfg(test)]
+pub fn sample_uniform_sphere(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = safe_sqrt(1.0 - z * z);
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg(test)]
+pub fn uniform_sphere_pdf() -> f64 {
+    1.0 / (4.0 * PI)
+}
+
+// a point inside the unit ball, the radius from a third sample
+#[cfg(test)]
+pub fn sample_uniform_ball(u: (f64, f64), u_radius: f64) -> Vec3 {
+    sample_uniform_sphere(u) * u_radius.cbrt()
+}
+
+#[cfg(test)]
+pub fn uniform_ball_pdf() -> f64 {
+    3.0 / (4.0 * PI)
+}
+
+#[cfg(test)]
+pub fn sample_uniform_hemisphere(u: (f64, f64)) -> Vec3 {
+    let z = u.0;
+    let r = safe_sqrt(1.0 - z * z);
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+#[cfg(test)]
+pub fn uniform_hemisphere_pdf() -> f64 {
+    1.0 / (2.0 * PI)
+}
+
+// Malley's method: points of the disk projected up onto the hemisphere
+pub fn sample_cosine_hemisphere(u: (f64, f64)) -> Vec3 {
+    let d = sample_concentric_disk(u);
+    let z = safe_sqrt(1.0 - d.x * d.x - d.y * d.y);
+    Vec3::new(d.x, d.y, z)
+}
+
+pub fn cosine_hemisphere_pdf(cos_theta: f64) -> f64 {
+    cos_theta.max(0.0) / PI
+}
+
+// directions within `cos_theta_max` of +z, as seen of a sphere from outside
+pub fn sample_uniform_cone(u: (f64, f64), cos_theta_max: f64) -> Vec3 {
+    let cos_theta = (1.0 - u.0) + u.0 * cos_theta_max;
+    let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+pub fn uniform_cone_pdf(cos_theta_max: f64) -> f64 {
+    1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+}
+
+// The Henyey-Greenstein phase function for light going along +z, scattering forwards
+// for `g` towards 1, backwards towards -1 and uniformly at 0
+pub fn sample_henyey_greenstein(u: (f64, f64), g: f64) -> Vec3 {
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u.0
+    } else {
+        let s = (1.0 - g * g) / (1.0 + g - 2.0 * g * u.0);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+#[cfg(test)]
+pub fn henyey_greenstein_pdf(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denom * safe_sqrt(denom))
+}
+
+// Barycentric coordinates of a uniformly distributed point of a triangle,
+// without folding the square over the diagonal (Heitz 2019)
+pub fn sample_uniform_triangle(u: (f64, f64)) -> (f64, f64, f64) {
+    let (b0, b1) = if u.0 < u.1 {
+        let b0 = u.0 / 2.0;
+        (b0, u.1 - b0)
+    } else {
+        let b1 = u.1 / 2.0;
+        (u.0 - b1, b1)
+    };
+    (b0, b1, 1.0 - b0 - b1)
+}
+
+#[cfg(test)]
+pub fn uniform_triangle_pdf(p0: Point3, p1: Point3, p2: Point3) -> f64 {
+    2.0 / Vec3::cross(p1 - p0, p2 - p0).length()
+}
+
+// angle between two unit vectors, accurate also when they are almost parallel
+fn angle_between(a: Vec3, b: Vec3) -> f64 {
+    if a * b < 0.0 {
+        PI - 2.0 * ((a + b).length() / 2.0).min(1.0).asin()
+    } else {
+        2.0 * ((b - a).length() / 2.0).min(1.0).asin()
+    }
+}
+
+// the part of `v` orthogonal to the unit vector `w`
+fn gram_schmidt(v: Vec3, w: Vec3) -> Vec3 {
+    v - w * (v * w)
+}
+
+// Solid angle of the triangle as seen from `p`, zero if it is degenerate
+pub fn spherical_triangle_area(vertices: [Point3; 3], p: Point3) -> f64 {
+    let (a, b, c) = (
+        (vertices[0] - p).unit(),
+        (vertices[1] - p).unit(),
+        (vertices[2] - p).unit(),
+    );
+    // Van Oosterom and Strackee
+    let numerator = (Vec3::cross(a, b) * c).abs();
+    let denominator = 1.0 + a * b + a * c + b * c;
+    2.0 * numerator.atan2(denominator).abs()
+}
+
+// Arvo's method: a direction from `p` uniformly distributed over the solid angle
+// of the triangle, and its density. `None` if the triangle is too small to sample.
+pub fn sample_spherical_triangle(
+    vertices: [Point3; 3],
+    p: Point3,
+    u: (f64, f64),
+) -> Option<(Vec3, f64)> {
+    let (a, b, c) = (
+        (vertices[0] - p).unit(),
+        (vertices[1] - p).unit(),
+        (vertices[2] - p).unit(),
+    );
+    // normals of the great circles through the edges
+    let (n_ab, n_bc, n_ca) = (Vec3::cross(a, b), Vec3::cross(b, c), Vec3::cross(c, a));
+    if n_ab.squared_length() == 0.0 || n_bc.squared_length() == 0.0 || n_ca.squared_length() == 0.0
+    {
+        return None;
+    }
+    let (n_ab, n_bc, n_ca) = (n_ab.unit(), n_bc.unit(), n_ca.unit());
+    // interior angles at the vertices, their excess over pi is the solid angle
+    let alpha = angle_between(n_ab, -n_ca);
+    let beta = angle_between(n_bc, -n_ab);
+    let gamma = angle_between(n_ca, -n_bc);
+    let area = alpha + beta + gamma - PI;
+    if area <= 0.0 {
+        return None;
+    }
+
+    // the sub-triangle a, b, c' with the sampled fraction of the area
+    let area_sub = PI + u.0 * area;
+    let (sin_alpha, cos_alpha) = alpha.sin_cos();
+    let sin_phi = area_sub.sin() * cos_alpha - area_sub.cos() * sin_alpha;
+    let cos_phi = area_sub.cos() * cos_alpha + area_sub.sin() * sin_alpha;
+    let k1 = cos_phi + cos_alpha;
+    let k2 = sin_phi - sin_alpha * (a * b);
+    let cos_b = ((k2 + (k2 * cos_phi - k1 * sin_phi) * cos_alpha)
+        / ((k2 * sin_phi + k1 * cos_phi) * sin_alpha))
+        .clamp(-1.0, 1.0);
+    let sin_b = safe_sqrt(1.0 - cos_b * cos_b);
+    let c_sub = a * cos_b + gram_schmidt(c, a).unit() * sin_b;
+
+    // and a point on the arc from b to c'
+    let cos_theta = 1.0 - u.1 * (1.0 - c_sub * b);
+    let sin_theta = safe_sqrt(1.0 - cos_theta * cos_theta);
+    let direction = b * cos_theta + gram_schmidt(c_sub, b).unit() * sin_theta;
+    Some((direction, 1.0 / area))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    const SAMPLES: usize = 100_000;
+
+    // Pearson's chi-square test of `counts` against the `expected` ones. Cells with
+    // small expectations are pooled, and the statistic is compared with the 99.9%
+    // quantile of the distribution from the Wilson-Hilferty approximation.
+    fn chi_square(counts: &[f64], expected: &[f64]) -> Result<(), String> {
+        let mut cells: Vec<(f64, f64)> = counts
+            .iter()
+            .cloned()
+            .zip(expected.iter().cloned())
+            .collect();
+        cells.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        let (mut statistic, mut dof) = (0.0, 0);
+        let (mut pooled_count, mut pooled_expected) = (0.0, 0.0);
+        for &(count, expected) in &cells {
+            // the cells with the smallest expectations are pooled until the pool has enough,
+            // including the ones the integration missed at the edge of the support
+            if pooled_expected < 5.0 {
+                pooled_count += count;
+                pooled_expected += expected;
+            } else {
+                statistic += (count - expected) * (count - expected) / expected;
+                dof += 1;
+            }
+        }
+        if pooled_count > 0.0 && pooled_expected == 0.0 {
+            return Err(format!(
+                "{} samples where the density is zero",
+                pooled_count
+            ));
+        }
+        if pooled_expected > 0.0 {
+            statistic += (pooled_count - pooled_expected) * (pooled_count - pooled_expected)
+                / pooled_expected;
+            dof += 1;
+        }
+        let k = (dof - 1) as f64;
+        let z = 3.09;
+        let quantile = k * (1.0 - 2.0 / (9.0 * k) + z * (2.0 / (9.0 * k)).sqrt()).powi(3);
+        if statistic > quantile {
+            return Err(format!(
+                "chi-square {} over {} cells exceeds {}",
+                statistic, dof, quantile
+            ));
+        }
+        Ok(())
+    }
+
+    fn uniform_samples(seed: u64) -> impl Iterator<Item = (f64, f64)> {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        (0..SAMPLES).map(move |_| (rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)))
+    }
+
+    // Compare the directions of `warp` with `pdf`, binned by cos(theta) and phi,
+    // which divides the sphere into cells of equal solid angle.
+    fn test_directions<W, P>(warp: W, pdf: P) -> Result<(), String>
+    where
+        W: Fn((f64, f64)) -> Vec3,
+        P: Fn(Vec3) -> f64,
+    {
+        let (z_bins, phi_bins) = (20, 40);
+        let mut counts = vec![0.0; z_bins * phi_bins];
+        for u in uniform_samples(1) {
+            let d = warp(u);
+            assert!((d.length() - 1.0).abs() < 1e-9, "not a unit vector");
+            let z = (((d.z + 1.0) / 2.0 * z_bins as f64) as usize).min(z_bins - 1);
+            let phi = d.y.atan2(d.x).rem_euclid(2.0 * PI) / (2.0 * PI);
+            let phi = ((phi * phi_bins as f64) as usize).min(phi_bins - 1);
+            counts[z * phi_bins + phi] += 1.0;
+        }
+        // integrate the density over every cell with the midpoint rule
+        let n = 8;
+        let cell = (2.0 / z_bins as f64) * (2.0 * PI / phi_bins as f64);
+        let mut expected = vec![0.0; z_bins * phi_bins];
+        for (i, e) in expected.iter_mut().enumerate() {
+            let (zi, pi) = (i / phi_bins, i % phi_bins);
+            for s in 0..n * n {
+                let z = -1.0
+                    + (zi as f64 + (s / n) as f64 / n as f64 + 0.5 / n as f64) * 2.0
+                        / z_bins as f64;
+                let phi = (pi as f64 + (s % n) as f64 / n as f64 + 0.5 / n as f64) * 2.0 * PI
+                    / phi_bins as f64;
+                let r = safe_sqrt(1.0 - z * z);
+                *e += pdf(Vec3::new(r * phi.cos(), r * phi.sin(), z)) * cell / (n * n) as f64;
+            }
+        }
+        let total: f64 = expected.iter().sum();
+        assert!(
+            (total - 1.0).abs() < 1e-2,
+            "density integrates to {}",
+            total
+        );
+        let expected: Vec<f64> = expected.iter().map(|e| e * SAMPLES as f64).collect();
+        chi_square(&counts, &expected)
+    }
+
+    #[test]
+    fn test_sphere_and_hemispheres() {
+        test_directions(sample_uniform_sphere, |_| uniform_sphere_pdf()).unwrap();
+        let hemisphere = |d: Vec3| {
+            if d.z > 0.0 {
+                uniform_hemisphere_pdf()
+            } else {
+                0.0
+            }
+        };
+        test_directions(sample_uniform_hemisphere, hemisphere).unwrap();
+        test_directions(sample_cosine_hemisphere, |d| cosine_hemisphere_pdf(d.z)).unwrap();
+        // a wrong density has to be caught
+        assert!(test_directions(sample_cosine_hemisphere, hemisphere).is_err());
+    }
+
+    #[test]
+    fn test_cone() {
+        for &cos_theta_max in &[0.3, 0.9] {
+            let pdf = |d: Vec3| {
+                if d.z >= cos_theta_max {
+                    uniform_cone_pdf(cos_theta_max)
+                } else {
+                    0.0
+                }
+            };
+            test_directions(|u| sample_uniform_cone(u, cos_theta_max), pdf).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_henyey_greenstein() {
+        for &g in &[0.0, 0.7, -0.4] {
+            let pdf = |d: Vec3| henyey_greenstein_pdf(d.z, g);
+            test_directions(|u| sample_henyey_greenstein(u, g), pdf).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_spherical_triangle() {
+        let p = Point3::new(0.1, -0.2, 0.0);
+        let vertices = [
+            Point3::new(-1.0, -1.0, 1.0),
+            Point3::new(2.0, 0.0, 1.5),
+            Point3::new(0.0, 1.5, 0.5),
+        ];
+        let area = spherical_triangle_area(vertices, p);
+        let (a, b, c) = (
+            (vertices[0] - p).unit(),
+            (vertices[1] - p).unit(),
+            (vertices[2] - p).unit(),
+        );
+        // inside when on the inner side of all three great circles
+        let inside = |d: Vec3| {
+            let orientation = Vec3::cross(a, b) * c;
+            Vec3::cross(a, b) * d * orientation >= 0.0
+                && Vec3::cross(b, c) * d * orientation >= 0.0
+                && Vec3::cross(c, a) * d * orientation >= 0.0
+        };
+        let warp = |u| {
+            let (d, pdf) = sample_spherical_triangle(vertices, p, u).unwrap();
+            assert!((pdf - 1.0 / area).abs() < 1e-9 / area);
+            d
+        };
+        test_directions(warp, |d| if inside(d) { 1.0 / area } else { 0.0 }).unwrap();
+
+        // an octant covers an eighth of the sphere
+        let octant = [
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Point3::new(0.0, 0.0, 1.0),
+        ];
+        assert!((spherical_triangle_area(octant, Point3::zero()) - PI / 2.0).abs() < 1e-12);
+        let (_, pdf) = sample_spherical_triangle(octant, Point3::zero(), (0.3, 0.6)).unwrap();
+        assert!((pdf - 2.0 / PI).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_disk() {
+        // cells of equal area from r² and phi
+        let (r_bins, phi_bins) = (10, 20);
+        let mut counts = vec![0.0; r_bins * phi_bins];
+        for u in uniform_samples(2) {
+            let d = sample_concentric_disk(u);
+            let r2 = d.squared_length();
+            assert!(r2 <= 1.0 + 1e-12 && d.z == 0.0);
+            let r = ((r2 * r_bins as f64) as usize).min(r_bins - 1);
+            let phi = d.y.atan2(d.x).rem_euclid(2.0 * PI) / (2.0 * PI);
+            let phi = ((phi * phi_bins as f64) as usize).min(phi_bins - 1);
+            counts[r * phi_bins + phi] += 1.0;
+        }
+        let cell_area = PI / (r_bins * phi_bins) as f64;
+        let expected = vec![concentric_disk_pdf() * cell_area * SAMPLES as f64; counts.len()];
+        chi_square(&counts, &expected).unwrap();
+    }
+
+    #[test]
+    fn test_ball() {
+        // uniform in the ball means r³ and the direction are uniform
+        let mut rng = SmallRng::seed_from_u64(3);
+        let bins = 20;
+        let mut counts = vec![0.0; bins];
+        for u in uniform_samples(3) {
+            let p = sample_uniform_ball(u, rng.gen_range(0.0..1.0));
+            let r3 = p.length().powi(3);
+            counts[((r3 * bins as f64) as usize).min(bins - 1)] += 1.0;
+        }
+        chi_square(&counts, &vec![SAMPLES as f64 / bins as f64; bins]).unwrap();
+        assert!((uniform_ball_pdf() * 4.0 / 3.0 * PI - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_triangle() {
+        // the triangle cut into n² congruent triangles, which all have the same area
+        let n = 10;
+        let mut counts = vec![0.0; 2 * n * n];
+        for u in uniform_samples(4) {
+            let (b0, b1, b2) = sample_uniform_triangle(u);
+            assert!(b0 >= 0.0 && b1 >= 0.0 && b2 >= -1e-12);
+            let (x, y) = (b0 * n as f64, b1 * n as f64);
+            let (i, j) = ((x as usize).min(n - 1), (y as usize).min(n - 1));
+            let upper = (x - i as f64) + (y - j as f64) >= 1.0;
+            counts[2 * (j * n + i) + upper as usize] += 1.0;
+        }
+        // half the cells of the square are outside the triangle
+        let expected: Vec<f64> = (0..2 * n * n)
+            .map(|cell| {
+                let (i, j, upper) = ((cell / 2) % n, (cell / 2) / n, cell % 2 == 1);
+                let outside = i + j > n - 1 || (i + j == n - 1 && upper);
+                if outside {
+                    0.0
+                } else {
+                    SAMPLES as f64 / (n * n) as f64
+                }
+            })
+            .collect();
+        chi_square(&counts, &expected).unwrap();
+        let (p0, p1, p2) = (
+            Point3::zero(),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(0.0, 3.0, 0.0),
+        );
+        assert!((uniform_triangle_pdf(p0, p1, p2) - 1.0 / 3.0).abs() < 1e-12);
+    }
+}